@@ -0,0 +1,505 @@
+//! # Attribute macro front end for `easy_ffi`
+//!
+//! This crate provides the `#[easy_ffi(handler = ...)]` attribute, which is
+//! re-exported from `easy_ffi` as `easy_ffi::attr::easy_ffi` when the `attr`
+//! feature is enabled, and `#[derive(FfiErrorCode)]`, re-exported as
+//! `easy_ffi::FfiErrorCode` with the `derive` feature. See the `easy_ffi` docs
+//! for usage.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Wrap a plain `fn foo(...) -> Result<T, E>` with a wrapper macro generated by
+/// `easy_ffi!`.
+///
+/// The `handler` argument names the wrapper macro to use. The annotated
+/// function is passed to it unchanged, so
+///
+/// ```text
+/// #[easy_ffi(handler = my_ffi_fn)]
+/// fn foo(i: i32) -> Result<i32, &'static str> { ... }
+/// ```
+///
+/// expands to exactly what `my_ffi_fn!(fn foo(i: i32) -> ...)` would. Anything
+/// after `handler = ...` is passed on as the function's `#[easy_ffi(...)]`
+/// overrides, e.g. `#[easy_ffi(handler = my_ffi_fn, abi = "system")]`.
+#[proc_macro_attribute]
+pub fn easy_ffi(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match parse_args(attr) {
+        Ok((handler, overrides)) => {
+            let mut body: Vec<TokenTree> = Vec::new();
+            if !overrides.is_empty() {
+                body.push(Punct::new('#', Spacing::Alone).into());
+                let attr: Vec<TokenTree> = vec![
+                    Ident::new("easy_ffi", Span::call_site()).into(),
+                    Group::new(Delimiter::Parenthesis, overrides.into_iter().collect()).into(),
+                ];
+                body.push(Group::new(Delimiter::Bracket, attr.into_iter().collect()).into());
+            }
+            body.extend(item);
+
+            let mut out: Vec<TokenTree> = handler;
+            out.push(Punct::new('!', Spacing::Alone).into());
+            out.push(Group::new(Delimiter::Brace, body.into_iter().collect()).into());
+            out.into_iter().collect()
+        }
+        Err((span, msg)) => compile_error(span, &msg),
+    }
+}
+
+/// Derive `easy_ffi::FfiErrorCode` for an enum.
+///
+/// Variants get the codes `-1`, `-2`, ... in order. `#[ffi_code(...)]` pins
+/// a variant's code to any constant expression, and the variants after it
+/// count down from there:
+///
+/// ```text
+/// #[derive(FfiErrorCode)]
+/// enum MyError {
+///     Io(io::Error),           // -1
+///     #[ffi_code(-404)]
+///     NotFound,                // -404
+///     Unknown { msg: String }, // -405
+/// }
+/// ```
+///
+/// Codes only depend on the order of the variants and the pinned values, so
+/// pin them if the enum might be reordered. Two variants with the same code
+/// are a compile error.
+///
+/// The first paragraph of each variant's doc comment is used as the
+/// description of its code, as returned by `FfiErrorCode::code_description`.
+/// Without one, a `thiserror`-style `#[error("...")]` message is used, as long
+/// as it doesn't format any fields, and otherwise the variant's name. Since
+/// it's returned as a C string, a NUL in it is a compile error.
+///
+/// `#[ffi_severity(warning)]` or `#[ffi_severity(fatal)]` on a variant sets
+/// the `FfiErrorCode::severity` of its errors, which is `error` otherwise.
+#[proc_macro_derive(FfiErrorCode, attributes(ffi_code, ffi_severity))]
+pub fn derive_ffi_error_code(item: TokenStream) -> TokenStream {
+    match parse_enum(item) {
+        Ok((name, variants)) => {
+            let mut arms = String::new();
+            let mut descriptions = String::new();
+            let mut severities = String::new();
+            let mut checks = String::new();
+            let mut code = "0".to_string();
+            let mut value = Some(0);
+            let mut seen: Vec<(Ident, String, Option<i128>)> = Vec::new();
+            for variant in variants {
+                match variant.code {
+                    Some(pinned) => {
+                        value = parse_int(&pinned);
+                        code = format!("({})", pinned);
+                    }
+                    None => {
+                        value = value.map(|value| value - 1);
+                        code = format!("({} - 1)", code);
+                    }
+                }
+                for (other, other_code, other_value) in &seen {
+                    match (value, *other_value) {
+                        (Some(value), Some(other_value)) if value == other_value => {
+                            return compile_error(
+                                variant.name.span(),
+                                &format!("`{}` has the same code as `{}`", variant.name, other),
+                            );
+                        }
+                        (Some(_), Some(_)) => {}
+                        // Codes that can't be worked out here are compared
+                        // when the constants are evaluated instead.
+                        _ => checks.push_str(&format!(
+                            "const _: () = if {} as ::std::os::raw::c_int \
+                             == {} as ::std::os::raw::c_int {{ \
+                             panic!(\"`{}` has the same code as `{}`\") }};\n",
+                            code, other_code, variant.name, other
+                        )),
+                    }
+                }
+                seen.push((variant.name.clone(), code.clone(), value));
+                arms.push_str(&format!(
+                    "{}::{} {{ .. }} => {} as ::std::os::raw::c_int,\n",
+                    name, variant.name, code
+                ));
+                severities.push_str(&format!(
+                    "{}::{} {{ .. }} => ::easy_ffi::Severity::{},\n",
+                    name,
+                    variant.name,
+                    variant.severity.unwrap_or("Error")
+                ));
+                let lines = if !variant.doc.is_empty() {
+                    variant.doc
+                } else {
+                    variant.message.into_iter().collect()
+                };
+                // `code_description` gives a C string, which would be cut
+                // short.
+                if let Some((_, span)) = lines.iter().find(|(line, _)| has_nul(line)) {
+                    return compile_error(*span, "error code descriptions can't contain `\\0`");
+                }
+                let lines: Vec<_> = lines.into_iter().map(|(line, _)| line).collect();
+                let mut description = lines.join(", \" \", ");
+                if description.is_empty() {
+                    description = format!("\"{}\"", variant.name);
+                }
+                descriptions.push_str(&format!(
+                    "if code == {} as ::std::os::raw::c_int {{ concat!({}, \"\\0\") }} else ",
+                    code, description
+                ));
+            }
+            format!(
+                "impl ::easy_ffi::FfiErrorCode for {} {{
+                    fn error_code(&self) -> ::std::os::raw::c_int {{
+                        match *self {{ {} }}
+                    }}
+
+                    fn code_description(
+                        code: ::std::os::raw::c_int,
+                    ) -> ::std::option::Option<&'static ::std::ffi::CStr> {{
+                        let description: &'static str = {} {{ return None }};
+                        ::std::ffi::CStr::from_bytes_with_nul(description.as_bytes()).ok()
+                    }}
+
+                    fn severity(&self) -> ::easy_ffi::Severity {{
+                        match *self {{ {} }}
+                    }}
+                }}
+                {}",
+                name, arms, descriptions, severities, checks
+            )
+            .parse()
+            .expect("generated impl should parse")
+        }
+        Err((span, msg)) => compile_error(span, &msg),
+    }
+}
+
+type Error = (Span, String);
+
+struct Variant {
+    name: Ident,
+    /// From `#[ffi_code(...)]`.
+    code: Option<TokenStream>,
+    /// The lines of the first paragraph of the doc comment, as string
+    /// literals, with their spans.
+    doc: Vec<(String, Span)>,
+    /// From `#[error("...")]`, as a string literal, if it's a plain message.
+    message: Option<(String, Span)>,
+    /// From `#[ffi_severity(...)]`, as the name of a `Severity` variant.
+    severity: Option<&'static str>,
+}
+
+/// Parse an enum into its name and its variants.
+fn parse_enum(item: TokenStream) -> Result<(Ident, Vec<Variant>), Error> {
+    let mut tokens = item.into_iter();
+
+    // Skip attributes and visibility.
+    let name = loop {
+        match tokens.next() {
+            Some(TokenTree::Ident(ref i)) if i.to_string() == "enum" => match tokens.next() {
+                Some(TokenTree::Ident(name)) => break name,
+                _ => return Err((i.span(), "expected the enum's name".into())),
+            },
+            Some(TokenTree::Ident(ref i))
+                if i.to_string() == "struct" || i.to_string() == "union" =>
+            {
+                return Err((
+                    i.span(),
+                    "`FfiErrorCode` can only be derived for enums".into(),
+                ));
+            }
+            Some(_) => {}
+            None => return Err((Span::call_site(), "expected an enum".into())),
+        }
+    };
+
+    let body = match tokens.next() {
+        Some(TokenTree::Group(ref g)) if g.delimiter() == Delimiter::Brace => g.stream(),
+        Some(other) => {
+            return Err((
+                other.span(),
+                "`FfiErrorCode` can't be derived for generic enums".into(),
+            ));
+        }
+        None => return Err((name.span(), "expected the enum's variants".into())),
+    };
+
+    let mut variants = Vec::new();
+    let mut tokens = body.into_iter().peekable();
+    while tokens.peek().is_some() {
+        let mut code = None;
+        let mut message = None;
+        let mut severity = None;
+        let mut doc = Vec::new();
+        let mut doc_done = false;
+        let name = loop {
+            match tokens.next() {
+                Some(TokenTree::Punct(ref p)) if p.as_char() == '#' => match tokens.next() {
+                    Some(TokenTree::Group(attr)) => {
+                        if let Some(pinned) = parse_ffi_code(attr.stream())? {
+                            code = Some(pinned);
+                        } else if let Some(level) = parse_ffi_severity(attr.stream())? {
+                            severity = Some(level);
+                        } else if let Some(plain) = parse_error_message(attr.stream()) {
+                            message = Some(plain);
+                        } else if let Some(line) = parse_doc(attr.stream()) {
+                            if line.0 == "\"\"" {
+                                doc_done |= !doc.is_empty();
+                            } else if !doc_done {
+                                doc.push(line);
+                            }
+                        }
+                    }
+                    _ => return Err((p.span(), "expected an attribute".into())),
+                },
+                Some(TokenTree::Ident(name)) => break name,
+                Some(other) => return Err((other.span(), "expected a variant".into())),
+                None => return Err((name.span(), "expected a variant".into())),
+            }
+        };
+        // Skip the fields and discriminant, if any.
+        for tt in tokens.by_ref() {
+            if let TokenTree::Punct(ref p) = tt {
+                if p.as_char() == ',' {
+                    break;
+                }
+            }
+        }
+        variants.push(Variant {
+            name,
+            code,
+            doc,
+            message,
+            severity,
+        });
+    }
+
+    Ok((name, variants))
+}
+
+/// Get the line out of a `doc = "..."` attribute, as a string literal with
+/// the surrounding spaces trimmed, or `None` for any other attribute.
+fn parse_doc(attr: TokenStream) -> Option<(String, Span)> {
+    let mut tokens = attr.into_iter();
+    match (tokens.next(), tokens.next(), tokens.next()) {
+        (
+            Some(TokenTree::Ident(ref i)),
+            Some(TokenTree::Punct(ref p)),
+            Some(TokenTree::Literal(ref l)),
+        ) if i.to_string() == "doc" && p.as_char() == '=' => {
+            let line = l.to_string();
+            // Raw strings are left alone.
+            if line.len() >= 2 && line.starts_with('"') && line.ends_with('"') {
+                Some((
+                    format!("\"{}\"", line[1..line.len() - 1].trim_matches(' ')),
+                    l.span(),
+                ))
+            } else {
+                Some((line, l.span()))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Get the message out of an `error("...")` attribute, as a string literal,
+/// or `None` for any other attribute, or a message with `{...}` in it, which
+/// would need the variant's fields to format.
+fn parse_error_message(attr: TokenStream) -> Option<(String, Span)> {
+    let mut tokens = attr.into_iter();
+    match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Ident(ref i)), Some(TokenTree::Group(ref g)))
+            if i.to_string() == "error" && g.delimiter() == Delimiter::Parenthesis =>
+        {
+            let mut args = g.stream().into_iter();
+            match (args.next(), args.next()) {
+                (Some(TokenTree::Literal(ref l)), None) => {
+                    let message = l.to_string();
+                    let plain = message.starts_with('"')
+                        && !message.contains('{')
+                        && !message.contains('}');
+                    if plain {
+                        Some((message, l.span()))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Get the code out of an `ffi_code(...)` attribute, or `None` for any
+/// other attribute.
+fn parse_ffi_code(attr: TokenStream) -> Result<Option<TokenStream>, Error> {
+    let mut tokens = attr.into_iter();
+    match tokens.next() {
+        Some(TokenTree::Ident(ref i)) if i.to_string() == "ffi_code" => match tokens.next() {
+            Some(TokenTree::Group(ref g))
+                if g.delimiter() == Delimiter::Parenthesis && !g.stream().is_empty() =>
+            {
+                Ok(Some(g.stream()))
+            }
+            _ => Err((i.span(), "expected `#[ffi_code(...)]`".into())),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Whether a string literal has a NUL in it, written out or escaped.
+fn has_nul(lit: &str) -> bool {
+    if lit.contains('\0') {
+        return true;
+    }
+    // Raw strings don't have escapes.
+    if !lit.starts_with('"') {
+        return false;
+    }
+    let mut chars = lit.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        let nul = match chars.next() {
+            Some('0') => true,
+            Some('x') => chars.next() == Some('0') && chars.next() == Some('0'),
+            Some('u') => {
+                let escape: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let digits = escape.trim_start_matches('{').replace('_', "");
+                u32::from_str_radix(&digits, 16) == Ok(0)
+            }
+            _ => false,
+        };
+        if nul {
+            return true;
+        }
+    }
+    false
+}
+
+/// Work out the value of a code that's an integer literal, possibly negated,
+/// or `None` for anything else.
+fn parse_int(code: &TokenStream) -> Option<i128> {
+    let mut tokens = code.clone().into_iter();
+    let (negative, lit) = match (tokens.next(), tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(lit)), None, None) => (false, lit),
+        (Some(TokenTree::Punct(ref p)), Some(TokenTree::Literal(lit)), None)
+            if p.as_char() == '-' =>
+        {
+            (true, lit)
+        }
+        _ => return None,
+    };
+    let lit = lit.to_string().replace('_', "");
+    let (digits, radix) = match lit.get(..2) {
+        Some("0x") => (&lit[2..], 16),
+        Some("0o") => (&lit[2..], 8),
+        Some("0b") => (&lit[2..], 2),
+        _ => (&lit[..], 10),
+    };
+    // Leave off a suffix like `i32`.
+    let end = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    match &digits[end..] {
+        "" | "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize" => {}
+        _ => return None,
+    }
+    let value = i128::from_str_radix(&digits[..end], radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Get the `Severity` variant out of an `ffi_severity(...)` attribute, or
+/// `None` for any other attribute.
+fn parse_ffi_severity(attr: TokenStream) -> Result<Option<&'static str>, Error> {
+    let mut tokens = attr.into_iter();
+    match tokens.next() {
+        Some(TokenTree::Ident(ref i)) if i.to_string() == "ffi_severity" => {
+            let level = match tokens.next() {
+                Some(TokenTree::Group(ref g)) if g.delimiter() == Delimiter::Parenthesis => {
+                    let mut args = g.stream().into_iter();
+                    match (args.next(), args.next()) {
+                        (Some(TokenTree::Ident(ref level)), None) => match &*level.to_string() {
+                            "warning" => Some("Warning"),
+                            "error" => Some("Error"),
+                            "fatal" => Some("Fatal"),
+                            _ => None,
+                        },
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match level {
+                Some(level) => Ok(Some(level)),
+                None => Err((
+                    i.span(),
+                    "expected `#[ffi_severity(warning)]`, `#[ffi_severity(error)]` or \
+                     `#[ffi_severity(fatal)]`"
+                        .into(),
+                )),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Parse `handler = some::path` out of the start of the attribute arguments,
+/// returning the path tokens and whatever follows it.
+fn parse_args(attr: TokenStream) -> Result<(Vec<TokenTree>, Vec<TokenTree>), Error> {
+    let mut tokens = attr.into_iter().peekable();
+    let missing = || {
+        (
+            Span::call_site(),
+            "expected `handler = ...` naming an `easy_ffi!` wrapper macro".to_string(),
+        )
+    };
+
+    match tokens.next() {
+        Some(TokenTree::Ident(ref i)) if i.to_string() == "handler" => {}
+        Some(other) => return Err((other.span(), missing().1)),
+        None => return Err(missing()),
+    }
+    match tokens.next() {
+        Some(TokenTree::Punct(ref p)) if p.as_char() == '=' => {}
+        Some(other) => return Err((other.span(), "expected `=` after `handler`".into())),
+        None => return Err(missing()),
+    }
+
+    let mut handler = Vec::new();
+    while let Some(tt) = tokens.peek() {
+        if let TokenTree::Punct(ref p) = *tt {
+            if p.as_char() == ',' {
+                break;
+            }
+        }
+        handler.push(tokens.next().unwrap());
+    }
+    tokens.next(); // `,` separating the overrides, if any
+
+    if handler.is_empty() {
+        return Err(missing());
+    }
+
+    Ok((handler, tokens.collect()))
+}
+
+fn compile_error(span: Span, msg: &str) -> TokenStream {
+    let mut lit = Literal::string(msg);
+    lit.set_span(span);
+    let mut path: Vec<TokenTree> = vec![
+        Ident::new("compile_error", span).into(),
+        Punct::new('!', Spacing::Alone).into(),
+    ];
+    let mut args = Group::new(Delimiter::Parenthesis, TokenTree::from(lit).into());
+    args.set_span(span);
+    path.push(args.into());
+    path.push(Punct::new(';', Spacing::Alone).into());
+    path.into_iter()
+        .map(|mut tt| {
+            tt.set_span(span);
+            tt
+        })
+        .collect()
+}
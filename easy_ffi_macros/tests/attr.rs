@@ -0,0 +1,51 @@
+use easy_ffi::attr::easy_ffi;
+
+easy_ffi::easy_ffi!(my_ffi_fn =>
+    |err| {
+        println!("{}", err);
+        -1
+    }
+    |panic_val| {
+        match panic_val.downcast_ref::<&'static str>() {
+            Some(s) => println!("panic: {}", s),
+            None => println!("unknown panic!"),
+        };
+        -1
+    }
+);
+
+/// Foo: do stuff
+#[easy_ffi(handler = my_ffi_fn)]
+fn foo(i: i32) -> Result<i32, &'static str> {
+    match i {
+        5 => panic!("I'm afraid of 5's!"),
+        i if i <= 0 => Err("already <= 0, can't go lower"),
+        i => Ok(i - 1),
+    }
+}
+
+#[test]
+fn attr_works() {
+    assert_eq!(-1, foo(5));
+    assert_eq!(-1, foo(0));
+    assert_eq!(0, foo(1));
+    assert_eq!(1, foo(2));
+}
+
+#[easy_ffi(handler = my_ffi_fn, prefix = "easy_ffi_attr_test_", |_failure| { -2 })]
+fn bar(i: i32) -> Result<i32, &'static str> {
+    if i < 0 {
+        Err("negative")
+    } else {
+        Ok(i)
+    }
+}
+
+#[test]
+fn attr_overrides() {
+    extern "C" {
+        fn easy_ffi_attr_test_bar(i: i32) -> i32;
+    }
+    assert_eq!(1, bar(1));
+    assert_eq!(-2, unsafe { easy_ffi_attr_test_bar(-1) });
+}
@@ -0,0 +1,141 @@
+use std::ffi::CStr;
+
+use easy_ffi::FfiErrorCode;
+
+#[allow(dead_code)]
+#[derive(Debug, FfiErrorCode)]
+enum MyError {
+    /// An I/O error
+    /// happened.
+    ///
+    /// This isn't part of the description.
+    Io(std::io::Error),
+    #[ffi_code(-404)]
+    #[ffi_severity(warning)]
+    NotFound,
+    Unknown {
+        msg: String,
+    },
+    /// Doc comments and other attributes are fine.
+    #[ffi_code(i32::MIN)]
+    #[ffi_severity(fatal)]
+    Min,
+}
+
+easy_ffi::easy_ffi_strerror!(easy_ffi_derive_test, MyError);
+
+easy_ffi::easy_ffi!(my_ffi_fn =>
+    |err| { err.error_code() }
+    |_panic_val| { -500 }
+);
+
+my_ffi_fn!(
+    fn find(i: i32) -> Result<i32, MyError> {
+        match i {
+            5 => panic!("I'm afraid of 5's!"),
+            0 => Err(MyError::NotFound),
+            i if i < 0 => Err(MyError::Unknown {
+                msg: "negative".into(),
+            }),
+            i => Ok(i),
+        }
+    }
+);
+
+#[test]
+fn derive_codes() {
+    let io = std::io::Error::other("io");
+    assert_eq!(-1, MyError::Io(io).error_code());
+    assert_eq!(-404, MyError::NotFound.error_code());
+    assert_eq!(i32::MIN, MyError::Min.error_code());
+}
+
+#[test]
+fn derive_severities() {
+    use easy_ffi::Severity;
+
+    let io = std::io::Error::other("io");
+    assert_eq!(Severity::Error, MyError::Io(io).severity());
+    assert_eq!(Severity::Warning, MyError::NotFound.severity());
+    assert_eq!(Severity::Fatal, MyError::Min.severity());
+}
+
+#[test]
+fn derive_in_handlers() {
+    assert_eq!(1, find(1));
+    assert_eq!(-404, find(0));
+    assert_eq!(-405, find(-1));
+    assert_eq!(-500, find(5));
+}
+
+#[test]
+fn derive_descriptions() {
+    let description = |code| unsafe { CStr::from_ptr(strerror(code)) }.to_str().unwrap();
+    assert_eq!("An I/O error happened.", description(-1));
+    assert_eq!("NotFound", description(-404));
+    assert_eq!(
+        "Doc comments and other attributes are fine.",
+        description(i32::MIN)
+    );
+    assert_eq!("unknown error", description(1));
+}
+
+#[derive(Debug, thiserror::Error, FfiErrorCode)]
+enum ParseError {
+    #[error("empty input")]
+    Empty,
+    /// The doc comment wins.
+    #[error("bad digit")]
+    BadDigit,
+    #[error("{0} is too big")]
+    TooBig(u64),
+}
+
+easy_ffi::easy_ffi_last_error!(easy_ffi_derive_test);
+
+easy_ffi::easy_ffi!(code_ffi_fn => handlers = "error_code", last_error = set_last_error);
+
+code_ffi_fn!(
+    fn parse_small(s: u64) -> Result<u8, ParseError> {
+        match s {
+            0 => Err(ParseError::Empty),
+            1 => Err(ParseError::BadDigit),
+            2 => panic!("I'm afraid of 2's!"),
+            s if s > 255 => Err(ParseError::TooBig(s)),
+            s => Ok(s as u8),
+        }
+    }
+);
+
+#[test]
+fn derive_thiserror_descriptions() {
+    let description = |code| {
+        ParseError::code_description(code)
+            .unwrap()
+            .to_str()
+            .unwrap()
+    };
+    assert_eq!("empty input", description(-1));
+    assert_eq!("The doc comment wins.", description(-2));
+    assert_eq!("TooBig", description(-3));
+}
+
+#[test]
+fn error_code_handlers() {
+    let message = || {
+        unsafe { CStr::from_ptr(last_error_message()) }
+            .to_str()
+            .unwrap()
+    };
+
+    assert_eq!(3, parse_small(3));
+    assert_eq!(0, parse_small(0));
+    assert_eq!((-1, "empty input"), (last_error_code(), message()));
+    assert_eq!(0, parse_small(300));
+    assert_eq!((-3, "300 is too big"), (last_error_code(), message()));
+    assert_eq!(0, parse_small(2));
+    assert_eq!(
+        (easy_ffi::PANIC_CODE, "I'm afraid of 2's!"),
+        (last_error_code(), message())
+    );
+}
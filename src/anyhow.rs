@@ -0,0 +1,30 @@
+use crate::default::default_handler;
+use crate::error_callback::report_error;
+use crate::{FfiDefault, FfiFailure, LastError};
+
+/// The handler used for `handlers = "anyhow"` without a `last_error`
+/// function. `anyhow::Error`'s `Debug` output already has the whole chain of
+/// causes, so this is just the default handler.
+#[doc(hidden)]
+pub fn anyhow_handler<T: FfiDefault>(fn_name: &str, failure: FfiFailure<::anyhow::Error>) -> T {
+    default_handler(fn_name, failure)
+}
+
+/// The handler used for `handlers = "anyhow"` with a `last_error` function:
+/// records the failure with code `-1`, with the whole chain of contexts and
+/// causes in the message, and returns `FfiDefault::ffi_default()`.
+#[doc(hidden)]
+pub fn anyhow_last_error_handler<T: FfiDefault>(
+    fn_name: &str,
+    set_last_error: fn(LastError),
+    failure: FfiFailure<::anyhow::Error>,
+) -> T {
+    let error = match failure {
+        // The alternate form is each message in the chain, separated by
+        // `: `, where the normal one is only the outermost.
+        FfiFailure::Error(err) => LastError::new(-1, &format!("{:#}", err)),
+        FfiFailure::Panic(panic_val) => LastError::from_panic(-1, &*panic_val),
+    };
+    report_error(fn_name, set_last_error, error);
+    T::ffi_default()
+}
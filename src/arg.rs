@@ -0,0 +1,224 @@
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::ops::RangeBounds;
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+use std::str::Utf8Error;
+use std::sync::atomic;
+
+/// An argument from C that couldn't be converted to its Rust type.
+///
+/// These are passed to the error handler like any other error, so the
+/// function's error type must implement `From<ArgError>`. It's implemented
+/// here for `io::Error` and `String`, and `Box<dyn Error>` and `anyhow::Error`
+/// get it too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArgError {
+    /// The named argument was null.
+    Null(&'static str),
+    /// The named string argument wasn't valid UTF-8.
+    Utf8(&'static str, Utf8Error),
+    /// The named slice argument's length was too big for its element type.
+    TooLong(&'static str),
+    /// The named slice argument wasn't aligned for its element type.
+    Misaligned(&'static str),
+    /// The named `#[nonzero]` argument was zero.
+    Zero(&'static str),
+    /// The named `#[range(...)]` argument was outside the given range.
+    OutOfRange(&'static str, &'static str),
+}
+
+impl ArgError {
+    /// The name of the argument.
+    pub fn arg(&self) -> &'static str {
+        match *self {
+            ArgError::Null(arg)
+            | ArgError::Utf8(arg, _)
+            | ArgError::TooLong(arg)
+            | ArgError::Misaligned(arg)
+            | ArgError::Zero(arg)
+            | ArgError::OutOfRange(arg, _) => arg,
+        }
+    }
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArgError::Null(arg) => write!(f, "`{}` is null", arg),
+            ArgError::Utf8(arg, ref err) => write!(f, "`{}` isn't valid UTF-8: {}", arg, err),
+            ArgError::TooLong(arg) => write!(f, "`{}` is too long", arg),
+            ArgError::Misaligned(arg) => write!(f, "`{}` is misaligned", arg),
+            ArgError::Zero(arg) => write!(f, "`{}` is zero", arg),
+            ArgError::OutOfRange(arg, range) => write!(f, "`{}` isn't in {}", arg, range),
+        }
+    }
+}
+
+impl Error for ArgError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ArgError::Utf8(_, ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ArgError> for io::Error {
+    fn from(err: ArgError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+impl From<ArgError> for String {
+    fn from(err: ArgError) -> String {
+        err.to_string()
+    }
+}
+
+/// Used for `null_checks = true` and `#[nonnull]` arguments.
+#[doc(hidden)]
+pub fn check_non_null<T: ?Sized>(arg: &'static str, ptr: *const T) -> Result<(), ArgError> {
+    if ptr.is_null() {
+        Err(ArgError::Null(arg))
+    } else {
+        Ok(())
+    }
+}
+
+/// Used for `#[nonzero]` arguments.
+#[doc(hidden)]
+pub fn check_non_zero<T: Default + PartialEq>(
+    arg: &'static str,
+    value: &T,
+) -> Result<(), ArgError> {
+    if *value == T::default() {
+        Err(ArgError::Zero(arg))
+    } else {
+        Ok(())
+    }
+}
+
+/// Used for `#[range(...)]` arguments.
+#[doc(hidden)]
+pub fn check_range<T: PartialOrd, R: RangeBounds<T>>(
+    arg: &'static str,
+    range_str: &'static str,
+    value: &T,
+    range: R,
+) -> Result<(), ArgError> {
+    if range.contains(value) {
+        Ok(())
+    } else {
+        Err(ArgError::OutOfRange(arg, range_str))
+    }
+}
+
+/// Used for `&str` arguments.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string that lives for `'a`.
+#[doc(hidden)]
+pub unsafe fn str_arg<'a>(arg: &'static str, s: *const c_char) -> Result<&'a str, ArgError> {
+    if s.is_null() {
+        return Err(ArgError::Null(arg));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|err| ArgError::Utf8(arg, err))
+}
+
+/// Used for `&FfiCancelToken` arguments.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a `T` that lives for `'a`.
+#[doc(hidden)]
+pub unsafe fn ref_arg<'a, T>(arg: &'static str, ptr: *const T) -> Result<&'a T, ArgError> {
+    ptr.as_ref().ok_or(ArgError::Null(arg))
+}
+
+/// Used for `&[T]` arguments. A length of 0 gives an empty slice, even if
+/// `ptr` is null.
+///
+/// # Safety
+///
+/// Unless `len` is 0, `ptr` must be null or point to `len` initialized
+/// `T`s, which live for `'a` and aren't mutated meanwhile.
+#[doc(hidden)]
+pub unsafe fn slice_arg<'a, T>(
+    arg: &'static str,
+    ptr: *const T,
+    len: usize,
+) -> Result<&'a [T], ArgError> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    check_slice(arg, ptr, len)?;
+    Ok(slice::from_raw_parts(ptr, len))
+}
+
+/// Used for `&mut [T]` arguments. A length of 0 gives an empty slice, even
+/// if `ptr` is null.
+///
+/// # Safety
+///
+/// Unless `len` is 0, `ptr` must be null or point to `len` initialized
+/// `T`s, which live for `'a` and aren't accessed through anything else
+/// meanwhile.
+#[doc(hidden)]
+pub unsafe fn slice_mut_arg<'a, T>(
+    arg: &'static str,
+    ptr: *mut T,
+    len: usize,
+) -> Result<&'a mut [T], ArgError> {
+    if len == 0 {
+        return Ok(&mut []);
+    }
+    check_slice(arg, ptr, len)?;
+    Ok(slice::from_raw_parts_mut(ptr, len))
+}
+
+/// Used for `#[zeroize]` arguments: overwrites the `len` `T`s at `ptr`
+/// with zeroes, in a way that isn't optimized out. Does nothing if `ptr` is
+/// null, or `len` is too long to have been a valid slice.
+///
+/// # Safety
+///
+/// Unless `len` is 0, `ptr` must be null or valid for writing `len` `T`s,
+/// and nothing may read them as `T`s afterwards unless all zeroes is a
+/// valid `T`.
+#[doc(hidden)]
+pub unsafe fn zeroize_slice<T>(ptr: *mut T, len: usize) {
+    let size = match len.checked_mul(mem::size_of::<T>()) {
+        Some(size) if !ptr.is_null() && size <= isize::MAX as usize => size,
+        _ => return,
+    };
+    let bytes = ptr as *mut u8;
+    for i in 0..size {
+        ptr::write_volatile(bytes.add(i), 0);
+    }
+    atomic::compiler_fence(atomic::Ordering::SeqCst);
+}
+
+/// Checks what `slice::from_raw_parts{,_mut}` needs that can be checked.
+fn check_slice<T>(arg: &'static str, ptr: *const T, len: usize) -> Result<(), ArgError> {
+    if ptr.is_null() {
+        return Err(ArgError::Null(arg));
+    }
+    // Alignments are powers of two. `pointer::is_aligned` would do, but it
+    // needs Rust 1.79.
+    if (ptr as usize) & (mem::align_of::<T>() - 1) != 0 {
+        return Err(ArgError::Misaligned(arg));
+    }
+    match len.checked_mul(mem::size_of::<T>()) {
+        Some(size) if size <= isize::MAX as usize => Ok(()),
+        _ => Err(ArgError::TooLong(arg)),
+    }
+}
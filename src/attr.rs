@@ -0,0 +1,38 @@
+//! Attribute-macro front end for wrapper macros generated by `easy_ffi!`.
+//!
+//! Instead of passing the function to the wrapper macro directly, annotate it
+//! with `#[easy_ffi(handler = ...)]`:
+//!
+//! ```
+//! use easy_ffi::attr::easy_ffi;
+//!
+//! easy_ffi::easy_ffi!(my_ffi_fn =>
+//!     |err| {
+//!         println!("{}", err);
+//!         -1
+//!     }
+//!     |panic_val| {
+//!         -1
+//!     }
+//! );
+//!
+//! /// This works just like `my_ffi_fn!(fn foo(...) ...)`.
+//! #[easy_ffi(handler = my_ffi_fn)]
+//! fn foo(i: i32) -> Result<i32, &'static str> {
+//!     Ok(i + 1)
+//! }
+//! # fn main() {
+//! #     assert_eq!(foo(1), 2);
+//! # }
+//! ```
+//!
+//! Anything after `handler = ...` is passed on as the function's
+//! `#[easy_ffi(...)]` overrides, so `#[easy_ffi(handler = my_ffi_fn, abi =
+//! "system")]` is the same as `#[easy_ffi(abi = "system")]` inside
+//! `my_ffi_fn!`.
+//!
+//! Note that importing the attribute shadows the `easy_ffi!` macro from
+//! `#[macro_use]`, so the wrapper macro needs to be defined through its full
+//! path, `easy_ffi::easy_ffi!`.
+
+pub use easy_ffi_macros::easy_ffi;
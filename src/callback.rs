@@ -0,0 +1,43 @@
+use std::io::{self, Write};
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::failure::payload_str;
+use crate::redact::Redacted;
+
+/// Used by the shims from [`easy_ffi_closure!`](macro.easy_ffi_closure.html)
+/// and [`easy_ffi_vtable!`](macro.easy_ffi_vtable.html): calls `f` with the
+/// value behind `data`, printing a panic, or a null `data`, to stderr and
+/// returning `on_panic` instead.
+///
+/// # Safety
+///
+/// `data` must be null or point to a live `T` that nothing else is using.
+#[doc(hidden)]
+pub unsafe fn call_boxed<T, R>(
+    name: &str,
+    data: *mut c_void,
+    on_panic: R,
+    f: impl FnOnce(&mut T) -> R,
+) -> R {
+    let value = match (data as *mut T).as_mut() {
+        Some(value) => value,
+        None => {
+            let _ = writeln!(io::stderr(), "{}: called with null data", name);
+            return on_panic;
+        }
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| f(value))) {
+        Ok(ret) => ret,
+        Err(panic_val) => {
+            let mut stderr = io::stderr();
+            let _ = match payload_str(&*panic_val) {
+                Some(msg) => writeln!(stderr, "{}: panic: {}", name, Redacted(msg)),
+                None => writeln!(stderr, "{}: panic", name),
+            };
+            // Dropping the payload could panic again.
+            ::std::mem::forget(panic_val);
+            on_panic
+        }
+    }
+}
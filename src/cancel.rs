@@ -0,0 +1,115 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::FfiErrorCode;
+
+/// A token C can use to ask a wrapped function to stop early.
+///
+/// A wrapped function taking a `cancel: &FfiCancelToken` argument is passed
+/// a `*const FfiCancelToken` by C, made and freed by the functions from
+/// [`easy_ffi_cancel_token!`](macro.easy_ffi_cancel_token.html). Another C
+/// thread can cancel the token while the function runs, which it's up to
+/// the function to notice, e.g. by calling
+/// [`check`](#method.check) between steps.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::FfiCancelToken;
+///
+/// easy_ffi!(my_ffi_fn =>);
+///
+/// my_ffi_fn!(
+///     fn count_to(n: u32, cancel: &FfiCancelToken) -> Result<u32, String> {
+///         let mut count = 0;
+///         while count < n {
+///             cancel.check()?;
+///             count += 1;
+///         }
+///         Ok(count)
+///     }
+/// );
+/// # fn main() {
+/// let token = FfiCancelToken::new();
+/// assert_eq!(3, unsafe { count_to(3, &token) });
+/// token.cancel();
+/// assert_eq!(0, unsafe { count_to(3, &token) });
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct FfiCancelToken {
+    cancelled: AtomicBool,
+}
+
+impl FfiCancelToken {
+    /// A token that hasn't been cancelled.
+    pub const fn new() -> FfiCancelToken {
+        FfiCancelToken {
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// Asks whatever's using the token to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Whether it's been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// A [`Cancelled`](struct.Cancelled.html) error if it's been cancelled,
+    /// for returning with `?`.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A wrapped function stopped because its
+/// [`FfiCancelToken`](struct.FfiCancelToken.html) was cancelled.
+///
+/// It's returned from the body with `?` on
+/// [`FfiCancelToken::check`](struct.FfiCancelToken.html#method.check), so
+/// the function's error type must implement `From<Cancelled>`. That's
+/// implemented here for `io::Error` and `String`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Cancelled {
+    /// The code from its `FfiErrorCode` implementation, next to
+    /// [`TimedOut::CODE`](struct.TimedOut.html#associatedconstant.CODE).
+    pub const CODE: c_int = -1002;
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+impl FfiErrorCode for Cancelled {
+    fn error_code(&self) -> c_int {
+        Cancelled::CODE
+    }
+}
+
+impl From<Cancelled> for io::Error {
+    fn from(err: Cancelled) -> io::Error {
+        io::Error::new(io::ErrorKind::Interrupted, err)
+    }
+}
+
+impl From<Cancelled> for String {
+    fn from(err: Cancelled) -> String {
+        err.to_string()
+    }
+}
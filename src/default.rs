@@ -0,0 +1,211 @@
+use std::fmt::{Debug, Display};
+use std::io::{self, Write};
+
+use crate::error_callback::{call_error_callback_with, report_error};
+use crate::error_code::PANIC_CODE;
+use crate::failure::payload_str;
+use crate::panic_hook::take_panic_report;
+use crate::poison::poison;
+use crate::rate_limit::may_log;
+use crate::redact::Redacted;
+use crate::{FfiErrorCode, FfiFailure, LastError, Severity};
+
+/// A value to return across the ffi boundary when nothing better is known.
+///
+/// When `easy_ffi!` is given no handlers, this is what wrapped functions
+/// return on failure. Signed integers give `-1`, unsigned integers
+/// `0`, floats `NaN`, pointers and `Option`s null and `bool`s `false`.
+///
+/// Handlers can return it too, so that one wrapper macro works for functions
+/// with different return types:
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::FfiDefault;
+///
+/// easy_ffi!(my_ffi_fn =>
+///     |err| {
+///         println!("{}", err);
+///         FfiDefault::ffi_default()
+///     }
+///     |panic_val| {
+///         FfiDefault::ffi_default()
+///     }
+/// );
+///
+/// my_ffi_fn!(
+///     fn parse_int(i: i32) -> Result<i32, &'static str> {
+///         Err("not an int")
+///     }
+///
+///     fn parse_float(f: f64) -> Result<f64, &'static str> {
+///         Err("not a float")
+///     }
+/// );
+/// # fn main() {
+/// #     assert_eq!(parse_int(1), -1);
+/// #     assert!(parse_float(1.0).is_nan());
+/// # }
+/// ```
+///
+/// Implement it for your own return types, e.g. `#[repr(C)]` structs or
+/// handle types, to give them a failure value as well.
+///
+/// Each type only has the one failure value, shared by the default handling
+/// and every handler returning `ffi_default()`, so there's no way to give a
+/// type another sentinel for some functions through this trait. Functions
+/// that need one can return it from handlers of their own, set with
+/// `#[easy_ffi(...)]`.
+pub trait FfiDefault {
+    /// The failure value.
+    fn ffi_default() -> Self;
+}
+
+macro_rules! impl_ffi_default {
+    ($val:expr => $($ty:ty),*) => ($(
+        impl FfiDefault for $ty {
+            fn ffi_default() -> Self {
+                $val
+            }
+        }
+    )*);
+}
+
+impl_ffi_default!(-1 => i8, i16, i32, i64, i128, isize);
+impl_ffi_default!(0 => u8, u16, u32, u64, u128, usize);
+impl_ffi_default!(Self::NAN => f32, f64);
+impl_ffi_default!(false => bool);
+impl_ffi_default!(() => ());
+
+impl<T> FfiDefault for *const T {
+    fn ffi_default() -> Self {
+        ::std::ptr::null()
+    }
+}
+
+impl<T> FfiDefault for *mut T {
+    fn ffi_default() -> Self {
+        ::std::ptr::null_mut()
+    }
+}
+
+/// Covers nullable pointers like `Option<&T>` and `Option<extern fn()>`.
+impl<T> FfiDefault for Option<T> {
+    fn ffi_default() -> Self {
+        None
+    }
+}
+
+/// The handler used when `easy_ffi!` isn't given any: logs the failure to
+/// stderr, passes it to the error callback, if one is registered, and
+/// returns `FfiDefault::ffi_default()`.
+#[doc(hidden)]
+pub fn default_handler<T: FfiDefault, E: Debug>(fn_name: &str, failure: FfiFailure<E>) -> T {
+    // Not `eprintln!`, which panics if stderr is gone.
+    let mut stderr = io::stderr();
+    let logged = may_log(|suppressed| {
+        let _ = writeln!(stderr, "{}: suppressed {} messages", fn_name, suppressed);
+    });
+    let mut sink = io::sink();
+    let stderr: &mut dyn Write = if logged { &mut stderr } else { &mut sink };
+    let _ = match failure {
+        FfiFailure::Error(err) => {
+            call_error_callback_with(|| LastError::new(-1, &format!("{:?}", err)));
+            writeln!(
+                stderr,
+                "{}: {}",
+                fn_name,
+                Redacted(format_args!("{:?}", err))
+            )
+        }
+        FfiFailure::Panic(panic_val) => match take_panic_report() {
+            Some(report) => {
+                call_error_callback_with(|| LastError::from_error(-1, &report));
+                writeln!(
+                    stderr,
+                    "{}: panic: {}\n{}",
+                    fn_name,
+                    Redacted(&report),
+                    report.backtrace()
+                )
+            }
+            None => {
+                call_error_callback_with(|| LastError::from_panic(-1, &*panic_val));
+                match payload_str(&*panic_val) {
+                    Some(msg) => writeln!(stderr, "{}: panic: {}", fn_name, Redacted(msg)),
+                    None => writeln!(stderr, "{}: panic", fn_name),
+                }
+            }
+        },
+    };
+    T::ffi_default()
+}
+
+/// The handler used when `easy_ffi!` isn't given any, but is given a
+/// `last_error` function: records the failure with code `-1` and returns
+/// `FfiDefault::ffi_default()`.
+#[doc(hidden)]
+pub fn last_error_handler<T: FfiDefault, E: Display>(
+    fn_name: &str,
+    set_last_error: fn(LastError),
+    failure: FfiFailure<E>,
+) -> T {
+    report_error(
+        fn_name,
+        set_last_error,
+        LastError::from_failure(-1, &failure),
+    );
+    T::ffi_default()
+}
+
+/// What `handlers = "error_code"` returns for an error with `severity`:
+/// `warning` for warnings if there's one, otherwise
+/// `FfiDefault::ffi_default()`, after poisoning the library for fatal errors.
+fn by_severity<T: FfiDefault>(severity: Severity, warning: Option<T>) -> T {
+    match (severity, warning) {
+        (Severity::Warning, Some(ok)) => return ok,
+        (Severity::Fatal, _) => poison(),
+        _ => {}
+    }
+    T::ffi_default()
+}
+
+/// The handler used for `handlers = "error_code"` without a `last_error`
+/// function: the default handler, except that it acts on the error's
+/// `Severity`. `warning` is what to return for warnings, if the function has
+/// a success value.
+#[doc(hidden)]
+pub fn error_code_default_handler<T: FfiDefault, E: FfiErrorCode + Debug>(
+    fn_name: &str,
+    failure: FfiFailure<E>,
+    warning: Option<T>,
+) -> T {
+    let severity = match failure {
+        FfiFailure::Error(ref err) => err.severity(),
+        FfiFailure::Panic(_) => Severity::Error,
+    };
+    default_handler::<(), E>(fn_name, failure);
+    by_severity(severity, warning)
+}
+
+/// The handler used for `handlers = "error_code"` with a `last_error`
+/// function: records errors with their `FfiErrorCode` and `Severity`, and
+/// panics with `PANIC_CODE`, then acts on the severity like
+/// `error_code_default_handler`.
+#[doc(hidden)]
+pub fn error_code_handler<T: FfiDefault, E: FfiErrorCode + Display>(
+    fn_name: &str,
+    set_last_error: fn(LastError),
+    failure: FfiFailure<E>,
+    warning: Option<T>,
+) -> T {
+    let error = match failure {
+        FfiFailure::Error(err) => {
+            LastError::from_error(err.error_code(), &err).with_severity(err.severity())
+        }
+        FfiFailure::Panic(panic_val) => LastError::from_panic(PANIC_CODE, &*panic_val),
+    };
+    let severity = error.severity();
+    report_error(fn_name, set_last_error, error);
+    by_severity(severity, warning)
+}
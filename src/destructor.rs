@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fmt;
+use std::io::{self, Write};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::Mutex;
+
+use crate::failure::payload_str;
+use crate::redact::Redacted;
+use crate::HandleError;
+
+/// Addresses freed by guarded destructors, in debug builds.
+static FREED: Mutex<Option<HashSet<usize>>> = Mutex::new(None);
+
+fn with_freed<R>(f: impl FnOnce(&mut HashSet<usize>) -> R) -> R {
+    // A panic while the lock was held can't have left the set half-updated.
+    let mut freed = FREED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(freed.get_or_insert_with(HashSet::new))
+}
+
+/// Called with every pointer handed out by a generated function, since the
+/// allocator may reuse the address of something freed earlier.
+#[doc(hidden)]
+pub fn mark_allocated<T>(fn_name: &'static str, ptr: *mut T) {
+    if ptr.is_null() {
+        return;
+    }
+    if cfg!(debug_assertions) {
+        with_freed(|freed| freed.remove(&(ptr as usize)));
+    }
+    #[cfg(feature = "live_objects")]
+    crate::live::record(fn_name, ptr);
+    #[cfg(not(feature = "live_objects"))]
+    let _ = fn_name;
+}
+
+/// Records `ptr` as freed, returning whether it already was. Always false in
+/// release builds.
+fn already_freed<T>(ptr: *mut T) -> bool {
+    cfg!(debug_assertions) && !with_freed(|freed| freed.insert(ptr as usize))
+}
+
+/// Like `already_freed`, without recording `ptr`.
+fn was_freed<T>(ptr: *mut T) -> bool {
+    cfg!(debug_assertions) && with_freed(|freed| freed.contains(&(ptr as usize)))
+}
+
+/// Tells stderr what a destructor did instead of freeing something, since
+/// it has nowhere else to say.
+fn report(fn_name: &str, msg: fmt::Arguments) {
+    let _ = writeln!(io::stderr(), "{}: {}", fn_name, msg);
+}
+
+/// Frees a `Box` that was handed to C as `ptr`, unless it's null.
+///
+/// In debug builds, freeing the same pointer twice gives
+/// `HandleError::DoubleFree` rather than corrupting the heap, so that a
+/// destructor written as a wrapped function passes it to the error handler.
+/// This only works for pointers handed out by functions generated by
+/// `easy_ffi!`, e.g. ones returning `Result<Box<T>, E>`, since freed
+/// addresses get reused.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::HandleError;
+///
+/// pub struct Widget;
+///
+/// easy_ffi!(my_ffi_fn =>
+///     |err| {
+///         println!("{}", err);
+///         -1
+///     }
+///     |panic_val| { -2 }
+/// );
+///
+/// my_ffi_fn!(
+///     #[easy_ffi(returns = "status")]
+///     unsafe fn widget_free(widget: *mut Widget) -> Result<(), HandleError> {
+///         easy_ffi::free_boxed(widget)
+///     }
+/// );
+/// # fn main() {}
+/// ```
+///
+/// # Safety
+///
+/// `ptr` must be null or come from `Box::<T>::into_raw`. In release builds,
+/// it mustn't have been freed already.
+pub unsafe fn free_boxed<T>(ptr: *mut T) -> Result<(), HandleError> {
+    if ptr.is_null() {
+        return Ok(());
+    }
+    if already_freed(ptr) {
+        return Err(HandleError::DoubleFree);
+    }
+    #[cfg(feature = "live_objects")]
+    crate::live::forget(ptr);
+    drop(Box::from_raw(ptr));
+    Ok(())
+}
+
+/// Used by [`easy_ffi_destructor!`](macro.easy_ffi_destructor.html): drops
+/// the box that `ptr` came from, unless it's null.
+///
+/// With `guard`, debug builds abort if `ptr` has already been freed this way.
+/// A panic while dropping is printed to stderr and otherwise ignored.
+///
+/// # Safety
+///
+/// `ptr` must be null or come from `Box::<T>::into_raw`, and mustn't be used
+/// again afterwards.
+#[doc(hidden)]
+pub unsafe fn drop_boxed<T>(fn_name: &str, ptr: *mut T, guard: bool) {
+    if ptr.is_null() {
+        return;
+    }
+    if guard && already_freed(ptr) {
+        report(
+            fn_name,
+            format_args!("{:p} was already freed, aborting", ptr),
+        );
+        process::abort();
+    }
+    #[cfg(feature = "live_objects")]
+    crate::live::forget(ptr);
+    let dropped = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(ptr))));
+    if let Err(panic_val) = dropped {
+        match payload_str(&*panic_val) {
+            Some(msg) => report(
+                fn_name,
+                format_args!("panic while dropping: {}", Redacted(msg)),
+            ),
+            None => report(fn_name, format_args!("panic while dropping")),
+        }
+        // Dropping the payload could panic again.
+        ::std::mem::forget(panic_val);
+    }
+}
+
+/// Used by [`easy_ffi_handle!`](macro.easy_ffi_handle.html)'s destructor:
+/// drops the handle that `ptr` came from, unless it's null, or has already
+/// been freed, or `is_valid` says it isn't a handle, which are reported to
+/// stderr instead.
+///
+/// Whether it's been freed is checked first, so that a freed handle isn't
+/// read, though only debug builds keep track.
+///
+/// # Safety
+///
+/// `ptr` must be null or come from `Box::<T>::into_raw`, and mustn't be used
+/// again afterwards. In release builds, it mustn't have been freed already.
+#[doc(hidden)]
+pub unsafe fn drop_handle<T>(fn_name: &str, ptr: *mut T, is_valid: impl FnOnce(&T) -> bool) {
+    if ptr.is_null() {
+        return;
+    }
+    if was_freed(ptr) {
+        report(
+            fn_name,
+            format_args!("{:p} was already freed, not freeing it again", ptr),
+        );
+        return;
+    }
+    if !is_valid(&*ptr) {
+        report(
+            fn_name,
+            format_args!("invalid handle {:p}, not freeing it", ptr),
+        );
+        return;
+    }
+    drop_boxed(fn_name, ptr, true);
+}
+
+/// Used by [`easy_ffi_string_free!`](macro.easy_ffi_string_free.html): frees
+/// a string from `CString::into_raw`, unless it's null. Debug builds abort if
+/// it's already been freed this way.
+///
+/// # Safety
+///
+/// `s` must be null or come from `CString::into_raw`, and mustn't be used
+/// again afterwards.
+#[doc(hidden)]
+pub unsafe fn free_string(fn_name: &str, s: *mut c_char) {
+    if s.is_null() {
+        return;
+    }
+    if already_freed(s) {
+        report(fn_name, format_args!("{:p} was already freed, aborting", s));
+        process::abort();
+    }
+    #[cfg(feature = "live_objects")]
+    crate::live::forget(s);
+    drop(CString::from_raw(s));
+}
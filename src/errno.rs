@@ -0,0 +1,94 @@
+//! Reporting errors through `errno`, for POSIX-style callers.
+//!
+//! Implement [`AsErrno`](trait.AsErrno.html) for your error type, then set
+//! `errno` from the error handler before returning the failure value:
+//!
+//! ```
+//! # #[macro_use] extern crate easy_ffi;
+//! use std::os::raw::c_int;
+//!
+//! use easy_ffi::errno::{self, AsErrno};
+//!
+//! #[derive(Debug)]
+//! enum MyError {
+//!     NotFound,
+//! }
+//!
+//! impl AsErrno for MyError {
+//!     fn as_errno(&self) -> c_int {
+//!         match *self {
+//!             MyError::NotFound => 2, // ENOENT
+//!         }
+//!     }
+//! }
+//!
+//! easy_ffi!(my_ffi_fn =>
+//!     |err| {
+//!         errno::set_errno_from(&err);
+//!         -1
+//!     }
+//!     |panic_val| {
+//!         errno::set_errno(errno::EIO);
+//!         -1
+//!     }
+//! );
+//!
+//! my_ffi_fn!(
+//!     fn open_thing(id: i32) -> Result<i32, MyError> {
+//!         Err(MyError::NotFound)
+//!     }
+//! );
+//! # fn main() {
+//! #     assert_eq!(open_thing(1), -1);
+//! #     assert_eq!(errno::errno(), 2);
+//! # }
+//! ```
+//!
+//! This needs the `errno` feature.
+
+use std::io;
+use std::os::raw::c_int;
+
+/// `EIO`, for errors without a better `errno` value. This is the same on
+/// every platform with `errno`.
+pub const EIO: c_int = 5;
+
+/// An error that can be reported as an `errno` value.
+pub trait AsErrno {
+    /// The `errno` value for this error.
+    fn as_errno(&self) -> c_int;
+}
+
+/// Uses the OS error code when there is one, and `EIO` otherwise.
+impl AsErrno for io::Error {
+    fn as_errno(&self) -> c_int {
+        self.raw_os_error().unwrap_or(EIO)
+    }
+}
+
+impl<E: AsErrno + ?Sized> AsErrno for &E {
+    fn as_errno(&self) -> c_int {
+        (**self).as_errno()
+    }
+}
+
+impl<E: AsErrno + ?Sized> AsErrno for Box<E> {
+    fn as_errno(&self) -> c_int {
+        (**self).as_errno()
+    }
+}
+
+/// Set this thread's `errno`.
+pub fn set_errno(code: c_int) {
+    ::errno::set_errno(::errno::Errno(code));
+}
+
+/// Set this thread's `errno` from an error.
+pub fn set_errno_from<E: AsErrno + ?Sized>(err: &E) {
+    set_errno(err.as_errno());
+}
+
+/// This thread's current `errno`.
+pub fn errno() -> c_int {
+    ::errno::errno().0
+}
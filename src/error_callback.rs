@@ -0,0 +1,112 @@
+use std::cell::Cell;
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::Mutex;
+
+use crate::LastError;
+
+/// A C function to call with each failure the built-in handlers see: its
+/// code, its NUL-terminated message, which is only valid during the call,
+/// and the `user_data` it was registered with.
+pub type ErrorCallback = extern "C" fn(code: c_int, message: *const c_char, user_data: *mut c_void);
+
+#[derive(Clone, Copy)]
+struct Registered {
+    callback: ErrorCallback,
+    user_data: *mut c_void,
+}
+
+// It's up to C that `user_data` can be used from whichever thread fails.
+unsafe impl Send for Registered {}
+
+/// The callback from [`set_error_callback`](fn.set_error_callback.html).
+static CALLBACK: Mutex<Option<Registered>> = Mutex::new(None);
+
+thread_local! {
+    /// The callback from
+    /// [`set_thread_error_callback`](fn.set_thread_error_callback.html).
+    static THREAD_CALLBACK: Cell<Option<Registered>> = const { Cell::new(None) };
+}
+
+/// Registers `callback` to be called with `user_data` by the built-in
+/// handlers, on whichever thread fails, replacing any callback registered
+/// before. `None` unregisters it. Threads with their own callback from
+/// [`set_thread_error_callback`](fn.set_thread_error_callback.html) call
+/// that instead.
+///
+/// Handlers given to `easy_ffi!` as closures don't call it, but can do so
+/// with [`call_error_callback`](fn.call_error_callback.html).
+/// [`easy_ffi_error_callback!`](macro.easy_ffi_error_callback.html) exports
+/// this for C.
+pub fn set_error_callback(callback: Option<ErrorCallback>, user_data: *mut c_void) {
+    // Nothing can panic while the lock is held.
+    let mut registered = CALLBACK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *registered = callback.map(|callback| Registered {
+        callback,
+        user_data,
+    });
+}
+
+/// Registers `callback` to be called with `user_data` by the built-in
+/// handlers on this thread only, in place of the one from
+/// [`set_error_callback`](fn.set_error_callback.html), so that hosts can
+/// send errors from different threads to different places. `None` goes back
+/// to that one.
+///
+/// [`easy_ffi_error_callback!`](macro.easy_ffi_error_callback.html) exports
+/// this for C as well.
+pub fn set_thread_error_callback(callback: Option<ErrorCallback>, user_data: *mut c_void) {
+    let registered = callback.map(|callback| Registered {
+        callback,
+        user_data,
+    });
+    // A thread that's shutting down has no errors left to report.
+    let _ = THREAD_CALLBACK.try_with(|thread_callback| thread_callback.set(registered));
+}
+
+/// The registered callback for this thread, copied out so that it can
+/// register another one.
+fn registered() -> Option<Registered> {
+    THREAD_CALLBACK
+        .try_with(Cell::get)
+        .ok()
+        .flatten()
+        .or_else(|| {
+            *CALLBACK
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        })
+}
+
+/// Calls the registered error callback, if there is one, with `error`'s
+/// code and message.
+pub fn call_error_callback(error: &LastError) {
+    if let Some(Registered {
+        callback,
+        user_data,
+    }) = registered()
+    {
+        callback(error.code(), error.message().as_ptr(), user_data);
+    }
+}
+
+/// Like `call_error_callback`, but only makes the error if there's a
+/// callback to call.
+pub(crate) fn call_error_callback_with(error: impl FnOnce() -> LastError) {
+    if let Some(Registered {
+        callback,
+        user_data,
+    }) = registered()
+    {
+        let error = error();
+        callback(error.code(), error.message().as_ptr(), user_data);
+    }
+}
+
+/// Passes `error` to the registered callback, if there is one, then records
+/// it with `set_last_error`, as having happened in `fn_name`.
+pub(crate) fn report_error(fn_name: &str, set_last_error: fn(LastError), error: LastError) {
+    call_error_callback(&error);
+    set_last_error(error.with_function(fn_name));
+}
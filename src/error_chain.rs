@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::default::default_handler;
+use crate::error_callback::report_error;
+use crate::{FfiDefault, FfiFailure, LastError};
+
+/// Displays an error followed by each of its `source`s, as `A: caused by B:
+/// caused by C`, rather than only the outermost one.
+///
+/// This is what `handlers = "error_chain"` and
+/// [`LastError::from_error_chain`](struct.LastError.html#method.from_error_chain)
+/// use, and handlers of your own can use it the same way.
+///
+/// ```
+/// use std::error::Error;
+/// use std::{fmt, io};
+/// use easy_ffi::ErrorChain;
+///
+/// #[derive(Debug)]
+/// struct ConfigError(io::Error);
+///
+/// impl fmt::Display for ConfigError {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         f.write_str("reading config")
+///     }
+/// }
+///
+/// impl Error for ConfigError {
+///     fn source(&self) -> Option<&(dyn Error + 'static)> {
+///         Some(&self.0)
+///     }
+/// }
+///
+/// let err = ConfigError(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+/// assert_eq!("reading config: caused by no such file", ErrorChain(&err).to_string());
+/// ```
+#[derive(Clone, Copy)]
+pub struct ErrorChain<'a>(pub &'a dyn Error);
+
+impl fmt::Display for ErrorChain<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+        let mut source = self.0.source();
+        while let Some(err) = source {
+            write!(f, ": caused by {}", err)?;
+            source = err.source();
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ErrorChain<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// An error that the default handler prints with its whole chain.
+struct Chained<E>(E);
+
+impl<E: Error> fmt::Debug for Chained<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ErrorChain(&self.0))
+    }
+}
+
+/// The handler used for `handlers = "error_chain"` without a `last_error`
+/// function: the default handler, printing errors with their whole chain of
+/// sources.
+#[doc(hidden)]
+pub fn error_chain_handler<T: FfiDefault, E: Error>(fn_name: &str, failure: FfiFailure<E>) -> T {
+    let failure = match failure {
+        FfiFailure::Error(err) => FfiFailure::Error(Chained(err)),
+        FfiFailure::Panic(panic_val) => FfiFailure::Panic(panic_val),
+    };
+    default_handler(fn_name, failure)
+}
+
+/// The handler used for `handlers = "error_chain"` with a `last_error`
+/// function: records the failure with code `-1`, with the whole chain of
+/// sources in the message, and returns `FfiDefault::ffi_default()`.
+#[doc(hidden)]
+pub fn error_chain_last_error_handler<T: FfiDefault, E: Error>(
+    fn_name: &str,
+    set_last_error: fn(LastError),
+    failure: FfiFailure<E>,
+) -> T {
+    let error = match failure {
+        FfiFailure::Error(err) => LastError::from_error_chain(-1, &err),
+        FfiFailure::Panic(panic_val) => LastError::from_panic(-1, &*panic_val),
+    };
+    report_error(fn_name, set_last_error, error);
+    T::ffi_default()
+}
@@ -0,0 +1,195 @@
+use std::ffi::CStr;
+use std::os::raw::c_int;
+
+/// An error with a stable integer code for C callers.
+///
+/// With the `derive` feature, this can be derived for enums with
+/// `#[derive(FfiErrorCode)]`. Variants are numbered `-1`, `-2`, ... in order,
+/// and `#[ffi_code(...)]` on a variant pins its code, with the variants
+/// after it counting down from there. `#[ffi_severity(...)]` sets a
+/// variant's [`severity`](#method.severity):
+///
+/// ```text
+/// #[derive(Debug, FfiErrorCode)]
+/// enum MyError {
+///     Io(io::Error),           // -1
+///     #[ffi_code(-404)]
+///     #[ffi_severity(warning)]
+///     NotFound,                // -404
+///     Unknown { msg: String }, // -405
+/// }
+/// ```
+///
+/// Handlers can then return `err.error_code()`:
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::FfiErrorCode;
+/// use std::os::raw::c_int;
+///
+/// #[derive(Debug)]
+/// struct NotFound;
+///
+/// impl FfiErrorCode for NotFound {
+///     fn error_code(&self) -> c_int {
+///         -404
+///     }
+/// }
+///
+/// easy_ffi!(my_ffi_fn =>
+///     |err| { err.error_code() }
+///     |panic_val| { -500 }
+/// );
+///
+/// my_ffi_fn!(
+///     fn find(id: i32) -> Result<i32, NotFound> {
+///         Err(NotFound)
+///     }
+/// );
+/// # fn main() {
+/// #     assert_eq!(find(1), -404);
+/// # }
+/// ```
+pub trait FfiErrorCode {
+    /// The code for this error.
+    fn error_code(&self) -> c_int;
+
+    /// A description of what `code` means, if it's one of this type's codes.
+    /// This is what [`easy_ffi_strerror!`](macro.easy_ffi_strerror.html)
+    /// returns to C callers.
+    ///
+    /// The derived implementation uses the first paragraph of each variant's
+    /// doc comment, or else its `thiserror` `#[error("...")]` message if that
+    /// doesn't format any fields, or else its name.
+    fn code_description(code: c_int) -> Option<&'static CStr>
+    where
+        Self: Sized,
+    {
+        let _ = code;
+        None
+    }
+
+    /// How bad this error is. `Severity::Error` unless overridden, or with
+    /// the derive, unless the variant has `#[ffi_severity(...)]`.
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+}
+
+impl<E: FfiErrorCode + ?Sized> FfiErrorCode for &E {
+    fn error_code(&self) -> c_int {
+        (**self).error_code()
+    }
+
+    fn severity(&self) -> Severity {
+        (**self).severity()
+    }
+}
+
+impl<E: FfiErrorCode + ?Sized> FfiErrorCode for Box<E> {
+    fn error_code(&self) -> c_int {
+        (**self).error_code()
+    }
+
+    fn severity(&self) -> Severity {
+        (**self).severity()
+    }
+}
+
+/// The code that `handlers = "error_code"` records panics with, out of the
+/// way of derived codes, which count down from `-1`.
+pub const PANIC_CODE: c_int = -1001;
+
+/// How bad an error is, from
+/// [`FfiErrorCode::severity`](trait.FfiErrorCode.html#method.severity).
+///
+/// `handlers = "error_code"` records it with the last error, where C callers
+/// can get it as a `c_int`, and acts on it:
+///
+/// * A warning is still logged or recorded, but a function with `returns =
+///   "status"` then returns its `success` value, since it did what it was
+///   asked. Other functions have nothing to return but the failure value.
+/// * A fatal error poisons the library, just as a panic does with `poison =
+///   true`, so functions wrapped with that refuse to run until
+///   [`reset_poison`](fn.reset_poison.html) is called.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::{FfiErrorCode, Severity};
+/// use std::fmt;
+/// use std::os::raw::c_int;
+///
+/// #[derive(Debug)]
+/// enum DbError {
+///     AlreadyOpen,
+///     Corrupt,
+/// }
+///
+/// impl fmt::Display for DbError {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         f.write_str(match *self {
+///             DbError::AlreadyOpen => "already open",
+///             DbError::Corrupt => "corrupt",
+///         })
+///     }
+/// }
+///
+/// impl FfiErrorCode for DbError {
+///     fn error_code(&self) -> c_int {
+///         match *self {
+///             DbError::AlreadyOpen => -1,
+///             DbError::Corrupt => -2,
+///         }
+///     }
+///
+///     fn severity(&self) -> Severity {
+///         match *self {
+///             DbError::AlreadyOpen => Severity::Warning,
+///             DbError::Corrupt => Severity::Fatal,
+///         }
+///     }
+/// }
+///
+/// impl From<easy_ffi::Poisoned> for DbError {
+///     fn from(_: easy_ffi::Poisoned) -> DbError {
+///         DbError::Corrupt
+///     }
+/// }
+///
+/// easy_ffi_last_error!(db);
+///
+/// easy_ffi!(db_fn =>
+///     handlers = "error_code",
+///     last_error = set_last_error,
+///     returns = "status",
+///     poison = true,
+/// );
+///
+/// db_fn!(
+///     fn db_open(open: bool) -> Result<(), DbError> {
+///         if open { Err(DbError::AlreadyOpen) } else { Ok(()) }
+///     }
+///
+///     fn db_check(corrupt: bool) -> Result<(), DbError> {
+///         if corrupt { Err(DbError::Corrupt) } else { Ok(()) }
+///     }
+/// );
+/// # fn main() {
+/// assert_eq!(0, db_open(true));
+/// assert_eq!(Severity::Warning as c_int, last_error_severity());
+/// assert_eq!(-1, db_check(true));
+/// assert!(easy_ffi::is_poisoned());
+/// assert_eq!(-1, db_open(false));
+/// # }
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Worth telling the caller about, but the function still did its job.
+    Warning = 1,
+    /// The function failed.
+    Error = 2,
+    /// The function failed, and the library can't be trusted to keep
+    /// working.
+    Fatal = 3,
+}
@@ -0,0 +1,53 @@
+use crate::default::default_handler;
+use crate::error_callback::report_error;
+use crate::{FfiDefault, FfiFailure, LastError};
+
+/// The handler used for `handlers = "eyre"` without a `last_error` function.
+/// `eyre::Report`'s `Debug` output is the installed `EyreHandler`'s full
+/// report, so this is just the default handler.
+#[doc(hidden)]
+pub fn eyre_handler<T: FfiDefault>(fn_name: &str, failure: FfiFailure<::eyre::Report>) -> T {
+    default_handler(fn_name, failure)
+}
+
+/// The handler used for `handlers = "eyre"` with a `last_error` function:
+/// records the failure with code `-1`, with the installed `EyreHandler`'s
+/// full report as plain text in the message, and returns
+/// `FfiDefault::ffi_default()`.
+#[doc(hidden)]
+pub fn eyre_last_error_handler<T: FfiDefault>(
+    fn_name: &str,
+    set_last_error: fn(LastError),
+    failure: FfiFailure<::eyre::Report>,
+) -> T {
+    let error = match failure {
+        FfiFailure::Error(report) => LastError::new(-1, &strip_ansi(&format!("{:?}", report))),
+        FfiFailure::Panic(panic_val) => LastError::from_panic(-1, &*panic_val),
+    };
+    report_error(fn_name, set_last_error, error);
+    T::ffi_default()
+}
+
+/// Removes the ANSI escape sequences that handlers like `color-eyre` color
+/// their reports with, which C callers would only have to strip themselves.
+fn strip_ansi(report: &str) -> String {
+    let mut plain = String::with_capacity(report.len());
+    let mut chars = report.chars();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            plain.push(c);
+            continue;
+        }
+        // A control sequence is `ESC [`, then parameters, up to a final
+        // character from `@` to `~`. Anything else is a two-character
+        // escape.
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    plain
+}
@@ -0,0 +1,199 @@
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::mem;
+use std::panic;
+use std::process;
+
+use crate::panic_hook::take_panic_report;
+use crate::redact::Redacted;
+
+/// Either of the ways a wrapped function can fail.
+///
+/// This is what the handler receives when `easy_ffi!` is given a single
+/// handler rather than separate error and panic handlers.
+#[derive(Debug)]
+pub enum FfiFailure<E> {
+    /// The function returned `Err`.
+    Error(E),
+    /// The function panicked. See `std::panic::catch_unwind`.
+    Panic(Box<dyn Any + Send + 'static>),
+}
+
+/// The panic message, if it's a string, as it is for `panic!` with a message.
+pub(crate) fn payload_str(panic_val: &(dyn Any + Send)) -> Option<&str> {
+    match panic_val.downcast_ref::<&'static str>() {
+        Some(s) => Some(s),
+        None => panic_val.downcast_ref::<String>().map(String::as_str),
+    }
+}
+
+/// The message of a caught panic, for panic handlers to log or pass on.
+///
+/// `panic!` with a message gives a payload that's either a `&'static str` or
+/// a `String`. Anything else, e.g. from `std::panic::panic_any`, gives
+/// `"unknown panic"`.
+///
+/// ```
+/// let panic_val = std::panic::catch_unwind(|| panic!("oh no")).unwrap_err();
+/// assert_eq!("oh no", easy_ffi::panic_message(&*panic_val));
+///
+/// let panic_val = std::panic::catch_unwind(|| panic!("oh no {}", 1)).unwrap_err();
+/// assert_eq!("oh no 1", easy_ffi::panic_message(&*panic_val));
+///
+/// let panic_val = std::panic::catch_unwind(|| std::panic::panic_any(1)).unwrap_err();
+/// assert_eq!("unknown panic", easy_ffi::panic_message(&*panic_val));
+/// ```
+pub fn panic_message(panic_val: &(dyn Any + Send)) -> Cow<'_, str> {
+    Cow::Borrowed(payload_str(panic_val).unwrap_or("unknown panic"))
+}
+
+/// Used for `on_panic = "abort"`.
+#[doc(hidden)]
+pub fn abort_on_panic(fn_name: &str, panic_val: Box<dyn Any + Send + 'static>) -> ! {
+    let mut stderr = io::stderr();
+    if let Some(report) = take_panic_report() {
+        let _ = writeln!(
+            stderr,
+            "{}: panic: {}, aborting",
+            fn_name,
+            Redacted(&report)
+        );
+        process::abort()
+    }
+    let _ = match payload_str(&*panic_val) {
+        Some(msg) => writeln!(stderr, "{}: panic: {}, aborting", fn_name, Redacted(msg)),
+        None => writeln!(stderr, "{}: panic, aborting", fn_name),
+    };
+    process::abort()
+}
+
+/// Used for `panic_error = expr`. Passing `expr` through here lets closures
+/// leave out the payload's type.
+#[doc(hidden)]
+pub fn panic_to_error<E>(
+    panic_val: Box<dyn Any + Send + 'static>,
+    panic_error: impl FnOnce(Box<dyn Any + Send + 'static>) -> E,
+) -> E {
+    panic_error(panic_val)
+}
+
+thread_local! {
+    /// A panic caught with `on_panic = "defer"` on this thread, to be resumed.
+    static DEFERRED: RefCell<Option<Box<dyn Any + Send + 'static>>> = const { RefCell::new(None) };
+}
+
+/// Used for `on_panic = "defer"`: keeps `panic_val` for
+/// [`resume_deferred_panic`](fn.resume_deferred_panic.html), returning a
+/// stand-in with the same message, if it has one, for the panic handler.
+///
+/// Only the first panic is kept until it's resumed; any more are forgotten.
+#[doc(hidden)]
+pub fn defer_panic(panic_val: Box<dyn Any + Send + 'static>) -> Box<dyn Any + Send + 'static> {
+    let stand_in: Box<dyn Any + Send + 'static> = match payload_str(&*panic_val) {
+        Some(msg) => Box::new(msg.to_string()),
+        None => Box::new(()),
+    };
+    let mut panic_val = Some(panic_val);
+    let _ = DEFERRED.try_with(|deferred| {
+        if let Ok(mut deferred) = deferred.try_borrow_mut() {
+            if deferred.is_none() {
+                *deferred = panic_val.take();
+            }
+        }
+    });
+    // Dropping the payload could panic again.
+    mem::forget(panic_val);
+    stand_in
+}
+
+/// Resumes a panic caught on this thread by a wrapped function with
+/// `on_panic = "defer"`, if there's one that hasn't been resumed yet.
+///
+/// `on_panic = "defer"` is for callbacks, such as ones made with
+/// [`easy_ffi_callback!`](macro.easy_ffi_callback.html). A panic can't unwind
+/// through the C function that called the callback, but once that returns,
+/// the Rust code that called it can carry on unwinding with this, as if the
+/// C function had never been in the way.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::os::raw::c_int;
+///
+/// // From some C library.
+/// unsafe extern "C" fn call_twice(f: unsafe extern "C" fn(c_int) -> c_int) -> c_int {
+///     f(1) + f(2)
+/// }
+///
+/// easy_ffi_callback!(my_callback =>
+///     on_panic = "defer",
+///     |err| { -1 }
+///     |panic_val| { -1 }
+/// );
+///
+/// my_callback!(
+///     unsafe fn check(n: c_int) -> Result<c_int, &'static str> {
+///         assert!(n < 2, "too big");
+///         Ok(n)
+///     }
+/// );
+///
+/// let panic_val = std::panic::catch_unwind(|| {
+///     let total = unsafe { call_twice(check) };
+///     easy_ffi::resume_deferred_panic();
+///     total
+/// })
+/// .unwrap_err();
+/// assert_eq!(Some(&"too big"), panic_val.downcast_ref::<&str>());
+/// ```
+///
+/// A deferred panic that's never resumed stays around until the next call to
+/// this on the same thread. [`call_foreign`](fn.call_foreign.html) calls
+/// this itself, so it can't be forgotten.
+pub fn resume_deferred_panic() {
+    let panic_val = DEFERRED
+        .try_with(|deferred| deferred.try_borrow_mut().ok()?.take())
+        .ok()
+        .flatten();
+    if let Some(panic_val) = panic_val {
+        panic::resume_unwind(panic_val);
+    }
+}
+
+/// Calls `f`, e.g. a C function that may call callbacks wrapped with
+/// `on_panic = "defer"`, then resumes any panic they deferred, with
+/// [`resume_deferred_panic`](fn.resume_deferred_panic.html).
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::os::raw::c_int;
+///
+/// // From some C library.
+/// unsafe extern "C" fn call_twice(f: unsafe extern "C" fn(c_int) -> c_int) -> c_int {
+///     f(1) + f(2)
+/// }
+///
+/// easy_ffi_callback!(my_callback =>
+///     on_panic = "defer",
+///     |err| { -1 }
+///     |panic_val| { -1 }
+/// );
+///
+/// my_callback!(
+///     unsafe fn check(n: c_int) -> Result<c_int, &'static str> {
+///         assert!(n < 2, "too big");
+///         Ok(n)
+///     }
+/// );
+///
+/// let panic_val =
+///     std::panic::catch_unwind(|| easy_ffi::call_foreign(|| unsafe { call_twice(check) }))
+///         .unwrap_err();
+/// assert_eq!(Some(&"too big"), panic_val.downcast_ref::<&str>());
+/// ```
+pub fn call_foreign<T>(f: impl FnOnce() -> T) -> T {
+    let ret = f();
+    resume_deferred_panic();
+    ret
+}
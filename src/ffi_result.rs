@@ -0,0 +1,45 @@
+use std::os::raw::c_int;
+
+use crate::FfiDefault;
+
+/// A value and a status code, returned together.
+///
+/// This is what functions generated with `returns = "tagged"` return, for
+/// callers that can't easily get at out-parameters or thread-local errors.
+/// `code` is `0` on success, and `value` is only meaningful then; on failure
+/// it's the value's [`FfiDefault`](trait.FfiDefault.html).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FfiResult<T> {
+    /// `0` on success, or the code returned by the handler.
+    pub code: c_int,
+    /// The value, on success.
+    pub value: T,
+}
+
+impl<T> FfiResult<T> {
+    /// A successful result.
+    pub fn ok(value: T) -> FfiResult<T> {
+        FfiResult { code: 0, value }
+    }
+
+    /// A failed result with the given code.
+    pub fn err(code: c_int) -> FfiResult<T>
+    where
+        T: FfiDefault,
+    {
+        FfiResult {
+            code,
+            value: T::ffi_default(),
+        }
+    }
+
+    /// Convert into a `Result` with the code as the error.
+    pub fn into_result(self) -> Result<T, c_int> {
+        if self.code == 0 {
+            Ok(self.value)
+        } else {
+            Err(self.code)
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::ffi::c_void;
+use std::mem::ManuallyDrop;
+use std::num::{
+    NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU16, NonZeroU32,
+    NonZeroU64, NonZeroU8, NonZeroUsize,
+};
+use std::ptr::NonNull;
+
+use crate::{FfiErrorInfo, FfiResult, JobStatus, Severity};
+
+/// Types that C can be handed, for `check_ffi_types = true`.
+///
+/// With that option, wrapped functions that return or take a type that
+/// doesn't implement this fail to compile, pointing at the type, rather
+/// than getting an `improper_ctypes_definitions` warning from somewhere in
+/// the macro's expansion. Arguments that are converted, like `&str` and
+/// slices, are checked as what C passes for them.
+///
+/// It's implemented for the primitive types C has, for pointers to sized
+/// types, for references and `NonNull`s to types implementing it, and their
+/// `Option`s, and for `extern "C" fn` pointers of up to eight arguments.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::{FfiDefault, FfiSafe};
+///
+/// #[repr(C)]
+/// #[derive(Clone, Copy)]
+/// pub struct Point {
+///     pub x: f64,
+///     pub y: f64,
+/// }
+///
+/// unsafe impl FfiSafe for Point {}
+///
+/// impl FfiDefault for Point {
+///     fn ffi_default() -> Point {
+///         Point { x: f64::NAN, y: f64::NAN }
+///     }
+/// }
+///
+/// easy_ffi!(my_ffi_fn => check_ffi_types = true);
+///
+/// my_ffi_fn!(
+///     fn origin() -> Result<Point, String> {
+///         Ok(Point { x: 0.0, y: 0.0 })
+///     }
+/// );
+/// # fn main() {
+/// #     assert_eq!(0.0, origin().x);
+/// # }
+/// ```
+///
+/// # Safety
+///
+/// The type has to have a layout C can use: a `#[repr(C)]` struct or union
+/// of `FfiSafe` fields, a fieldless enum with a `#[repr(C)]` or integer
+/// `repr`, or `#[repr(transparent)]` over an `FfiSafe` type.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` isn't FFI-safe",
+    label = "can't be passed to or from C",
+    note = "with `check_ffi_types = true`, wrapped functions can only use types implementing `FfiSafe`",
+    note = "for a `#[repr(C)]` type of your own, add an `unsafe impl easy_ffi::FfiSafe` for it"
+)]
+pub unsafe trait FfiSafe {}
+
+macro_rules! ffi_safe {
+    ($($ty:ty)*) => {
+        $(unsafe impl FfiSafe for $ty {})*
+    };
+}
+
+ffi_safe!(
+    () bool c_void
+    u8 u16 u32 u64 usize i8 i16 i32 i64 isize f32 f64
+    NonZeroU8 NonZeroU16 NonZeroU32 NonZeroU64 NonZeroUsize
+    NonZeroI8 NonZeroI16 NonZeroI32 NonZeroI64 NonZeroIsize
+    FfiErrorInfo JobStatus Severity
+);
+
+unsafe impl<T> FfiSafe for *const T {}
+unsafe impl<T> FfiSafe for *mut T {}
+unsafe impl<T: FfiSafe> FfiSafe for &T {}
+unsafe impl<T: FfiSafe> FfiSafe for &mut T {}
+unsafe impl<T: FfiSafe> FfiSafe for Option<&T> {}
+unsafe impl<T: FfiSafe> FfiSafe for Option<&mut T> {}
+unsafe impl<T: FfiSafe> FfiSafe for NonNull<T> {}
+unsafe impl<T: FfiSafe> FfiSafe for Option<NonNull<T>> {}
+unsafe impl<T: FfiSafe> FfiSafe for ManuallyDrop<T> {}
+unsafe impl<T: FfiSafe> FfiSafe for FfiResult<T> {}
+
+macro_rules! ffi_safe_fns {
+    ($(($($arg:ident),*))*) => {
+        $(
+            unsafe impl<R, $($arg),*> FfiSafe for extern "C" fn($($arg),*) -> R {}
+            unsafe impl<R, $($arg),*> FfiSafe for unsafe extern "C" fn($($arg),*) -> R {}
+            unsafe impl<R, $($arg),*> FfiSafe for Option<extern "C" fn($($arg),*) -> R> {}
+            unsafe impl<R, $($arg),*> FfiSafe for Option<unsafe extern "C" fn($($arg),*) -> R> {}
+        )*
+    };
+}
+
+ffi_safe_fns!(()(A)(A, B)(A, B, C)(A, B, C, D)(A, B, C, D, E)(
+    A, B, C, D, E, F
+)(A, B, C, D, E, F, G)(A, B, C, D, E, F, G, H));
+
+/// Used for `check_ffi_types = true`: fails to compile unless `T` is
+/// FFI-safe.
+#[doc(hidden)]
+pub fn check_ffi_safe<T: FfiSafe>() {}
+
+/// Used for `check_ffi_types = true`: fails to compile unless `T`, the type
+/// of the parameter `Param` is named after, is FFI-safe.
+#[doc(hidden)]
+pub fn check_ffi_arg<T: FfiSafeArg<Param>, Param>() {}
+
+/// What `check_ffi_arg` checks, which only exists for the parameter's name
+/// to be in the error.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`{Param}` has the type `{Self}`, which isn't FFI-safe",
+    label = "can't be passed from C",
+    note = "with `check_ffi_types = true`, wrapped functions can only use types implementing `FfiSafe`",
+    note = "for a `#[repr(C)]` type of your own, add an `unsafe impl easy_ffi::FfiSafe` for it"
+)]
+pub trait FfiSafeArg<Param> {}
+
+impl<T: FfiSafe, Param> FfiSafeArg<Param> for T {}
@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::os::raw::c_int;
+
+/// A call through a C vtable that failed.
+///
+/// Used by [`easy_ffi_foreign_vtable!`](macro.easy_ffi_foreign_vtable.html),
+/// whose methods need an error type implementing `From<ForeignError>`. It's
+/// implemented here for `io::Error` and `String`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ForeignError {
+    /// The vtable had a null pointer for the named method.
+    Missing(&'static str),
+    /// The named method returned a nonzero status.
+    Status(&'static str, c_int),
+}
+
+impl fmt::Display for ForeignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ForeignError::Missing(method) => write!(f, "`{}` is null", method),
+            ForeignError::Status(method, status) => {
+                write!(f, "`{}` failed with status {}", method, status)
+            }
+        }
+    }
+}
+
+impl Error for ForeignError {}
+
+impl From<ForeignError> for io::Error {
+    fn from(err: ForeignError) -> io::Error {
+        io::Error::other(err)
+    }
+}
+
+impl From<ForeignError> for String {
+    fn from(err: ForeignError) -> String {
+        err.to_string()
+    }
+}
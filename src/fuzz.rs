@@ -0,0 +1,68 @@
+use std::mem;
+
+/// A fuzzer's input, read as arguments for wrapped functions by
+/// [`easy_ffi_fuzz!`](macro.easy_ffi_fuzz.html).
+///
+/// Once the input runs out, everything else is read as zeroes, so every
+/// input is a valid call.
+#[derive(Debug, Clone)]
+pub struct FuzzInput<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> FuzzInput<'a> {
+    /// Reads arguments from `data`.
+    pub fn new(data: &'a [u8]) -> FuzzInput<'a> {
+        FuzzInput { data }
+    }
+
+    /// The next `N` bytes of the input.
+    pub fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let mut bytes = [0; N];
+        let n = N.min(self.data.len());
+        bytes[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        bytes
+    }
+
+    /// The next argument.
+    pub fn arg<T: FuzzArg>(&mut self) -> T {
+        T::take(self)
+    }
+
+    /// The next slice argument: a byte for its length, then its elements.
+    pub fn slice<T: FuzzArg>(&mut self) -> Vec<T> {
+        let len = self.arg::<u8>();
+        (0..len).map(|_| self.arg()).collect()
+    }
+}
+
+/// Argument types that [`easy_ffi_fuzz!`](macro.easy_ffi_fuzz.html) can
+/// make up from a fuzzer's input.
+///
+/// It's implemented for the integer and float types, and `bool`. Implement
+/// it for `#[repr(C)]` types of your own to fuzz functions taking them.
+pub trait FuzzArg: Sized {
+    /// Reads one from the input.
+    fn take(input: &mut FuzzInput<'_>) -> Self;
+}
+
+macro_rules! fuzz_arg_from_le_bytes {
+    ($($ty:ty)*) => {
+        $(
+            impl FuzzArg for $ty {
+                fn take(input: &mut FuzzInput<'_>) -> $ty {
+                    <$ty>::from_le_bytes(input.bytes::<{ mem::size_of::<$ty>() }>())
+                }
+            }
+        )*
+    };
+}
+
+fuzz_arg_from_le_bytes!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64);
+
+impl FuzzArg for bool {
+    fn take(input: &mut FuzzInput<'_>) -> bool {
+        input.arg::<u8>() & 1 == 1
+    }
+}
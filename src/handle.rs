@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::panic;
+use std::ptr;
+
+use crate::destructor::mark_allocated;
+use crate::failure::payload_str;
+use crate::redact::Redacted;
+
+/// Why a handle from C couldn't be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HandleError {
+    /// The handle was null.
+    Null,
+    /// The handle was valid once, but has since been freed.
+    Stale,
+    /// The handle was never valid.
+    Invalid,
+    /// The handle was freed twice.
+    DoubleFree,
+}
+
+impl fmt::Display for HandleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HandleError::Null => f.write_str("null handle"),
+            HandleError::Stale => f.write_str("stale handle"),
+            HandleError::Invalid => f.write_str("invalid handle"),
+            HandleError::DoubleFree => f.write_str("handle freed twice"),
+        }
+    }
+}
+
+impl Error for HandleError {}
+
+/// Used by [`easy_ffi_handle!`](macro.easy_ffi_handle.html)'s `new`
+/// function: boxes up `new()`'s value, or returns null if it panics.
+#[doc(hidden)]
+pub fn new_boxed<T, F>(fn_name: &'static str, new: F) -> *mut T
+where
+    F: FnOnce() -> T + panic::UnwindSafe,
+{
+    match panic::catch_unwind(move || Box::into_raw(Box::new(new()))) {
+        Ok(ptr) => {
+            mark_allocated(fn_name, ptr);
+            ptr
+        }
+        Err(panic_val) => {
+            let mut stderr = io::stderr();
+            let _ = match payload_str(&*panic_val) {
+                Some(msg) => writeln!(stderr, "{}: panic: {}", fn_name, Redacted(msg)),
+                None => writeln!(stderr, "{}: panic", fn_name),
+            };
+            // Dropping the payload could panic again.
+            ::std::mem::forget(panic_val);
+            ptr::null_mut()
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::failure::payload_str;
+use crate::redact::Redacted;
+
+/// A function was called before the library was initialized.
+///
+/// This is the error from the `check_initialized` function generated by
+/// [`easy_ffi_init!`](macro.easy_ffi_init.html), so with it as the `before`
+/// hook, wrapped functions need an error type implementing
+/// `From<NotInitialized>`. It's implemented here for `io::Error` and
+/// `String`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotInitialized {
+    /// The function that was called.
+    pub fn_name: &'static str,
+}
+
+impl fmt::Display for NotInitialized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}` was called before initialization", self.fn_name)
+    }
+}
+
+impl Error for NotInitialized {}
+
+impl From<NotInitialized> for io::Error {
+    fn from(err: NotInitialized) -> io::Error {
+        io::Error::other(err)
+    }
+}
+
+impl From<NotInitialized> for String {
+    fn from(err: NotInitialized) -> String {
+        err.to_string()
+    }
+}
+
+fn lock<S>(state: &Mutex<Option<S>>) -> MutexGuard<'_, Option<S>> {
+    // The setup and teardown closures run under `catch_unwind`, so the lock
+    // is only poisoned by a panic in a `with_state` closure, which can't
+    // have left the state half-stored.
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn print_panic(fn_name: &str, panic_val: Box<dyn std::any::Any + Send>) {
+    let mut stderr = io::stderr();
+    let _ = match payload_str(&*panic_val) {
+        Some(msg) => writeln!(stderr, "{}: panic: {}", fn_name, Redacted(msg)),
+        None => writeln!(stderr, "{}: panic", fn_name),
+    };
+    // Dropping the payload could panic again.
+    ::std::mem::forget(panic_val);
+}
+
+/// Used by the `init` function from
+/// [`easy_ffi_lifecycle!`](macro.easy_ffi_lifecycle.html): runs `setup` and
+/// stores the state it returns, unless there's already some.
+///
+/// Returns 0 if there's state afterwards, or -1 if `config` was null or
+/// `setup` failed or panicked, which is printed to stderr. `setup` runs with
+/// `state` locked, so it mustn't use `state` itself.
+///
+/// # Safety
+///
+/// `config` must be null or point to a `C` that lives for the call.
+#[doc(hidden)]
+pub unsafe fn lifecycle_init<C, S, E: fmt::Display>(
+    fn_name: &str,
+    state: &Mutex<Option<S>>,
+    config: *const C,
+    setup: impl FnOnce(&C) -> Result<S, E>,
+) -> c_int {
+    let mut state = lock(state);
+    if state.is_some() {
+        return 0;
+    }
+    let config = match config.as_ref() {
+        Some(config) => config,
+        None => {
+            let _ = writeln!(io::stderr(), "{}: `config` is null", fn_name);
+            return -1;
+        }
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| setup(config))) {
+        Ok(Ok(new_state)) => {
+            *state = Some(new_state);
+            0
+        }
+        Ok(Err(err)) => {
+            let _ = writeln!(io::stderr(), "{}: {}", fn_name, Redacted(err));
+            -1
+        }
+        Err(panic_val) => {
+            print_panic(fn_name, panic_val);
+            -1
+        }
+    }
+}
+
+/// Used by the `shutdown` function from
+/// [`easy_ffi_lifecycle!`](macro.easy_ffi_lifecycle.html): takes the stored
+/// state, if there is any, and passes it to `teardown`, printing a panic to
+/// stderr. Like `setup`, `teardown` runs with `state` locked.
+#[doc(hidden)]
+pub fn lifecycle_shutdown<S>(fn_name: &str, state: &Mutex<Option<S>>, teardown: impl FnOnce(S)) {
+    let mut state = lock(state);
+    if let Some(old_state) = state.take() {
+        if let Err(panic_val) = panic::catch_unwind(AssertUnwindSafe(|| teardown(old_state))) {
+            print_panic(fn_name, panic_val);
+        }
+    }
+}
+
+/// Used by the `with_state` function from
+/// [`easy_ffi_lifecycle!`](macro.easy_ffi_lifecycle.html). `f` runs with
+/// `state` locked, so it mustn't use `state` itself.
+#[doc(hidden)]
+pub fn with_lifecycle_state<S, R>(
+    fn_name: &'static str,
+    state: &Mutex<Option<S>>,
+    f: impl FnOnce(&mut S) -> R,
+) -> Result<R, NotInitialized> {
+    match lock(state).as_mut() {
+        Some(state) => Ok(f(state)),
+        None => Err(NotInitialized { fn_name }),
+    }
+}
@@ -0,0 +1,304 @@
+use std::io::{self, Write};
+use std::os::raw::{c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+use crate::failure::payload_str;
+use crate::redact::Redacted;
+
+/// What's become of a job started with `jobs = true`, as returned to C by
+/// its `_poll`, `_result` and `_cancel` exports.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    /// The job was null, or its result was already taken.
+    Invalid = -1,
+    /// It's waiting for a thread, or running.
+    Pending = 0,
+    /// It's finished, and its result can be taken.
+    Done = 1,
+    /// It was cancelled before it finished, so it has no result.
+    Cancelled = 2,
+}
+
+/// Lets jobs take C's arguments to the pool, and their results back.
+struct AssertSend<T>(T);
+
+// The callers of `job_start` and `run_with_callback` promise that the
+// arguments and results can be used from the pool's threads.
+unsafe impl<T> Send for AssertSend<T> {}
+
+enum State<T> {
+    Pending,
+    Done(AssertSend<T>),
+    Taken,
+    Cancelled,
+}
+
+/// A job started by a function's `_start` export, with `jobs = true`: the
+/// function running on a thread pool inside `easy_ffi`, or its result once
+/// it's done.
+///
+/// C only ever sees pointers to it, which it passes back to the other
+/// exports, and finally to `_free`.
+pub struct FfiJob<T> {
+    state: Mutex<State<T>>,
+    finished: Condvar,
+}
+
+impl<T> FfiJob<T> {
+    /// The state, which nothing can panic while holding.
+    fn state(&self) -> MutexGuard<'_, State<T>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn status(state: &State<T>) -> JobStatus {
+        match *state {
+            State::Pending => JobStatus::Pending,
+            State::Done(_) => JobStatus::Done,
+            State::Taken => JobStatus::Invalid,
+            State::Cancelled => JobStatus::Cancelled,
+        }
+    }
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Where jobs are sent to be picked up by the pool's threads, which are
+/// started the first time there's a job for them.
+static POOL: Mutex<Option<Sender<Task>>> = Mutex::new(None);
+
+fn spawn_pool() -> Sender<Task> {
+    let (sender, receiver) = mpsc::channel::<Task>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let threads = thread::available_parallelism().map_or(4, |n| n.get());
+    for i in 0..threads {
+        let receiver: Arc<Mutex<Receiver<Task>>> = receiver.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("easy_ffi-job-{}", i))
+            .spawn(move || loop {
+                // Tasks run after the lock's released, and can't panic.
+                let task = receiver
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .recv();
+                match task {
+                    Ok(task) => task(),
+                    Err(_) => return,
+                }
+            });
+        if let Err(err) = spawned {
+            let _ = writeln!(
+                io::stderr(),
+                "easy_ffi: couldn't start a job thread: {}",
+                err
+            );
+        }
+    }
+    sender
+}
+
+/// Used for `jobs = true` by the `_start` export: runs `f` on the pool and
+/// returns the job for C, or null if it couldn't be started.
+///
+/// # Safety
+///
+/// Whatever `f` holds on to has to be usable from another thread until the
+/// job's done, and so does its result.
+#[doc(hidden)]
+pub unsafe fn job_start<T: 'static>(
+    fn_name: &str,
+    f: impl FnOnce() -> T + 'static,
+) -> *mut FfiJob<T> {
+    let job = Arc::new(FfiJob {
+        state: Mutex::new(State::Pending),
+        finished: Condvar::new(),
+    });
+    let f = AssertSend(f);
+    let running = job.clone();
+    let task: Task = Box::new(move || {
+        let f = f;
+        if let State::Cancelled = *running.state() {
+            return;
+        }
+        // `f` is a wrapped function, which can only panic in its fallback.
+        let ret = match panic::catch_unwind(AssertUnwindSafe(f.0)) {
+            Ok(ret) => Some(ret),
+            Err(panic_val) => {
+                // Dropping the payload could panic again.
+                ::std::mem::forget(panic_val);
+                None
+            }
+        };
+        let mut state = running.state();
+        if let State::Pending = *state {
+            *state = match ret {
+                Some(ret) => State::Done(AssertSend(ret)),
+                None => State::Cancelled,
+            };
+        }
+        running.finished.notify_all();
+    });
+    if !submit(fn_name, task) {
+        return ::std::ptr::null_mut();
+    }
+    Arc::into_raw(job) as *mut FfiJob<T>
+}
+
+/// Sends `task` to the pool, or says why it couldn't be.
+fn submit(fn_name: &str, task: Task) -> bool {
+    // Nothing can panic while the lock is held.
+    let sent = POOL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get_or_insert_with(spawn_pool)
+        .send(task);
+    if sent.is_err() {
+        let _ = writeln!(io::stderr(), "{}: couldn't start the job", fn_name);
+    }
+    sent.is_ok()
+}
+
+/// Used for `async_callback = true` by the `_async` export: runs `f` on the
+/// pool, then calls `done` with what it returned and `user_data`. Returns
+/// whether it was started, which it isn't if `done` is null.
+///
+/// # Safety
+///
+/// Whatever `f` holds on to has to be usable from another thread until
+/// `done` is called, and so do its result and `user_data`.
+#[doc(hidden)]
+pub unsafe fn run_with_callback<T: 'static>(
+    fn_name: &str,
+    f: impl FnOnce() -> T + 'static,
+    done: Option<impl FnOnce(T, *mut c_void) + Send + 'static>,
+    user_data: *mut c_void,
+) -> bool {
+    let done = match done {
+        Some(done) => done,
+        None => {
+            let _ = writeln!(io::stderr(), "{}: called with a null callback", fn_name);
+            return false;
+        }
+    };
+    let f = AssertSend((f, user_data));
+    submit(
+        fn_name,
+        Box::new(move || {
+            let f = f;
+            let (f, user_data) = f.0;
+            // `f` is a wrapped function, which can only panic in its
+            // fallback, when there's nothing to call back with.
+            match panic::catch_unwind(AssertUnwindSafe(f)) {
+                Ok(ret) => done(ret, user_data),
+                // Dropping the payload could panic again.
+                Err(panic_val) => ::std::mem::forget(panic_val),
+            }
+        }),
+    )
+}
+
+/// Used for `jobs = true` by the `_poll` export: the job's status, without
+/// waiting for it.
+///
+/// # Safety
+///
+/// `job` must be null or come from `job_start`, and not yet be freed.
+#[doc(hidden)]
+pub unsafe fn job_poll<T>(job: *const FfiJob<T>) -> c_int {
+    match job.as_ref() {
+        Some(job) => FfiJob::status(&job.state()) as c_int,
+        None => JobStatus::Invalid as c_int,
+    }
+}
+
+/// Used for `jobs = true` by the `_result` export: waits for the job to
+/// finish, then moves its result to `out`, unless it's null.
+///
+/// # Safety
+///
+/// `job` must be null or come from `job_start`, and not yet be freed, and
+/// `out` must be null or valid to write a `T` to.
+#[doc(hidden)]
+pub unsafe fn job_result<T>(job: *const FfiJob<T>, out: *mut T) -> c_int {
+    let job = match job.as_ref() {
+        Some(job) => job,
+        None => return JobStatus::Invalid as c_int,
+    };
+    let mut state = job.state();
+    while let State::Pending = *state {
+        state = job
+            .finished
+            .wait(state)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+    match ::std::mem::replace(&mut *state, State::Taken) {
+        State::Done(ret) => {
+            if !out.is_null() {
+                ::std::ptr::write(out, ret.0);
+            }
+            JobStatus::Done as c_int
+        }
+        other => {
+            let status = FfiJob::status(&other);
+            *state = other;
+            status as c_int
+        }
+    }
+}
+
+/// Used for `jobs = true` by the `_cancel` export: cancels the job unless
+/// it's already finished, and returns its status. A job that's already
+/// running is left to finish, but its result is thrown away.
+///
+/// # Safety
+///
+/// `job` must be null or come from `job_start`, and not yet be freed.
+#[doc(hidden)]
+pub unsafe fn job_cancel<T>(job: *const FfiJob<T>) -> c_int {
+    let job = match job.as_ref() {
+        Some(job) => job,
+        None => return JobStatus::Invalid as c_int,
+    };
+    let mut state = job.state();
+    if let State::Pending = *state {
+        *state = State::Cancelled;
+        job.finished.notify_all();
+    }
+    FfiJob::status(&state) as c_int
+}
+
+/// Used for `jobs = true` by the `_free` export: cancels the job and frees
+/// it, along with any result that wasn't taken.
+///
+/// # Safety
+///
+/// `job` must be null or come from `job_start`, and mustn't be used again
+/// afterwards.
+#[doc(hidden)]
+pub unsafe fn job_free<T>(fn_name: &str, job: *mut FfiJob<T>) {
+    if job.is_null() {
+        return;
+    }
+    job_cancel(job);
+    let job = Arc::from_raw(job as *const FfiJob<T>);
+    let dropped = panic::catch_unwind(AssertUnwindSafe(|| drop(job)));
+    if let Err(panic_val) = dropped {
+        let mut stderr = io::stderr();
+        let _ = match payload_str(&*panic_val) {
+            Some(msg) => writeln!(
+                stderr,
+                "{}: panic while dropping: {}",
+                fn_name,
+                Redacted(msg)
+            ),
+            None => writeln!(stderr, "{}: panic while dropping", fn_name),
+        };
+        // Dropping the payload could panic again.
+        ::std::mem::forget(panic_val);
+    }
+}
@@ -0,0 +1,184 @@
+use std::any::Any;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::fmt::Display;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+use crate::failure::payload_str;
+use crate::panic_hook::take_panic_report;
+use crate::redact::redact;
+use crate::string::c_string_lossy;
+use crate::{ErrorChain, FfiFailure, Severity};
+
+/// An error recorded for C callers to retrieve later.
+///
+/// This is what the thread-local storage created by
+/// [`easy_ffi_last_error!`](macro.easy_ffi_last_error.html) holds: an error
+/// code and a NUL-terminated message, its [`Severity`](enum.Severity.html),
+/// the function it happened in if that's known, and with the `backtrace`
+/// feature, a backtrace from where it was recorded.
+#[derive(Debug, Clone)]
+pub struct LastError {
+    code: c_int,
+    message: CString,
+    severity: Severity,
+    function: Option<CString>,
+    backtrace: Option<CString>,
+}
+
+/// A [`LastError`](struct.LastError.html) as a C struct, for bindings that
+/// would rather fetch every field at once.
+///
+/// The strings are NUL-terminated and owned by the `LastError`, so they're
+/// only valid until the next error is recorded on this thread. Those that
+/// aren't known are null, and with no error, so is the message, and the code
+/// and severity are `0`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FfiErrorInfo {
+    /// The error code.
+    pub code: c_int,
+    /// The error message.
+    pub message: *const c_char,
+    /// The error's severity, as a `Severity` value.
+    pub severity: c_int,
+    /// The function the error happened in.
+    pub function: *const c_char,
+    /// The backtrace recorded with the error.
+    pub backtrace: *const c_char,
+}
+
+impl Default for FfiErrorInfo {
+    /// The info for no error.
+    fn default() -> FfiErrorInfo {
+        FfiErrorInfo {
+            code: 0,
+            message: ptr::null(),
+            severity: 0,
+            function: ptr::null(),
+            backtrace: ptr::null(),
+        }
+    }
+}
+
+#[cfg(feature = "backtrace")]
+fn capture_backtrace() -> Option<CString> {
+    Some(c_string_lossy(
+        &std::backtrace::Backtrace::force_capture().to_string(),
+    ))
+}
+
+#[cfg(not(feature = "backtrace"))]
+fn capture_backtrace() -> Option<CString> {
+    None
+}
+
+impl LastError {
+    /// A new error with the given code and message. The message is rewritten
+    /// by the hook from [`set_redaction_hook`](fn.set_redaction_hook.html),
+    /// if there is one, and any NULs in it are replaced with U+FFFD, as by
+    /// [`c_string_lossy`](fn.c_string_lossy.html).
+    pub fn new(code: c_int, message: &str) -> LastError {
+        LastError {
+            code,
+            message: c_string_lossy(&redact(message)),
+            severity: Severity::Error,
+            function: None,
+            backtrace: capture_backtrace(),
+        }
+    }
+
+    /// An error whose message is `err`'s `Display` output.
+    pub fn from_error<E: Display>(code: c_int, err: &E) -> LastError {
+        LastError::new(code, &err.to_string())
+    }
+
+    /// An error whose message is `err`'s `Display` output followed by each of
+    /// its sources', as shown by [`ErrorChain`](struct.ErrorChain.html).
+    pub fn from_error_chain<E: Error>(code: c_int, err: &E) -> LastError {
+        LastError::from_error(code, &ErrorChain(err))
+    }
+
+    /// An error whose message is the panic's, if it has one. With the hook
+    /// from [`easy_ffi_panic_hook!`](macro.easy_ffi_panic_hook.html)
+    /// installed, it's the thread's [`PanicReport`](struct.PanicReport.html)
+    /// instead, which has the location too.
+    pub fn from_panic(code: c_int, panic_val: &(dyn Any + Send)) -> LastError {
+        match take_panic_report() {
+            // The panic's own backtrace is more use than one from here.
+            Some(report) if cfg!(feature = "backtrace") => LastError {
+                code,
+                message: c_string_lossy(&redact(&report.to_string())),
+                severity: Severity::Error,
+                function: None,
+                backtrace: Some(c_string_lossy(&report.backtrace().to_string())),
+            },
+            Some(report) => LastError::from_error(code, &report),
+            None => LastError::new(code, payload_str(panic_val).unwrap_or("panic")),
+        }
+    }
+
+    /// An error for either kind of failure, as passed to a single handler.
+    pub fn from_failure<E: Display>(code: c_int, failure: &FfiFailure<E>) -> LastError {
+        match *failure {
+            FfiFailure::Error(ref err) => LastError::from_error(code, err),
+            FfiFailure::Panic(ref panic_val) => LastError::from_panic(code, &**panic_val),
+        }
+    }
+
+    /// The same error, as having happened in `function`. The built-in
+    /// handlers record the wrapped function's name this way.
+    pub fn with_function(self, function: &str) -> LastError {
+        LastError {
+            function: Some(c_string_lossy(function)),
+            ..self
+        }
+    }
+
+    /// The same error, with the given severity rather than
+    /// `Severity::Error`.
+    pub fn with_severity(self, severity: Severity) -> LastError {
+        LastError { severity, ..self }
+    }
+
+    /// The error code.
+    pub fn code(&self) -> c_int {
+        self.code
+    }
+
+    /// The error message.
+    pub fn message(&self) -> &CStr {
+        &self.message
+    }
+
+    /// How bad the error is.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// With the `backtrace` feature, a backtrace from where the error was
+    /// recorded, or for a panic, from where it happened if the hook from
+    /// [`easy_ffi_panic_hook!`](macro.easy_ffi_panic_hook.html) is
+    /// installed. Always `None` without it.
+    pub fn backtrace(&self) -> Option<&CStr> {
+        self.backtrace.as_deref()
+    }
+
+    /// The function the error happened in, if it's known.
+    pub fn function(&self) -> Option<&CStr> {
+        self.function.as_deref()
+    }
+
+    /// All of the above, borrowed for C.
+    pub fn info(&self) -> FfiErrorInfo {
+        let as_ptr = |s: Option<&CStr>| s.map_or(ptr::null(), CStr::as_ptr);
+        FfiErrorInfo {
+            code: self.code,
+            message: self.message.as_ptr(),
+            severity: self.severity as c_int,
+            function: as_ptr(self.function()),
+            backtrace: as_ptr(self.backtrace()),
+        }
+    }
+}
@@ -80,10 +80,7 @@
 //!     // Next, the panic. This will have the type `Box<Any + Send + 'static>`. See
 //!     // `::std::panic::catch_unwind` for more details.
 //!     |panic_val| {
-//!         match panic_val.downcast_ref::<&'static str>() {
-//!             Some(s) => println!("panic: {}", s),
-//!             None => println!("unknown panic!"),
-//!         }
+//!         println!("panic: {}", easy_ffi::panic_message(&*panic_val));
 //!         // As with the error handler, the panic handler also needs to return
 //!         // the real ffi return type.
 //!         -1
@@ -105,74 +102,7685 @@
 //! );
 //! # fn main() {}
 //! ```
+//!
+//! Either handler can take a second argument, which is bound to the name of
+//! the wrapped function as a `&'static str`, e.g. `|err, fn_name| { ... }`.
+//! This is handy for log messages.
+//!
+//! A third argument opts in to getting copies of the wrapped function's
+//! arguments, as a tuple: `|err, fn_name, args| { ... }`. These are cloned
+//! before the function runs, so every argument must be `Clone`. A `clone`
+//! that panics is treated like a panicking handler, and gets the `fallback`.
+//!
+//! If both cases are handled the same way, a single handler can be given
+//! instead, which receives an [`FfiFailure`](enum.FfiFailure.html):
+//!
+//! ```
+//! # #[macro_use] extern crate easy_ffi;
+//! use easy_ffi::FfiFailure;
+//!
+//! easy_ffi!(my_ffi_fn =>
+//!     |failure| {
+//!         match failure {
+//!             FfiFailure::Error(err) => println!("error: {}", err),
+//!             FfiFailure::Panic(_) => println!("panic!"),
+//!         }
+//!         -1
+//!     }
+//! );
+//! # fn main() {}
+//! ```
+//!
+//! With no handlers at all, failures are logged to stderr (which needs the
+//! error type to be `Debug`) and the function returns
+//! [`FfiDefault::ffi_default()`](trait.FfiDefault.html), e.g. `-1` for an
+//! `i32` or null for a pointer (see also the `last_error` option below):
+//!
+//! ```
+//! # #[macro_use] extern crate easy_ffi;
+//! easy_ffi!(my_ffi_fn =>);
+//!
+//! my_ffi_fn!(
+//!     fn foo(i: i32) -> Result<i32, &'static str> {
+//!         i.checked_add(1).ok_or("overflow")
+//!     }
+//! );
+//! # fn main() {
+//! #     assert_eq!(foo(::std::i32::MAX), -1);
+//! # }
+//! ```
+//!
+//! Handlers can also return `FfiDefault::ffi_default()` themselves, so that
+//! one wrapper macro works for functions with any return type. That's the
+//! same value for every function returning the type, though, so a function
+//! that needs another sentinel has to return it from handlers of its own.
+//!
+//! ## Options
+//!
+//! Options for the generated wrapper macro go between the `=>` and the
+//! handlers, each followed by a comma:
+//!
+//! * `abi = "..."`: the ABI of the generated functions, `"C"` by default. For
+//!   example, `abi = "system"` gives `stdcall` on 32-bit Windows, as Win32
+//!   APIs expect.
+//!
+//!   Use `abi = "C-unwind"` (or `"system-unwind"`) if the body calls foreign
+//!   functions that may throw, e.g. C++ code declared as `extern "C-unwind"`.
+//!   A foreign exception unwinding through a plain `"C"` frame is an abort at
+//!   best, while with an unwinding ABI it reaches the `catch_unwind` around the
+//!   body and is passed to the panic handler as an opaque payload. The
+//!   standard library leaves it unspecified whether catching it instead
+//!   aborts the process, so don't rely on recovering from it.
+//! * `prefix = "..."`: prepended to the exported symbol name of every
+//!   generated function, e.g. `prefix = "mylib_"` exports `fn foo` as
+//!   `mylib_foo`. The Rust name of the function stays `foo`.
+//! * `context = name: expr`: binds `name` to `expr` inside the handlers, e.g.
+//!   `context = logger: &LOGGER` to log through a `static` rather than
+//!   reaching for globals in each handler. The expression is evaluated each
+//!   time a handler runs. Because of macro hygiene, a `context` given in a
+//!   function's `#[easy_ffi(...)]` attribute is only visible to handlers
+//!   given alongside it.
+//! * `fallback = expr`: returned if a handler itself panics, or cloning
+//!   the arguments for one does. Without it, a panicking handler aborts the
+//!   process, since letting it unwind into C would be undefined behavior.
+//! * `assert_unwind_safe = true`: wrap the function body in
+//!   `AssertUnwindSafe` before catching panics from it, for bodies that
+//!   touch things like `&mut` references or `RefCell`s and so aren't
+//!   `UnwindSafe`. It's then up to you not to observe anything left broken
+//!   by a panic.
+//! * `returns = "out_param"`: rather than returning the ok value, write it
+//!   through an extra `out: *mut T` parameter and return `0`, so
+//!   `fn foo(a: i32) -> Result<Widget, E>` becomes
+//!   `unsafe extern "C" fn foo(a: i32, out: *mut Widget) -> c_int`. The
+//!   handlers return the `c_int` status for failures. Nothing is written on
+//!   failure, or if `out` is null.
+//! * `returns = "tagged"`: return the value along with a status code, as an
+//!   [`FfiResult<T>`](struct.FfiResult.html). Again, the handlers return the
+//!   `c_int` code for failures.
+//! * `returns = "status"`: for functions returning `Result<(), E>`, return a
+//!   `c_int` status: `0` on success, and whatever the handlers return on
+//!   failure.
+//! * `returns = "bool"`: for functions returning `Result<bool, E>`, return a
+//!   `c_int` rather than a Rust `bool`: `1` for true, `0` for false, and
+//!   whatever the handlers return on failure.
+//! * `returns = "buffer"`: for functions returning `Result<String, E>`, copy
+//!   the string into a buffer from the caller, in two extra `buf: *mut
+//!   c_char, len: usize` parameters, instead of returning it. Like
+//!   `snprintf`, the string is cut short to fit, always NUL-terminated, and
+//!   its full length is returned, so it was cut short if that's at least
+//!   `len`. Nothing is written if `buf` is null, so callers can ask for the
+//!   length first. The string's bytes are copied as they are, so unlike the
+//!   other ways of returning a `String`, this doesn't need `From<NulError>`,
+//!   but a NUL in it ends the string early for C. The function is unsafe,
+//!   and the handlers return a negative `c_int` for failures.
+//! * `returns = "scratch"`: for functions returning `Result<String, E>`,
+//!   return a `*const c_char` to a string kept in thread-local storage,
+//!   which the caller doesn't free. It's only valid until the next function
+//!   on the same thread that returns a string this way, so it suits messages
+//!   that are read right away, without the bother of freeing them.
+//!
+//!   The default is `returns = "value"`.
+//! * `success = expr`: the status returned on success with
+//!   `returns = "out_param"` or `returns = "status"`, `0` by default.
+//! * `true_value = expr` and `false_value = expr`: what `returns = "bool"`
+//!   returns for `true` and `false`.
+//! * `free_results = true`: also export a destructor for each function
+//!   returning an owned pointer (see [Return types](#return-types)), named
+//!   after the function's symbol with `_result_free` added, e.g.
+//!   `widget_new_result_free(ptr: *mut Widget)`, like one made by
+//!   [`easy_ffi_destructor!`](macro.easy_ffi_destructor.html) with
+//!   `double_free_guard = true`. Only C can call these.
+//! * `jobs = true`: also export functions for running it in the
+//!   background on a thread pool inside `easy_ffi`, named after the
+//!   function's symbol with suffixes: `foo_start` takes the same parameters
+//!   and returns a [`FfiJob`](struct.FfiJob.html) pointer, `foo_poll(job)`
+//!   returns its [`JobStatus`](enum.JobStatus.html) without waiting,
+//!   `foo_result(job, out)` waits for it and writes what `foo` returned to
+//!   `out`, and `foo_cancel(job)` cancels it if it hasn't finished. Every job
+//!   is finally passed to `foo_free(job)`. Pointer arguments have to stay
+//!   valid until the job's done, and since it runs on another thread, its
+//!   errors can't be read with a `last_error` function. Only C can call
+//!   these.
+//! * `async_callback = true`: also export `foo_async`, named after the
+//!   function's symbol with `_async` added, which takes the same parameters
+//!   followed by `done: extern "C" fn(ret, user_data)` and `user_data:
+//!   *mut c_void`, and runs the function in the background on the same pool
+//!   as `jobs`. Both it and `done` use the `abi` given, if it isn't `"C"`.
+//!   Once it's done, `done` is called from the pool with what it returned,
+//!   e.g. a value or an error code from the handlers, and `user_data`. It
+//!   returns whether it was started, which it isn't if `done` is null. The
+//!   same restrictions as for `jobs` apply.
+//! * `safe = true`: also generate a safe Rust version of each function, for
+//!   Rust tests and users to call without going through raw pointers. It's
+//!   `safe` in a module named after the function, e.g. `parse::safe(s:
+//!   &str) -> Result<i32, FfiFailure<E>>`, taking the arguments as declared.
+//!   It checks for poisoning and runs `before` like the generated function,
+//!   and catches panics, but returns the failure rather than calling the
+//!   handlers. The module has a `use super::*;`, so the body can refer to
+//!   the same things, except through `self::` or `super::` paths.
+//! * `inner = true`: also keep the body as it was written, as `inner` in
+//!   the same module as `safe`, e.g. `parse::inner(s: &str) -> Result<i32,
+//!   E>`, so tests can look at the errors themselves rather than what C
+//!   would get. It's only visible where the function was defined, and
+//!   nothing is caught or checked.
+//! * `panic_tests = true`: in the crate's own unit tests, let
+//!   [`easy_ffi_tests!`](macro.easy_ffi_tests.html) make the function panic
+//!   as soon as it's called. Nothing is added to other builds, or to
+//!   functions without it.
+//! * `export = false`: don't export the generated functions, leaving them
+//!   plain `extern "C" fn`s, e.g. for callbacks handed to C as function
+//!   pointers. See [`easy_ffi_callback!`](macro.easy_ffi_callback.html).
+//! * `check_ffi_types = true`: check that the types passed to and returned
+//!   from C implement [`FfiSafe`](trait.FfiSafe.html), so that using
+//!   something C can't, like a `Vec` or a `String` or a reference to a type
+//!   that isn't `#[repr(C)]`, fails to compile with an error pointing at
+//!   it.
+//! * `improper_ctypes = "deny"`: set the level of the
+//!   `improper_ctypes_definitions` lint on the generated functions, rather
+//!   than leaving it to the crate's. `"deny"` makes it an error for a
+//!   function's types to not be FFI-safe, while `"allow"` is for when they're
+//!   fine anyway, e.g. a zero-sized marker that C never sees. `"warn"` is
+//!   the lint's own default.
+//! * `manifest = true`: with the `manifest` feature, list the exported
+//!   functions in [`exports()`](fn.exports.html), with their symbols, the
+//!   types C passes and gets back, and their doc comments, for tools built
+//!   on top of the library. [`easy_ffi_api_json!`](macro.easy_ffi_api_json.html)
+//!   exports them to C as JSON.
+//! * `null_checks = true`: check every raw pointer argument for null before
+//!   the body runs, passing an [`ArgError`](enum.ArgError.html) to the
+//!   handlers if one is, as described in [Arguments](#arguments). Arguments
+//!   marked `#[nullable]` are left alone.
+//! * `before = expr`: call `expr`, a function or closure taking the wrapped
+//!   function's name as a `&'static str` and returning `Result<(), E>`,
+//!   before each function's arguments are converted and its body runs. An
+//!   `Err` goes to the handlers instead of running the body, converted to
+//!   the function's error type with `From`, and panics are caught along
+//!   with the body's. This suits things like checking that the library's
+//!   been initialized, or logging calls.
+//! * `after = expr`: call `expr` with the wrapped function's name, a
+//!   reference to its `Result<T, FfiFailure<E>>`, and the `Duration` it took,
+//!   each time the body has run, before the handlers do. This suits things
+//!   like metrics, or flushing logs. Panics in it are treated like panics in
+//!   the handlers.
+//! * `tracing = true`: with the `tracing` feature, run each call in an
+//!   `INFO` span named after the function, with an `args` field listing the
+//!   arguments C passed in, using their `Debug` output where they have one,
+//!   and record failures in it as `ERROR` events, with an `error` or `panic`
+//!   field. The error is likewise shown with `Debug`, or as `_`. A `Debug`
+//!   impl that panics while the span is made gets the `fallback`.
+//! * `stats = true`: count each function's calls, errors and panics, for
+//!   [`fn_stats`](fn.fn_stats.html), or C callers through the export from
+//!   [`easy_ffi_stats!`](macro.easy_ffi_stats.html), so that it's possible
+//!   to see which functions are failing in production. To send metrics
+//!   elsewhere, e.g. Prometheus, install an
+//!   [`FfiMetrics`](trait.FfiMetrics.html), which every function reports to
+//!   without an option.
+//! * `timeout = duration`: run the body on a thread of its own, and if it
+//!   hasn't finished after the `Duration`, go to the error handler with a
+//!   [`TimedOut`](struct.TimedOut.html) error, converted to the function's
+//!   error type with `From`, leaving the body to finish in the background.
+//!   This is for hosts that can't be kept waiting. The body and its
+//!   arguments have to be `Send`, so pointer arguments can't be used, and
+//!   its result `'static`.
+//! * `stack_size = bytes`: run the body on a thread of its own with a stack
+//!   of that many bytes, and wait for it, for deeply recursive bodies that
+//!   would overflow the small stacks some hosts, like the JVM, give their
+//!   threads. Panics on it go to the panic handler as usual. The body still
+//!   has the arguments C passed in, but mustn't count on running on C's
+//!   thread, e.g. by using its thread locals.
+//! * `log_limit = n`: let the built-in handlers log at most `n` of the
+//!   function's failures a second, the rest being dropped, so that a C
+//!   caller retrying in a tight loop can't flood the logs. The next failure
+//!   logged after some were dropped is preceded by `fn_name: suppressed k
+//!   messages`. Only logging is limited: failures are still recorded and
+//!   passed to the error callback. Handlers of your own can't be limited
+//!   this way, but can use a [`LogLimit`](struct.LogLimit.html) themselves.
+//! * `poison = true`: once any function wrapped this way panics, the library
+//!   is considered poisoned, and every call to one of them goes straight to
+//!   the error handler with a [`Poisoned`](struct.Poisoned.html) error,
+//!   converted to the function's error type with `From`, rather than running
+//!   on possibly broken state. [`reset_poison`](fn.reset_poison.html), or the
+//!   export from [`easy_ffi_poison!`](macro.easy_ffi_poison.html), lets them
+//!   run again.
+//! * `last_error = path::to::set_last_error`: when there are no handlers,
+//!   record failures with a function generated by
+//!   [`easy_ffi_last_error!`](macro.easy_ffi_last_error.html), with the code
+//!   `-1`, rather than logging them. The error type must be `Display`.
+//! * `handlers = "default"`: go back to the default handling, as if no
+//!   handlers had been given. This is mostly useful in a function's
+//!   `#[easy_ffi(...)]`, e.g. for a pointer-returning function that should
+//!   return null on failure in a wrapper macro whose handlers return `-1`.
+//! * `handlers = "error_code"`: the default handling, except that with
+//!   `last_error`, errors are recorded with their
+//!   [`FfiErrorCode`](trait.FfiErrorCode.html) and `Display` message, and
+//!   panics with [`PANIC_CODE`](constant.PANIC_CODE.html). With an error
+//!   enum deriving both `thiserror::Error` and `FfiErrorCode`, C callers get
+//!   a stable code and a formatted message for each variant. Each
+//!   error's [`Severity`](enum.Severity.html) is acted on as well, and
+//!   recorded with `last_error`.
+//! * `handlers = "error_chain"`: the default handling, except that errors
+//!   are shown with each of their `source`s, as `A: caused by B: caused by
+//!   C`, both when they're logged and in the `last_error` message. The error
+//!   type must implement `std::error::Error`.
+//! * `handlers = "anyhow"`: with the `anyhow` feature, the default handling
+//!   for functions returning `anyhow::Result<T>`, except that with
+//!   `last_error`, the message has the whole chain of contexts and causes,
+//!   e.g. `reading config: No such file or directory`, rather than only the
+//!   outermost context.
+//! * `handlers = "eyre"`: likewise with the `eyre` feature, for functions
+//!   returning `eyre::Result<T>`, except that the message is the full report
+//!   from the installed `EyreHandler`, e.g. `color-eyre`'s, with any colors
+//!   removed.
+//! * `handlers = "log"`: with the `log` feature, log failures with
+//!   `log::error!`, as `fn_name: err` or `fn_name: panic: msg`, and return
+//!   `FfiDefault::ffi_default()`, so that they go wherever the host
+//!   application's logs do. With `last_error`, they're recorded as well. The
+//!   error type must be `Display`.
+//! * `log_target = "..."`: the target for `handlers = "log"`, which must come
+//!   after it. By default this is the module the wrapper macro is used in.
+//! * `on_panic = "abort"`: rather than calling the panic handler, print the
+//!   panic to stderr and abort the process, for callers that would rather
+//!   crash loudly than get an error value back. With a single handler, it
+//!   then only ever sees `FfiFailure::Error`.
+//! * `on_panic = "defer"`: for callbacks, keep the panic in thread-local
+//!   storage, so that Rust code calling the C function that called the
+//!   callback through [`call_foreign`](fn.call_foreign.html) resumes it
+//!   once that returns. The panic handler still runs, to get a value for C,
+//!   but gets a stand-in payload with the same message.
+//! * `on_panic = "error"`: convert panics to the function's error type with
+//!   `From<Box<dyn Any + Send>>`, so that they go to the error handler, or
+//!   with a single handler, arrive as `FfiFailure::Error`, and both kinds of
+//!   failure are treated the same. The panic handler is then never called.
+//! * `panic_error = expr`: like `on_panic = "error"`, but converting panics
+//!   with `expr`, a function or closure taking the `Box<dyn Any + Send>`
+//!   payload, e.g. `|panic_val| easy_ffi::panic_message(&*panic_val).into_owned()`
+//!   for a `String` error.
+//!
+//!   The default is `on_panic = "handle"`.
+//!
+//! ```
+//! # #[macro_use] extern crate easy_ffi;
+//! easy_ffi!(my_win32_fn =>
+//!     abi = "system",
+//!     |err| { -1 }
+//!     |panic_val| { -1 }
+//! );
+//! # fn main() {}
+//! ```
+//!
+//! ## Symbol names
+//!
+//! Generated functions are `#[no_mangle]`, so the exported symbol is the
+//! function's name. To export it under a different name, give the wrapped
+//! function an `#[export_name = "..."]` attribute, which is then used instead
+//! (without any `prefix`).
+//!
+//! ## Visibility
+//!
+//! Generated functions are `pub` by default. Any other visibility on the
+//! wrapped function, such as `pub(crate)`, is used as-is; use `pub(self)` for a
+//! private function. This only affects visibility from Rust: the linker still
+//! sees the `#[no_mangle]` symbol.
+//!
+//! ## `panic = "abort"`
+//!
+//! When the crate is built with `panic = "abort"`, panics never unwind, so
+//! the generated functions leave out `catch_unwind` entirely. The panic
+//! handler is never called, and `on_panic` and `fallback` have no effect.
+//!
+//! ## Unsafe functions
+//!
+//! Functions that rely on their callers for soundness, e.g. because they
+//! dereference raw pointer arguments, can be declared `unsafe fn`. The
+//! generated function is then an `unsafe extern "C" fn`.
+//!
+//! ## Async functions
+//!
+//! Wrapped functions can be `async fn`s, for exposing async code through a
+//! synchronous C API: the generated function blocks on the body until it's
+//! done. It's run on the [`FfiRuntime`](trait.FfiRuntime.html) given with
+//! `runtime = expr`, which is evaluated with each call, like the body, or
+//! on [`CurrentThread`](struct.CurrentThread.html) otherwise. With the
+//! `tokio` feature, `runtime = tokio` is the runtime started by C through
+//! [`easy_ffi_runtime!`](macro.easy_ffi_runtime.html).
+//!
+//! ## Per-function overrides
+//!
+//! A wrapped function can override the wrapper macro's options and handlers
+//! with an `#[easy_ffi(...)]` attribute, which takes the same options and
+//! handlers as `easy_ffi!`. Handlers are optional here, and when only some
+//! options are given the rest are inherited from the wrapper macro:
+//!
+//! ```
+//! # #[macro_use] extern crate easy_ffi;
+//! # easy_ffi!(my_ffi_fn => |err| { -1 } |panic_val| { -1 });
+//! # struct Thing;
+//! my_ffi_fn!(
+//!     /// Returns null rather than -1 on failure.
+//!     #[easy_ffi(
+//!         |err| { std::ptr::null_mut() }
+//!         |panic_val| { std::ptr::null_mut() }
+//!     )]
+//!     fn thing_new() -> Result<*mut Thing, &'static str> {
+//!         Ok(Box::into_raw(Box::new(Thing)))
+//!     }
+//! );
+//! # fn main() {}
+//! ```
+//!
+//! ## Wrapping several functions at once
+//!
+//! The generated wrapper macro takes any number of functions, which saves a
+//! macro invocation per function in larger APIs:
+//!
+//! ```
+//! # #[macro_use] extern crate easy_ffi;
+//! # easy_ffi!(my_ffi_fn => |err| { -1 } |panic_val| { -1 });
+//! my_ffi_fn! {
+//!     fn add(a: i32, b: i32) -> Result<i32, &'static str> {
+//!         a.checked_add(b).ok_or("overflow")
+//!     }
+//!
+//!     fn sub(a: i32, b: i32) -> Result<i32, &'static str> {
+//!         a.checked_sub(b).ok_or("overflow")
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! For a whole module of exports, [`easy_ffi_mod!`](macro.easy_ffi_mod.html)
+//! wraps every function in a `mod` without a separate wrapper macro.
+//!
+//! ## Reporting errors
+//!
+//! C libraries usually report errors through an error code or sentinel
+//! return value, with details kept until the caller asks for them.
+//! [`easy_ffi_last_error!`](macro.easy_ffi_last_error.html) sets up the
+//! storage and exported functions for this, for handlers to fill in.
+//!
+//! With the `errno` feature, handlers can instead report errors through
+//! `errno` using the helpers in [`easy_ffi::errno`](errno/index.html). On
+//! Windows, [`easy_ffi::windows`](windows/index.html) does the same for
+//! `SetLastError`.
+//!
+//! For failures that are hard to reproduce, the `backtrace` feature records
+//! a backtrace along with each last error, which C callers can fetch with
+//! the function `easy_ffi_last_error!` exports for it. Capturing one is
+//! slow, so this is best left off unless it's needed.
+//!
+//! Hosts that would rather be told about errors as they happen can register
+//! a callback through the function
+//! [`easy_ffi_error_callback!`](macro.easy_ffi_error_callback.html) exports,
+//! which the built-in handlers call with each failure's code and message.
+//!
+//! Messages can be scrubbed of anything sensitive before they go anywhere
+//! with a hook installed by
+//! [`set_redaction_hook`](fn.set_redaction_hook.html).
+//!
+//! To turn errors into codes, implement [`FfiErrorCode`](trait.FfiErrorCode.html)
+//! for them, or derive it for an error enum with the `derive` feature.
+//! [`easy_ffi_strerror!`](macro.easy_ffi_strerror.html) then exports a
+//! function that turns the codes back into messages.
+//!
+//! ## Return types
+//!
+//! Wrapped functions can return a plain `Result<T, E>`, a path-qualified one
+//! like `std::result::Result<T, E>`, or any alias, such as `io::Result<T>`,
+//! `anyhow::Result<T>` or your own `MyResult<T>`. Anything other than a
+//! literal `Result` is resolved through the [`IntoResult`](trait.IntoResult.html)
+//! trait.
+//!
+//! An ok type of `Box<T>` is returned as a `*mut T` instead, through
+//! `Box::into_raw`, which owns the value so that C can hand it back to be
+//! freed later. `Option<Box<T>>` is returned the same way, with `None` as
+//! null. The handlers then return a `*mut T` for failures as well. This needs
+//! a literal `Result`, since it's the written-out ok type that's matched.
+//! [`easy_ffi_destructor!`](macro.easy_ffi_destructor.html) exports the
+//! matching function to free the boxed values, and with the `live_objects` feature,
+//! [`easy_ffi_live_objects!`](macro.easy_ffi_live_objects.html) exports one
+//! listing those that haven't been freed yet.
+//!
+//! Likewise, a `String` is returned as a NUL-terminated `*mut c_char`, which
+//! C frees with the function exported by
+//! [`easy_ffi_string_free!`](macro.easy_ffi_string_free.html). A string with
+//! a NUL in it is passed to the error handler as a `NulError`, converted with
+//! `From`, so the error type must implement `From<NulError>`, as e.g.
+//! `io::Error` and `Box<dyn Error>` do.
+//!
+//! ## Arguments
+//!
+//! Arguments are passed through as-is, except for some Rust types with no C
+//! equivalent, which are converted from ffi types before the body runs. If
+//! that fails, the body doesn't run, and the handlers get an
+//! [`ArgError`](enum.ArgError.html), converted to the function's error type
+//! with `From`. Since the conversions trust pointers from the caller, the
+//! generated function is unsafe.
+//!
+//! * `s: &str` is passed as a NUL-terminated `s: *const c_char`, which must
+//!   not be null, and must be valid UTF-8.
+//! * `data: &[T]` is passed as `data: *const T, len: usize`. The pointer may
+//!   only be null if `len` is 0, and must be aligned for `T`. The length can
+//!   be given another name with `as`, e.g. `data: &[T] as data_len`, which
+//!   every slice but one has to be, so that they're all named differently.
+//! * `data: &mut [T]` is passed as `data: *mut T, len: usize`, and checked
+//!   the same way. Like any `&mut`, nothing else may touch the elements
+//!   until the function returns. That includes other slice arguments, so C
+//!   mustn't pass overlapping buffers, which isn't checked.
+//! * `f: extern "C" fn(..)` is passed as `Option<extern "C" fn(..)>`, which
+//!   has the same ABI, and must not be null. Callbacks that are allowed to be
+//!   null can just be declared as an `Option`.
+//! * `cancel: &FfiCancelToken` is passed as `cancel: *const FfiCancelToken`,
+//!   from the functions made by
+//!   [`easy_ffi_cancel_token!`](macro.easy_ffi_cancel_token.html), which
+//!   must not be null. It has to be written just like that, with
+//!   [`FfiCancelToken`](struct.FfiCancelToken.html) imported.
+//!
+//! A raw pointer argument marked `#[nonnull]`, e.g. `#[nonnull] widget: *mut
+//! Widget`, is checked for null the same way, as is every raw pointer with
+//! the `null_checks = true` option, unless it's marked `#[nullable]`. Other
+//! attributes check the argument's value before the body runs, but leave it
+//! as it is:
+//!
+//! * `#[nonzero]`: it isn't zero, or more precisely, its type's `Default`.
+//! * `#[range(1..=100)]`: it's in the given range, which can be any
+//!   expression implementing `RangeBounds`.
+//! * `#[utf8]`: it's a `*const c_char` pointing to a NUL-terminated UTF-8
+//!   string, which makes the function unsafe.
+//!
+//! A `&mut [T]` argument marked `#[zeroize]`, e.g. `#[zeroize] key: &mut
+//! [u8]`, is overwritten with zeroes if the function fails, including if
+//! the body panics partway through, before the handlers run. This is for
+//! buffers holding key material or the like, which shouldn't be left
+//! lying around after an error.
+//!
+//! Failed checks give the handlers an `ArgError` saying which argument was
+//! wrong and how, and any number of them can be stacked on one argument.
+//!
+//! ```
+//! # #[macro_use] extern crate easy_ffi;
+//! easy_ffi!(my_ffi_fn =>);
+//!
+//! my_ffi_fn!(
+//!     fn count_words(text: &str) -> Result<i32, String> {
+//!         Ok(text.split_whitespace().count() as i32)
+//!     }
+//! );
+//! # fn main() {
+//! #     assert_eq!(2, unsafe { count_words("two words\0".as_ptr() as *const _) });
+//! #     assert_eq!(-1, unsafe { count_words(std::ptr::null()) });
+//! # }
+//! ```
+//!
+//! ## Generics
+//!
+//! Wrapped functions may be generic over lifetimes, including bounds between
+//! them and `where` clauses, since these are erased at the ABI level. Type and
+//! const parameters are rejected: a `#[no_mangle]` function can't be
+//! monomorphized.
+//!
+//! ## Attribute form
+//!
+//! With the `attr` feature enabled, functions can instead be annotated with
+//! [`#[easy_ffi(handler = ...)]`](attr/index.html), naming a wrapper macro
+//! created by `easy_ffi!`. This keeps the function a plain Rust item, which
+//! tooling like rust-analyzer and cbindgen copes with much better.
+//!
+//! ## Testing
+//!
+//! Unit tests can call the bodies of wrapped functions directly with
+//! `inner = true` or `safe = true`, and
+//! [`easy_ffi_tests!`](macro.easy_ffi_tests.html) checks that the handlers
+//! of functions wrapped with `panic_tests = true` don't let panics through.
+//! To check the built library itself, export
+//! [`easy_ffi_selftest!`](macro.easy_ffi_selftest.html) and open it from an
+//! integration test with the `selftest` feature's
+//! [`selftest::check_library`](selftest/fn.check_library.html). For
+//! `cargo-fuzz` targets, [`easy_ffi_fuzz!`](macro.easy_ffi_fuzz.html) calls
+//! wrapped functions with arguments made up from the fuzzer's input.
+
+#[cfg(feature = "anyhow")]
+mod anyhow;
+mod arg;
+#[cfg(feature = "attr")]
+pub mod attr;
+mod callback;
+mod cancel;
+mod default;
+mod destructor;
+#[cfg(feature = "errno")]
+pub mod errno;
+mod error_callback;
+mod error_chain;
+mod error_code;
+#[cfg(feature = "eyre")]
+mod eyre;
+mod failure;
+mod ffi_result;
+mod ffi_safe;
+mod foreign;
+mod fuzz;
+mod handle;
+mod init;
+mod jobs;
+mod last_error;
+#[cfg(feature = "live_objects")]
+mod live;
+#[cfg(feature = "log")]
+mod log;
+#[cfg(feature = "manifest")]
+mod manifest;
+mod metrics;
+mod panic_hook;
+mod panic_test;
+mod poison;
+mod rate_limit;
+mod redact;
+mod registry;
+mod result;
+mod runtime;
+pub mod selftest;
+mod stack;
+mod stats;
+mod string;
+mod timeout;
+#[cfg(feature = "tokio")]
+mod tokio;
+#[cfg(feature = "tracing")]
+mod tracing;
+#[cfg(windows)]
+pub mod windows;
+
+#[cfg(feature = "anyhow")]
+#[doc(hidden)]
+pub use crate::anyhow::{anyhow_handler, anyhow_last_error_handler};
+pub use crate::arg::ArgError;
+#[doc(hidden)]
+pub use crate::arg::{
+    check_non_null, check_non_zero, check_range, ref_arg, slice_arg, slice_mut_arg, str_arg,
+    zeroize_slice,
+};
+#[doc(hidden)]
+pub use crate::callback::call_boxed;
+pub use crate::cancel::{Cancelled, FfiCancelToken};
+pub use crate::default::FfiDefault;
+#[doc(hidden)]
+pub use crate::default::{
+    default_handler, error_code_default_handler, error_code_handler, last_error_handler,
+};
+pub use crate::destructor::free_boxed;
+#[doc(hidden)]
+pub use crate::destructor::{drop_boxed, drop_handle, free_string, mark_allocated};
+pub use crate::error_callback::{
+    call_error_callback, set_error_callback, set_thread_error_callback, ErrorCallback,
+};
+pub use crate::error_chain::ErrorChain;
+#[doc(hidden)]
+pub use crate::error_chain::{error_chain_handler, error_chain_last_error_handler};
+pub use crate::error_code::{FfiErrorCode, Severity, PANIC_CODE};
+#[cfg(feature = "eyre")]
+#[doc(hidden)]
+pub use crate::eyre::{eyre_handler, eyre_last_error_handler};
+#[doc(hidden)]
+pub use crate::failure::{abort_on_panic, defer_panic, panic_to_error};
+pub use crate::failure::{call_foreign, panic_message, resume_deferred_panic, FfiFailure};
+pub use crate::ffi_result::FfiResult;
+pub use crate::ffi_safe::FfiSafe;
+#[doc(hidden)]
+pub use crate::ffi_safe::{check_ffi_arg, check_ffi_safe};
+pub use crate::foreign::ForeignError;
+pub use crate::fuzz::{FuzzArg, FuzzInput};
+#[doc(hidden)]
+pub use crate::handle::new_boxed;
+pub use crate::handle::HandleError;
+pub use crate::init::NotInitialized;
+#[doc(hidden)]
+pub use crate::init::{lifecycle_init, lifecycle_shutdown, with_lifecycle_state};
+#[doc(hidden)]
+pub use crate::jobs::{job_cancel, job_free, job_poll, job_result, job_start, run_with_callback};
+pub use crate::jobs::{FfiJob, JobStatus};
+pub use crate::last_error::{FfiErrorInfo, LastError};
+#[cfg(feature = "live_objects")]
+#[doc(hidden)]
+pub use crate::live::dump_live_objects;
+#[cfg(feature = "log")]
+#[doc(hidden)]
+pub use crate::log::{log_handler, log_last_error_handler};
+#[cfg(feature = "manifest")]
+#[doc(hidden)]
+pub use crate::manifest::error_code_descriptions;
+#[cfg(feature = "manifest")]
+pub use crate::manifest::{api_json, exports, FfiExport, FfiParam};
+pub use crate::metrics::{clear_metrics, set_metrics, FfiMetrics};
+#[doc(hidden)]
+pub use crate::metrics::{metrics_call, metrics_result};
+#[doc(hidden)]
+pub use crate::panic_hook::{clear_panic_report, install_panic_hook};
+pub use crate::panic_hook::{take_panic_report, PanicLocation, PanicReport};
+#[doc(hidden)]
+pub use crate::panic_test::{inject_panic, with_injected_panic};
+#[cfg(feature = "manifest")]
+#[doc(hidden)]
+pub use crate::panic_test::{unlisted_panic_tests, PanicTest};
+#[doc(hidden)]
+pub use crate::poison::{check_poisoned, poison};
+pub use crate::poison::{is_poisoned, reset_poison, Poisoned};
+pub use crate::rate_limit::LogLimit;
+#[doc(hidden)]
+pub use crate::rate_limit::LogLimitGuard;
+#[doc(hidden)]
+pub use crate::redact::Redacted;
+pub use crate::redact::{clear_redaction_hook, set_redaction_hook};
+pub use crate::registry::HandleRegistry;
+pub use crate::result::IntoResult;
+#[doc(hidden)]
+pub use crate::runtime::block_on;
+pub use crate::runtime::{CurrentThread, FfiRuntime};
+#[doc(hidden)]
+pub use crate::selftest::self_test;
+#[doc(hidden)]
+pub use crate::stack::run_on_stack;
+#[doc(hidden)]
+pub use crate::stats::store_stats_json;
+pub use crate::stats::{fn_stats, fn_stats_json, FnStats};
+pub use crate::string::c_string_lossy;
+#[doc(hidden)]
+pub use crate::string::{fill_buffer, store_scratch};
+#[doc(hidden)]
+pub use crate::timeout::run_with_timeout;
+pub use crate::timeout::TimedOut;
+#[cfg(feature = "tokio")]
+pub use crate::tokio::{tokio_runtime, TokioRuntime};
+#[cfg(feature = "tokio")]
+#[doc(hidden)]
+pub use crate::tokio::{tokio_runtime_init, tokio_runtime_shutdown};
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use crate::tracing::{push_arg, Summarize, SummarizeAny, SummarizeDebug};
+#[cfg(feature = "manifest")]
+#[doc(hidden)]
+pub use ::inventory as __inventory;
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use ::tracing as __tracing;
+#[cfg(feature = "derive")]
+pub use easy_ffi_macros::FfiErrorCode;
 
 #[macro_export]
 macro_rules! easy_ffi {
-    ($name:ident => |$err:ident| $err_body:tt |$panic:ident| $panic_body:tt) => (
-        easy_ffi!(@actual ($) $name $err $err_body $panic $panic_body);
+    ($name:ident => $($rest:tt)*) => (
+        $crate::easy_ffi!(@new [$name] $($rest)*);
+    );
+    // Options are collected into a config, along with the handlers, and then
+    // used for either a wrapper macro (`[$name]`), an `easy_ffi_mod!` (`[]`),
+    // or a single function's `#[easy_ffi(...)]` overrides (`[@emit ...]`).
+    (@new $target:tt $($rest:tt)*) => (
+        $crate::easy_ffi!(@opts $target {
+            handlers: [default]
+            symbol: {
+                abi: ["C"] prefix: [] export: [true] check_ffi_types: [false]
+                improper_ctypes: [] manifest: [false]
+            }
+            errors: {
+                context: [] fallback: [] on_panic: ["handle"] assert_unwind_safe: [false]
+                last_error: [] poison: [false]
+            }
+            output: {
+                returns: ["value"] success: [0] true_value: [1] false_value: [0]
+                free_results: [false]
+            }
+            hooks: {
+                null_checks: [false] before: [] after: [] tracing: [false] stats: [false]
+                log_limit: [] panic_tests: [false]
+            }
+            run: {
+                timeout: [] stack_size: [] jobs: [false] async_callback: [false]
+                runtime: [] async_fn: [false] tokio_runtime: [false]
+            }
+            rust: { safe: [false] inner: [false] }
+        } $($rest)*);
+    );
+    // `runtime = tokio` is the runtime from `easy_ffi_runtime!`, rather than
+    // an expression.
+    (@opts $target:tt $cfg:tt runtime = tokio $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@opts $target $cfg tokio_runtime = true $(, $($rest)*)?);
+    );
+    // Each option is parsed as its own kind of fragment, then stored in the
+    // field of the same name by `@set`. Fields are kept in small groups so
+    // that finding one takes a few steps, rather than one per field, since
+    // every step counts towards the recursion limit.
+    (@opts $target:tt $cfg:tt abi = $abi:literal $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg symbol abi [$abi] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt prefix = $prefix:literal $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg symbol prefix [$prefix] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt export = $export:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg symbol export [$export] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt check_ffi_types = $check_ffi_types:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg symbol check_ffi_types [$check_ffi_types] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt improper_ctypes = $improper_ctypes:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg symbol improper_ctypes [$improper_ctypes] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt manifest = $manifest:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg symbol manifest [$manifest] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt context = $ctx:ident : $ctx_expr:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg errors context [$ctx $ctx_expr] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt fallback = $fallback:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg errors fallback [$fallback] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt on_panic = $on_panic:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg errors on_panic [$on_panic] $($($rest)*)?);
+    );
+    // A conversion for panics is kept in `on_panic`, since it replaces it.
+    (@opts $target:tt $cfg:tt panic_error = $panic_error:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg errors on_panic [["error" $panic_error]] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt assert_unwind_safe = $assert_unwind_safe:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg errors assert_unwind_safe [$assert_unwind_safe] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt last_error = $last_error:path $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg errors last_error [$last_error] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt poison = $poison:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg errors poison [$poison] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt returns = $returns:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg output returns [$returns] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt success = $success:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg output success [$success] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt true_value = $true_value:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg output true_value [$true_value] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt false_value = $false_value:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg output false_value [$false_value] $($($rest)*)?);
     );
-    (@actual ($dol:tt) $name:ident $err:ident $err_body:tt $panic:ident $panic_body:tt) => {
+    (@opts $target:tt $cfg:tt free_results = $free_results:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg output free_results [$free_results] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt null_checks = $null_checks:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg hooks null_checks [$null_checks] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt before = $before:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg hooks before [$before] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt after = $after:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg hooks after [$after] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt tracing = $tracing:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg hooks tracing [$tracing] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt stats = $stats:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg hooks stats [$stats] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt log_limit = $log_limit:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg hooks log_limit [$log_limit] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt panic_tests = $panic_tests:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg hooks panic_tests [$panic_tests] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt timeout = $timeout:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg run timeout [$timeout] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt stack_size = $stack_size:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg run stack_size [$stack_size] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt jobs = $jobs:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg run jobs [$jobs] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt async_callback = $async_callback:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg run async_callback [$async_callback] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt runtime = $runtime:expr $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg run runtime [$runtime] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt async_fn = $async_fn:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg run async_fn [$async_fn] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt tokio_runtime = $tokio_runtime:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg run tokio_runtime [$tokio_runtime] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt safe = $safe:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg rust safe [$safe] $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt inner = $inner:tt $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@set $target $cfg rust inner [$inner] $($($rest)*)?);
+    );
+    // Goes back to the default handling, e.g. for a single function.
+    (@opts $target:tt { handlers: $old:tt $($opts:tt)* } handlers = "default" $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@opts $target { handlers: [default] $($opts)* } $($($rest)*)?);
+    );
+    (@opts $target:tt { handlers: $old:tt $($opts:tt)* } handlers = "error_code" $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@opts $target { handlers: [error_code] $($opts)* } $($($rest)*)?);
+    );
+    (@opts $target:tt { handlers: $old:tt $($opts:tt)* } handlers = "error_chain" $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@opts $target { handlers: [error_chain] $($opts)* } $($($rest)*)?);
+    );
+    (@opts $target:tt { handlers: $old:tt $($opts:tt)* } handlers = "anyhow" $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@opts $target { handlers: [anyhow] $($opts)* } $($($rest)*)?);
+    );
+    (@opts $target:tt { handlers: $old:tt $($opts:tt)* } handlers = "eyre" $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@opts $target { handlers: [eyre] $($opts)* } $($($rest)*)?);
+    );
+    (@opts $target:tt { handlers: $old:tt $($opts:tt)* } handlers = "log" $(, $($rest:tt)*)?) => (
+        $crate::easy_ffi!(@opts $target { handlers: [log []] $($opts)* } $($($rest)*)?);
+    );
+    (
+        @opts $target:tt { handlers: [log $old:tt] $($opts:tt)* }
+        log_target = $log_target:literal $(, $($rest:tt)*)?
+    ) => (
+        $crate::easy_ffi!(@opts $target { handlers: [log [$log_target]] $($opts)* } $($($rest)*)?);
+    );
+    (@opts $target:tt $cfg:tt log_target = $($rest:tt)*) => (
+        compile_error!("`log_target` needs `handlers = \"log\"` before it");
+    );
+    (@opts $target:tt $cfg:tt $opt:ident = $($rest:tt)+) => (
+        compile_error!(concat!("unknown `easy_ffi!` option `", stringify!($opt), "`"));
+    );
+    (
+        @opts $target:tt { handlers: $old:tt $($opts:tt)* }
+        |$err:ident $(, $err_fn_name:ident $(, $err_args:ident)?)?| $err_body:tt
+        |$panic:ident $(, $panic_fn_name:ident $(, $panic_args:ident)?)?| $panic_body:tt
+        $($rest:tt)*
+    ) => (
+        $crate::easy_ffi!(@done $target {
+            handlers: [
+                |$err [$($err_fn_name)?] [$($($err_args)?)?]| $err_body
+                |$panic [$($panic_fn_name)?] [$($($panic_args)?)?]| $panic_body
+            ]
+            $($opts)*
+        } $($rest)*);
+    );
+    // A single handler gets an `FfiFailure` for either case.
+    (
+        @opts $target:tt { handlers: $old:tt $($opts:tt)* }
+        |$failure:ident $(, $failure_fn_name:ident $(, $failure_args:ident)?)?| $failure_body:tt
+        $($rest:tt)*
+    ) => (
+        $crate::easy_ffi!(@done $target {
+            handlers: [|$failure [$($failure_fn_name)?] [$($($failure_args)?)?]| $failure_body]
+            $($opts)*
+        } $($rest)*);
+    );
+    (@opts $target:tt $cfg:tt $($rest:tt)*) => (
+        $crate::easy_ffi!(@done $target $cfg $($rest)*);
+    );
+    // `@set` finds the option's group...
+    (
+        @set $target:tt { handlers: $handlers:tt symbol: { $($fields:tt)* } $($after:tt)* }
+        symbol $($set:tt)*
+    ) => (
+        $crate::easy_ffi!(@set_field $target [handlers: $handlers symbol:] [] { $($fields)* } [$($after)*] $($set)*);
+    );
+    (
+        @set $target:tt { handlers: $handlers:tt symbol: $symbol:tt errors: { $($fields:tt)* } $($after:tt)* }
+        errors $($set:tt)*
+    ) => (
+        $crate::easy_ffi!(@set_field $target [handlers: $handlers symbol: $symbol errors:] [] { $($fields)* } [$($after)*] $($set)*);
+    );
+    (
+        @set $target:tt { handlers: $handlers:tt symbol: $symbol:tt errors: $errors:tt output: { $($fields:tt)* } $($after:tt)* }
+        output $($set:tt)*
+    ) => (
+        $crate::easy_ffi!(@set_field $target [handlers: $handlers symbol: $symbol errors: $errors output:] [] { $($fields)* } [$($after)*] $($set)*);
+    );
+    (
+        @set $target:tt { handlers: $handlers:tt symbol: $symbol:tt errors: $errors:tt output: $output:tt hooks: { $($fields:tt)* } $($after:tt)* }
+        hooks $($set:tt)*
+    ) => (
+        $crate::easy_ffi!(@set_field $target [handlers: $handlers symbol: $symbol errors: $errors output: $output hooks:] [] { $($fields)* } [$($after)*] $($set)*);
+    );
+    (
+        @set $target:tt { handlers: $handlers:tt symbol: $symbol:tt errors: $errors:tt output: $output:tt hooks: $hooks:tt run: { $($fields:tt)* } $($after:tt)* }
+        run $($set:tt)*
+    ) => (
+        $crate::easy_ffi!(@set_field $target [handlers: $handlers symbol: $symbol errors: $errors output: $output hooks: $hooks run:] [] { $($fields)* } [$($after)*] $($set)*);
+    );
+    (
+        @set $target:tt { handlers: $handlers:tt symbol: $symbol:tt errors: $errors:tt output: $output:tt hooks: $hooks:tt run: $run:tt rust: { $($fields:tt)* } $($after:tt)* }
+        rust $($set:tt)*
+    ) => (
+        $crate::easy_ffi!(@set_field $target [handlers: $handlers symbol: $symbol errors: $errors output: $output hooks: $hooks run: $run rust:] [] { $($fields)* } [$($after)*] $($set)*);
+    );
+    // ...and then the field within it, replacing its value.
+    (@set_field $target:tt $before:tt $seen:tt { abi: $old:tt $($fields:tt)* } $after:tt abi $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen abi [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { prefix: $old:tt $($fields:tt)* } $after:tt prefix $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen prefix [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { export: $old:tt $($fields:tt)* } $after:tt export $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen export [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { check_ffi_types: $old:tt $($fields:tt)* } $after:tt check_ffi_types $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen check_ffi_types [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { improper_ctypes: $old:tt $($fields:tt)* } $after:tt improper_ctypes $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen improper_ctypes [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { manifest: $old:tt $($fields:tt)* } $after:tt manifest $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen manifest [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { context: $old:tt $($fields:tt)* } $after:tt context $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen context [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { fallback: $old:tt $($fields:tt)* } $after:tt fallback $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen fallback [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { on_panic: $old:tt $($fields:tt)* } $after:tt on_panic $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen on_panic [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { assert_unwind_safe: $old:tt $($fields:tt)* } $after:tt assert_unwind_safe $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen assert_unwind_safe [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { last_error: $old:tt $($fields:tt)* } $after:tt last_error $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen last_error [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { poison: $old:tt $($fields:tt)* } $after:tt poison $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen poison [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { returns: $old:tt $($fields:tt)* } $after:tt returns $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen returns [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { success: $old:tt $($fields:tt)* } $after:tt success $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen success [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { true_value: $old:tt $($fields:tt)* } $after:tt true_value $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen true_value [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { false_value: $old:tt $($fields:tt)* } $after:tt false_value $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen false_value [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { free_results: $old:tt $($fields:tt)* } $after:tt free_results $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen free_results [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { null_checks: $old:tt $($fields:tt)* } $after:tt null_checks $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen null_checks [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { before: $old:tt $($fields:tt)* } $after:tt before $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen before [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { after: $old:tt $($fields:tt)* } $after:tt after $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen after [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { tracing: $old:tt $($fields:tt)* } $after:tt tracing $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen tracing [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { stats: $old:tt $($fields:tt)* } $after:tt stats $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen stats [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { log_limit: $old:tt $($fields:tt)* } $after:tt log_limit $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen log_limit [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { panic_tests: $old:tt $($fields:tt)* } $after:tt panic_tests $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen panic_tests [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { timeout: $old:tt $($fields:tt)* } $after:tt timeout $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen timeout [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { stack_size: $old:tt $($fields:tt)* } $after:tt stack_size $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen stack_size [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { jobs: $old:tt $($fields:tt)* } $after:tt jobs $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen jobs [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { async_callback: $old:tt $($fields:tt)* } $after:tt async_callback $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen async_callback [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { runtime: $old:tt $($fields:tt)* } $after:tt runtime $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen runtime [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { async_fn: $old:tt $($fields:tt)* } $after:tt async_fn $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen async_fn [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { tokio_runtime: $old:tt $($fields:tt)* } $after:tt tokio_runtime $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen tokio_runtime [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { safe: $old:tt $($fields:tt)* } $after:tt safe $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen safe [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt $seen:tt { inner: $old:tt $($fields:tt)* } $after:tt inner $($set:tt)*) => (
+        $crate::easy_ffi!(@replace $target $before $seen inner [$($fields)*] $after $($set)*);
+    );
+    (@set_field $target:tt $before:tt [$($seen:tt)*] { $field:ident: $value:tt $($fields:tt)* } $($set:tt)*) => (
+        $crate::easy_ffi!(@set_field $target $before [$($seen)* $field: $value] { $($fields)* } $($set)*);
+    );
+    (
+        @replace $target:tt [$($before:tt)*] [$($seen:tt)*] $field:ident [$($fields:tt)*] [$($after:tt)*]
+        $value:tt $($rest:tt)*
+    ) => (
+        $crate::easy_ffi!(@opts $target {
+            $($before)* { $($seen)* $field: $value $($fields)* } $($after)*
+        } $($rest)*);
+    );
+    (@done [@emit $($state:tt)*] $cfg:tt) => (
+        $crate::__easy_ffi_fn!(@emit $cfg $($state)*);
+    );
+    (@done [$name:ident] $cfg:tt) => (
+        $crate::easy_ffi!(@actual ($) $name $cfg);
+    );
+    (
+        @done [] $cfg:tt
+        $(#[$attr:meta])*
+        $vis:vis mod $mod_name:ident { $($items:tt)* }
+    ) => (
+        $(#[$attr])*
+        $vis mod $mod_name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::__easy_ffi_fn! {
+                $cfg
+                $($items)*
+            }
+        }
+    );
+    (@actual ($dol:tt) $name:ident $cfg:tt) => {
         macro_rules! $name {
-            (
-                $dol (#[$dol attr:meta])*
-                fn $dol fn_name:ident (
-                    $dol ($dol arg:ident : $dol arg_ty:ty),* $dol (,)*
-                ) -> Result<$dol ok_ty:ty, $dol err_ty:ty>
-                $dol body:tt
-            ) => (
-                #[no_mangle]
-                $dol (#[$attr])*
-                pub extern "C" fn $fn_name($dol ($arg : $arg_ty),*) -> $ok_ty {
-                    let safe_res:
-                        ::std::result::Result<$ok_ty, ::std::result::Result<$err_ty, Box<::std::any::Any + Send + 'static>>> =
-                        ::std::panic::catch_unwind(move || $body)
-                            .map_err(|e| ::std::result::Result::Err(e))
-                            .and_then(|ok| ok.map_err(|e| ::std::result::Result::Ok(e)));
-                    match safe_res {
-                        Ok(x) => return x,
-                        Err(Ok($err)) => $err_body,
-                        Err(Err($panic)) => $panic_body,
-                    }
+            ($dol ($dol item:tt)*) => (
+                $crate::__easy_ffi_fn! {
+                    $cfg
+                    $dol ($dol item)*
                 }
             );
         }
     };
 }
 
-#[cfg(test)]
-mod tests {
-    #![allow(private_no_mangle_fns)]
+/// Like `easy_ffi!`, but for callbacks: functions handed to C as function
+/// pointers rather than exported.
+///
+/// A panic in a callback that C calls is just as much undefined behavior as
+/// one in an exported function, so callbacks need the same wrapping.
+/// `easy_ffi_callback!(name => ...)` takes the same options and handlers as
+/// `easy_ffi!`, and is the same as giving it `export = false`: the generated
+/// functions get no `#[no_mangle]` or `#[export_name]`, so they don't clash
+/// with other symbols, and can be private.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::os::raw::{c_int, c_void};
+///
+/// // From some C library: calls `f` for each item, stopping early if it
+/// // returns nonzero.
+/// unsafe extern "C" fn for_each_item(
+///     f: unsafe extern "C" fn(item: c_int, data: *mut c_void) -> c_int,
+///     data: *mut c_void,
+/// ) {
+///     for item in 1..10 {
+///         if f(item, data) != 0 {
+///             break;
+///         }
+///     }
+/// }
+///
+/// easy_ffi_callback!(my_callback =>
+///     |err| {
+///         println!("{}", err);
+///         1
+///     }
+///     |panic_val| { 1 }
+/// );
+///
+/// my_callback!(
+///     pub(self) unsafe fn add_item(item: c_int, data: *mut c_void) -> Result<c_int, &'static str> {
+///         let total = &mut *(data as *mut c_int);
+///         *total = total.checked_mul(item).ok_or("overflow")?;
+///         Ok(0)
+///     }
+/// );
+///
+/// let mut total: c_int = 1;
+/// unsafe { for_each_item(add_item, &mut total as *mut c_int as *mut c_void) };
+/// # assert_eq!(362880, total);
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_callback {
+    ($name:ident => $($rest:tt)*) => (
+        $crate::easy_ffi!(@new [$name] export = false, $($rest)*);
+    );
+}
 
-    easy_ffi!(my_ffi_fn =>
-        |err| {
-            println!("{}", err);
-            -1
+/// Wraps every function in a module with the same handlers.
+///
+/// This takes the same options and handlers as `easy_ffi!`, followed by a
+/// module of functions to wrap, which saves defining a wrapper macro that's
+/// only used once. The module gets a `use super::*;`, so the functions and
+/// handlers can refer to the same things they could outside of it.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// easy_ffi_mod!(
+///     |err| {
+///         println!("{}", err);
+///         -1
+///     }
+///     |panic_val| {
+///         -1
+///     }
+///     pub mod api {
+///         fn add(a: i32, b: i32) -> Result<i32, &'static str> {
+///             a.checked_add(b).ok_or("overflow")
+///         }
+///
+///         fn sub(a: i32, b: i32) -> Result<i32, &'static str> {
+///             a.checked_sub(b).ok_or("overflow")
+///         }
+///     }
+/// );
+/// # fn main() {
+/// #     assert_eq!(api::add(1, 2), 3);
+/// #     assert_eq!(api::sub(::std::i32::MIN, 1), -1);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_mod {
+    ($($rest:tt)+) => (
+        $crate::easy_ffi!(@new [] $($rest)+);
+    );
+}
+
+/// Generates tests checking that the listed wrapped functions don't unwind.
+///
+/// Each entry is a call to a wrapped function in the current module,
+/// followed by what its handlers should return when its body panics. The
+/// test makes the function panic before anything else it does, and checks
+/// that it returned that rather than unwinding into the test, so a handler
+/// that starts panicking itself, or returns the wrong thing, fails the
+/// test. The arguments only need to be valid enough to call it with. Only
+/// the functions listed are tested, but with easy_ffi's `manifest` feature
+/// there's also an `every_panic_test_is_listed` test, which fails if any
+/// function in the module wrapped with `panic_tests = true` isn't listed by
+/// one of its `easy_ffi_tests!`, so that new ones aren't left out.
+///
+/// The functions have to be wrapped with `panic_tests = true`, which
+/// compiles the panics into them for the crate's own tests, so the tests
+/// don't work from `tests/` or in builds with `panic = "abort"`.
+///
+/// The tests are in a `#[cfg(test)] mod easy_ffi_tests`, unless another
+/// name is given first with `mod name;`, and each is named after the
+/// function it calls, unless it's given a name of its own with `name:`,
+/// which it needs if the same function is listed twice.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// easy_ffi!(my_ffi_fn =>
+///     panic_tests = true,
+///     |err| {
+///         println!("{}", err);
+///         -1
+///     }
+///     |panic_val| {
+///         -2
+///     }
+/// );
+///
+/// my_ffi_fn!(
+///     fn add(a: i32, b: i32) -> Result<i32, &'static str> {
+///         a.checked_add(b).ok_or("overflow")
+///     }
+/// );
+///
+/// my_ffi_fn!(
+///     fn len(s: &str) -> Result<i32, String> {
+///         Ok(s.len() as i32)
+///     }
+/// );
+///
+/// easy_ffi_tests! {
+///     add(1, 2) => -2,
+///     add_overflowing: add(i32::MAX, 1) => -2,
+///     len(b"hi\0".as_ptr() as *const _) => -2,
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_tests {
+    (
+        @tests $mod_name:ident [$($tests:tt)*]
+        $test:ident : $fn_name:ident($($arg:expr),* $(,)?) => $expected:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::easy_ffi_tests!(@tests $mod_name [$($tests)* [$test $fn_name [$($arg),*] $expected]]
+            $($($rest)*)?
+        );
+    };
+    (
+        @tests $mod_name:ident [$($tests:tt)*]
+        $fn_name:ident($($arg:expr),* $(,)?) => $expected:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::easy_ffi_tests!(@tests $mod_name [$($tests)* [$fn_name $fn_name [$($arg),*] $expected]]
+            $($($rest)*)?
+        );
+    };
+    (@tests $mod_name:ident [$([$test:ident $fn_name:ident [$($arg:expr),*] $expected:expr])*]) => {
+        $(
+            #[cfg(test)]
+            $crate::__easy_ffi_panic_test!($fn_name true);
+        )*
+
+        #[cfg(test)]
+        mod $mod_name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::__easy_ffi_panic_test!(@check);
+
+            $(
+                #[test]
+                fn $test() {
+                    // The arguments can be as unsafe as calling it.
+                    #[allow(unused_unsafe, clippy::macro_metavars_in_unsafe)]
+                    let (ret, panicked) = $crate::with_injected_panic(stringify!($fn_name), || unsafe {
+                        super::$fn_name($($arg),*)
+                    });
+                    assert!(
+                        panicked,
+                        "`{}` returned without panicking, so it isn't wrapped with `panic_tests = true`",
+                        stringify!($fn_name),
+                    );
+                    assert_eq!($expected, ret);
+                }
+            )*
         }
-        |panic_val| {
-            match panic_val.downcast_ref::<&'static str>() {
-                Some(s) => println!("panic: {}", s),
-                None => println!("unknown panic!"),
-            };
-            -1
+    };
+    (@tests $mod_name:ident $tests:tt $($rest:tt)+) => {
+        compile_error!(concat!("expected `fn_name(args) => expected`, found `", stringify!($($rest)+), "`"));
+    };
+    (mod $mod_name:ident; $($rest:tt)*) => {
+        $crate::easy_ffi_tests!(@tests $mod_name [] $($rest)*);
+    };
+    ($($rest:tt)*) => {
+        $crate::easy_ffi_tests!(@tests easy_ffi_tests [] $($rest)*);
+    };
+}
+
+/// Calls wrapped functions with arguments from a fuzzer's input.
+///
+/// This takes the fuzzer's `&[u8]`, then calls to make, listing each
+/// function's argument types. Integers, floats and `bool`s are read from the
+/// input, as are slices of them, which are passed as a pointer and a length
+/// the way wrapped functions expect. See
+/// [`FuzzInput`](struct.FuzzInput.html) for how they're read, and
+/// [`FuzzArg`](trait.FuzzArg.html) for other types. The functions are
+/// called in order, each with arguments of its own, and the return values
+/// are thrown away. If one unwinds, which should never happen, this panics.
+///
+/// It's meant for `cargo-fuzz` targets:
+///
+/// ```text
+/// #![no_main]
+///
+/// libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+///     easy_ffi::easy_ffi_fuzz!(data => mylib::add(i32, i32), mylib::sum(&[i32]));
+/// });
+/// ```
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// easy_ffi!(my_ffi_fn =>);
+///
+/// my_ffi_fn!(
+///     fn add(a: i32, b: i32) -> Result<i32, &'static str> {
+///         a.checked_add(b).ok_or("overflow")
+///     }
+/// );
+///
+/// my_ffi_fn!(
+///     fn sum(nums: &[i32]) -> Result<i32, String> {
+///         let sum = nums.iter().try_fold(0i32, |sum, &n| sum.checked_add(n));
+///         sum.ok_or_else(|| "overflow".to_string())
+///     }
+/// );
+/// # fn main() {
+/// for data in [&[][..], &[1, 2, 3, 4, 5, 6, 7, 8], &[0xff; 64]] {
+///     easy_ffi_fuzz!(data => add(i32, i32), sum(&[i32]));
+/// }
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_fuzz {
+    ($data:expr => $($($fn_name:ident)::+ ($($arg:tt)*)),* $(,)?) => {{
+        let mut input = $crate::FuzzInput::new($data);
+        $(
+            $crate::easy_ffi_fuzz!(@call input [$($fn_name)::+] [] $($arg)*);
+        )*
+    }};
+    // A slice is passed as the pointer to its first element and its length.
+    (@call $input:ident $fn_name:tt [$($call:tt)*] & [$elem:ty] $(, $($rest:tt)*)?) => {{
+        let arg: Vec<$elem> = $input.slice();
+        $crate::easy_ffi_fuzz!(@call $input $fn_name [$($call)* arg.as_ptr(), arg.len(),] $($($rest)*)?);
+    }};
+    (@call $input:ident $fn_name:tt [$($call:tt)*] & mut [$elem:ty] $(, $($rest:tt)*)?) => {{
+        let mut arg: Vec<$elem> = $input.slice();
+        $crate::easy_ffi_fuzz!(@call $input $fn_name [$($call)* arg.as_mut_ptr(), arg.len(),] $($($rest)*)?);
+    }};
+    (@call $input:ident $fn_name:tt [$($call:tt)*] $ty:ty $(, $($rest:tt)*)?) => {{
+        let arg: $ty = $input.arg();
+        $crate::easy_ffi_fuzz!(@call $input $fn_name [$($call)* arg,] $($($rest)*)?);
+    }};
+    (@call $input:ident [$($fn_name:tt)*] [$($call:tt)*]) => {{
+        #[allow(unused_unsafe)]
+        let called = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| unsafe {
+            $($fn_name)*($($call)*)
+        }));
+        if called.is_err() {
+            panic!("`{}` unwound", stringify!($($fn_name)*));
         }
-    );
+    }};
+}
 
-    my_ffi_fn! (
-        /// Foo: do stuff
-        fn foo(i: i32) -> Result<i32, &'static str> {
-            match i {
-                5 => panic!("I'm afraid of 5's!"),
-                i if i <= 0 => Err("already <= 0, can't go lower"),
-                i => Ok(i-1),
-            }
+/// Creates thread-local storage for the last error, and functions for C
+/// callers to read it.
+///
+/// `easy_ffi_last_error!(mylib)` defines, in the current module:
+///
+/// * `mylib_last_error_message`, exported as an `extern "C" fn
+///   last_error_message() -> *const c_char`: the message of the last error
+///   on this thread, or null if there isn't one. The string is only valid
+///   until the next error is recorded on this thread.
+/// * `mylib_last_error_code` (`last_error_code() -> c_int`): its code, or `0`.
+/// * `mylib_last_error_length` (`last_error_length() -> c_int`): the length
+///   of its message in bytes, not counting the NUL, or `0`.
+/// * `mylib_last_error_backtrace` (`last_error_backtrace() -> *const
+///   c_char`): with the `backtrace` feature, its
+///   [`backtrace`](struct.LastError.html#method.backtrace), valid for as
+///   long as the message. Always null without it.
+/// * `mylib_last_error_function` (`last_error_function() -> *const
+///   c_char`): the name of the wrapped function it happened in, valid for as
+///   long as the message, or null if that isn't known. The built-in handlers
+///   record it.
+/// * `mylib_last_error_info` (`last_error_info() -> FfiErrorInfo`): all of
+///   the above in one [`FfiErrorInfo`](struct.FfiErrorInfo.html), for
+///   bindings that want a single structured error.
+/// * `mylib_last_error_severity` (`last_error_severity() -> c_int`): its
+///   [`Severity`](enum.Severity.html), or `0`.
+/// * `mylib_has_error` (`has_error() -> bool`): whether there's a last error
+///   on this thread.
+/// * `mylib_clear_last_error` (`clear_last_error()`): forgets it, so that
+///   callers can tell whether the next call failed. Handlers can call this
+///   too.
+/// * `set_last_error(LastError)`, for handlers to update it.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::LastError;
+///
+/// easy_ffi_last_error!(mylib);
+///
+/// easy_ffi!(my_ffi_fn =>
+///     |err| {
+///         set_last_error(LastError::from_error(-1, &err));
+///         -1
+///     }
+///     |panic_val| {
+///         set_last_error(LastError::from_panic(-2, &*panic_val));
+///         -2
+///     }
+/// );
+///
+/// my_ffi_fn!(
+///     fn add(a: i32, b: i32) -> Result<i32, &'static str> {
+///         a.checked_add(b).ok_or("overflow")
+///     }
+/// );
+/// # fn main() {
+/// #     assert_eq!(add(::std::i32::MAX, 1), -1);
+/// #     assert_eq!(last_error_code(), -1);
+/// #     assert_eq!(last_error_length(), 8);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_last_error {
+    ($prefix:ident) => {
+        thread_local! {
+            static EASY_FFI_LAST_ERROR: ::std::cell::RefCell<Option<$crate::LastError>> =
+                ::std::cell::RefCell::new(None);
         }
-    );
 
-    #[test]
-    fn it_works() {
-        assert_eq!(-1, foo(5));
-        assert_eq!(-1, foo(0));
-        assert_eq!(0, foo(1));
-        assert_eq!(1, foo(2));
+        /// Records `error` as this thread's last error.
+        #[allow(dead_code)]
+        pub fn set_last_error(error: $crate::LastError) {
+            // Neither of these can fail unless a handler is called while the
+            // thread is being torn down, and there's no one to tell then.
+            let _ = EASY_FFI_LAST_ERROR.try_with(|last| {
+                if let Ok(mut last) = last.try_borrow_mut() {
+                    *last = Some(error);
+                }
+            });
+        }
+
+        /// Forgets this thread's last error.
+        #[export_name = concat!(stringify!($prefix), "_clear_last_error")]
+        pub extern "C" fn clear_last_error() {
+            let _ = EASY_FFI_LAST_ERROR.try_with(|last| {
+                if let Ok(mut last) = last.try_borrow_mut() {
+                    *last = None;
+                }
+            });
+        }
+
+        /// The severity of the last error on this thread, or 0 if there isn't
+        /// one.
+        #[export_name = concat!(stringify!($prefix), "_last_error_severity")]
+        pub extern "C" fn last_error_severity() -> ::std::os::raw::c_int {
+            last_error_info().severity
+        }
+
+        /// The name of the function the last error on this thread happened
+        /// in, or null if there isn't one or it isn't known.
+        #[export_name = concat!(stringify!($prefix), "_last_error_function")]
+        pub extern "C" fn last_error_function() -> *const ::std::os::raw::c_char {
+            last_error_info().function
+        }
+
+        /// The code, message, function and backtrace of the last error on
+        /// this thread, valid until the next one.
+        #[export_name = concat!(stringify!($prefix), "_last_error_info")]
+        pub extern "C" fn last_error_info() -> $crate::FfiErrorInfo {
+            EASY_FFI_LAST_ERROR
+                .try_with(|last| match last.try_borrow() {
+                    Ok(ref last) => last.as_ref().map(|last| last.info()),
+                    Err(_) => None,
+                })
+                .ok()
+                .and_then(|info| info)
+                .unwrap_or_default()
+        }
+
+        /// Whether there's a last error on this thread.
+        #[export_name = concat!(stringify!($prefix), "_has_error")]
+        pub extern "C" fn has_error() -> bool {
+            EASY_FFI_LAST_ERROR
+                .try_with(|last| match last.try_borrow() {
+                    Ok(ref last) => last.is_some(),
+                    Err(_) => false,
+                })
+                .unwrap_or(false)
+        }
+
+        /// The message of the last error on this thread, or null if there
+        /// isn't one. It's only valid until the next error on this thread.
+        #[export_name = concat!(stringify!($prefix), "_last_error_message")]
+        pub extern "C" fn last_error_message() -> *const ::std::os::raw::c_char {
+            EASY_FFI_LAST_ERROR
+                .try_with(|last| match last.try_borrow() {
+                    Ok(ref last) => last.as_ref().map(|last| last.message().as_ptr()),
+                    Err(_) => None,
+                })
+                .ok()
+                .and_then(|ptr| ptr)
+                .unwrap_or(::std::ptr::null())
+        }
+
+        /// The code of the last error on this thread, or 0 if there isn't
+        /// one.
+        #[export_name = concat!(stringify!($prefix), "_last_error_code")]
+        pub extern "C" fn last_error_code() -> ::std::os::raw::c_int {
+            EASY_FFI_LAST_ERROR
+                .try_with(|last| match last.try_borrow() {
+                    Ok(ref last) => last.as_ref().map_or(0, |last| last.code()),
+                    Err(_) => 0,
+                })
+                .unwrap_or(0)
+        }
+
+        /// The length in bytes of the last error's message on this thread,
+        /// not counting the NUL, or 0 if there isn't one.
+        #[export_name = concat!(stringify!($prefix), "_last_error_length")]
+        pub extern "C" fn last_error_length() -> ::std::os::raw::c_int {
+            EASY_FFI_LAST_ERROR
+                .try_with(|last| match last.try_borrow() {
+                    Ok(ref last) => last.as_ref().map_or(0, |last| {
+                        last.message().to_bytes().len() as ::std::os::raw::c_int
+                    }),
+                    Err(_) => 0,
+                })
+                .unwrap_or(0)
+        }
+
+        /// The backtrace recorded with the last error on this thread, or
+        /// null if there isn't one.
+        #[export_name = concat!(stringify!($prefix), "_last_error_backtrace")]
+        pub extern "C" fn last_error_backtrace() -> *const ::std::os::raw::c_char {
+            EASY_FFI_LAST_ERROR
+                .try_with(|last| match last.try_borrow() {
+                    Ok(ref last) => last
+                        .as_ref()
+                        .and_then(|last| last.backtrace())
+                        .map(|backtrace| backtrace.as_ptr()),
+                    Err(_) => None,
+                })
+                .ok()
+                .and_then(|ptr| ptr)
+                .unwrap_or(::std::ptr::null())
+        }
+    };
+}
+
+/// Exports a `strerror`-style function describing error codes.
+///
+/// `easy_ffi_strerror!(mylib, MyError)` defines `mylib_strerror`, exported as
+/// an `extern "C" fn strerror(code: c_int) -> *const c_char`. It returns
+/// `MyError`'s [`code_description`](trait.FfiErrorCode.html#method.code_description)
+/// for `code`, or "unknown error" if it doesn't have one. The strings are
+/// static, so callers must not free them.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::ffi::CStr;
+/// use std::os::raw::c_int;
+///
+/// use easy_ffi::FfiErrorCode;
+///
+/// struct NotFound;
+///
+/// impl FfiErrorCode for NotFound {
+///     fn error_code(&self) -> c_int {
+///         -404
+///     }
+///
+///     fn code_description(code: c_int) -> Option<&'static CStr> {
+///         match code {
+///             -404 => CStr::from_bytes_with_nul(b"not found\0").ok(),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// easy_ffi_strerror!(mylib, NotFound);
+/// # fn main() {
+/// #     let description = unsafe { CStr::from_ptr(strerror(-404)) };
+/// #     assert_eq!(description.to_str(), Ok("not found"));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_strerror {
+    ($prefix:ident, $err_ty:ty) => {
+        /// A static description of `code`.
+        #[export_name = concat!(stringify!($prefix), "_strerror")]
+        pub extern "C" fn strerror(code: ::std::os::raw::c_int) -> *const ::std::os::raw::c_char {
+            match <$err_ty as $crate::FfiErrorCode>::code_description(code) {
+                Some(description) => description.as_ptr(),
+                None => "unknown error\0".as_ptr() as *const ::std::os::raw::c_char,
+            }
+        }
+    };
+}
+
+/// Exports a function describing the library as JSON, with the `manifest`
+/// feature.
+///
+/// `easy_ffi_api_json!(mylib)` defines `mylib_api_json`, exported as an
+/// `extern "C" fn api_json() -> *const c_char`, which returns
+/// [`api_json`](fn.api_json.html): every function wrapped with `manifest =
+/// true`, for bindings that set themselves up from the library they've
+/// loaded. The string is made by the first call, and is valid from then on,
+/// so callers must not free it.
+///
+/// `easy_ffi_api_json!(mylib, MyError, codes)` also lists the error codes
+/// in `codes`, e.g. `-100..=-1`, that `MyError` has a
+/// [`code_description`](trait.FfiErrorCode.html#method.code_description)
+/// for.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::ffi::CStr;
+/// use std::os::raw::c_int;
+///
+/// use easy_ffi::FfiErrorCode;
+///
+/// struct Overflow;
+///
+/// impl FfiErrorCode for Overflow {
+///     fn error_code(&self) -> c_int {
+///         -1
+///     }
+///
+///     fn code_description(code: c_int) -> Option<&'static CStr> {
+///         match code {
+///             -1 => CStr::from_bytes_with_nul(b"overflow\0").ok(),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// easy_ffi!(my_ffi_fn =>
+///     manifest = true,
+///     |err| { err.error_code() }
+///     |panic_val| { -2 }
+/// );
+///
+/// my_ffi_fn!(
+///     fn add(a: i32, b: i32) -> Result<i32, Overflow> {
+///         a.checked_add(b).ok_or(Overflow)
+///     }
+/// );
+///
+/// easy_ffi_api_json!(mylib, Overflow, -10..=-1);
+/// # fn main() {
+/// let json = unsafe { CStr::from_ptr(api_json()) }.to_str().unwrap();
+/// assert!(json.contains(r#"{"symbol":"add","name":"add","params":[{"name":"a","type":"i32"}"#));
+/// assert!(json.ends_with(r#""error_codes":[{"code":-1,"description":"overflow"}]}"#));
+/// # }
+/// ```
+#[cfg(feature = "manifest")]
+#[macro_export]
+macro_rules! easy_ffi_api_json {
+    (@export $prefix:ident $codes:expr) => {
+        /// Returns the library's exports as JSON, which mustn't be freed.
+        #[export_name = concat!(stringify!($prefix), "_api_json")]
+        pub extern "C" fn api_json() -> *const ::std::os::raw::c_char {
+            static API_JSON: ::std::sync::OnceLock<::std::ffi::CString> = ::std::sync::OnceLock::new();
+            API_JSON
+                .get_or_init(|| {
+                    let codes: ::std::vec::Vec<(::std::os::raw::c_int, &str)> = $codes;
+                    $crate::c_string_lossy(&$crate::api_json(&codes))
+                })
+                .as_ptr()
+        }
+    };
+    ($prefix:ident) => {
+        $crate::easy_ffi_api_json!(@export $prefix ::std::vec::Vec::new());
+    };
+    ($prefix:ident, $err_ty:ty, $codes:expr) => {
+        $crate::easy_ffi_api_json!(@export $prefix $crate::error_code_descriptions::<$err_ty>($codes));
+    };
+}
+
+/// Exports a function that frees a `Box` handed out as a raw pointer.
+///
+/// `easy_ffi_destructor!(widget_free, Widget)` defines
+/// `unsafe extern "C" fn widget_free(ptr: *mut Widget)`, which does nothing if
+/// `ptr` is null, and otherwise turns it back into a `Box<Widget>` and drops
+/// it. A panic while dropping is printed to stderr rather than unwinding into
+/// C. Any attributes, such as doc comments, are passed on to the function.
+///
+/// With `double_free_guard = true`, debug builds also remember what's been
+/// freed, and abort with a message if the same pointer is freed twice. Since
+/// freed addresses get reused, this only works for pointers that come from
+/// functions generated by `easy_ffi!`, e.g. ones returning `Result<Box<T>, E>`.
+/// To pass double frees to the error handler instead, write the destructor as
+/// a wrapped function calling [`free_boxed`](fn.free_boxed.html).
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// pub struct Widget {
+///     size: i32,
+/// }
+///
+/// easy_ffi!(my_ffi_fn =>);
+///
+/// my_ffi_fn!(
+///     fn widget_new(size: i32) -> Result<Box<Widget>, &'static str> {
+///         Ok(Box::new(Widget { size }))
+///     }
+/// );
+///
+/// easy_ffi_destructor!(
+///     /// Frees a widget from `widget_new`.
+///     widget_free, Widget, double_free_guard = true
+/// );
+/// # fn main() {
+/// #     unsafe { widget_free(widget_new(1)) };
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_destructor {
+    (
+        $(#[$attr:meta])*
+        $name:ident, $ty:ty $(, double_free_guard = $guard:expr)? $(,)?
+    ) => {
+        $(#[$attr])*
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(ptr: *mut $ty) {
+            $crate::drop_boxed(stringify!($name), ptr, false $(|| $guard)?);
+        }
+    };
+}
+
+/// Exports a function that lists the objects C hasn't freed yet.
+///
+/// This needs the `live_objects` feature, which keeps track of every pointer
+/// handed out by functions generated by this crate: those returning
+/// `Result<Box<T>, E>`, `Result<Option<Box<T>>, E>` or `Result<String, E>`,
+/// and the `new` function from
+/// [`easy_ffi_handle!`](macro.easy_ffi_handle.html). Pointers freed by
+/// [`easy_ffi_destructor!`](macro.easy_ffi_destructor.html), an
+/// `easy_ffi_handle!` destructor, [`free_boxed`](fn.free_boxed.html) or
+/// [`easy_ffi_string_free!`](macro.easy_ffi_string_free.html) are forgotten
+/// again.
+///
+/// `easy_ffi_live_objects!(mylib)` then exports `mylib_dump_live_objects`,
+/// an `extern "C" fn() -> c_int` printing each live object's address, type
+/// and the function that made it to stderr, and returning how many there are.
+/// Calling it as C shuts down makes a cheap leak check.
+///
+/// ```text
+/// live object 0x55d0c3a4e9d0: mylib::Widget from widget_new
+/// ```
+#[cfg(feature = "live_objects")]
+#[macro_export]
+macro_rules! easy_ffi_live_objects {
+    ($prefix:ident) => {
+        /// Prints the objects that haven't been freed yet to stderr, returning
+        /// how many there are.
+        #[export_name = concat!(stringify!($prefix), "_dump_live_objects")]
+        pub extern "C" fn dump_live_objects() -> ::std::os::raw::c_int {
+            $crate::dump_live_objects() as ::std::os::raw::c_int
+        }
+    };
+}
+
+/// Exports a function that frees strings returned by wrapped functions.
+///
+/// `easy_ffi_string_free!(mylib)` defines `mylib_string_free`, exported as an
+/// `unsafe extern "C" fn string_free(s: *mut c_char)`. It does nothing if `s`
+/// is null. As with a destructor made with
+/// [`easy_ffi_destructor!`](macro.easy_ffi_destructor.html) and
+/// `double_free_guard = true`, debug builds abort if a string is freed twice.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::io;
+///
+/// easy_ffi!(my_ffi_fn =>);
+///
+/// my_ffi_fn!(
+///     fn greeting(excited: bool) -> Result<String, io::Error> {
+///         Ok(if excited { "hello!" } else { "hello" }.to_string())
+///     }
+/// );
+///
+/// easy_ffi_string_free!(mylib);
+/// # fn main() {
+/// #     let s = greeting(true);
+/// #     assert_eq!(unsafe { std::ffi::CStr::from_ptr(s) }.to_str(), Ok("hello!"));
+/// #     unsafe { string_free(s) };
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_string_free {
+    ($prefix:ident) => {
+        /// Frees a string returned by this library. Does nothing if it's null.
+        #[export_name = concat!(stringify!($prefix), "_string_free")]
+        pub unsafe extern "C" fn string_free(s: *mut ::std::os::raw::c_char) {
+            $crate::free_string(concat!(stringify!($prefix), "_string_free"), s);
+        }
+    };
+}
+
+/// Creates a flag for whether the library's been initialized, and an `init`
+/// function for C callers to set it.
+///
+/// `easy_ffi_init!(mylib)` defines, in the current module:
+///
+/// * `mylib_init`, exported as an `extern "C" fn init() -> c_int`, which
+///   marks the library as initialized and returns `0`. Calling it again does
+///   nothing.
+/// * `set_initialized()`, which does the same from Rust, e.g. for another
+///   wrapped function that sets the library up some other way.
+/// * `is_initialized() -> bool`.
+/// * `check_initialized(fn_name: &'static str) -> Result<(), NotInitialized>`,
+///   which gives a [`NotInitialized`](struct.NotInitialized.html) error if
+///   `init` hasn't been called yet. As the `before` hook of a wrapper macro,
+///   this rejects calls to its functions before `init`, without running
+///   their bodies.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// easy_ffi_init!(mylib);
+///
+/// easy_ffi!(my_ffi_fn =>
+///     before = check_initialized,
+///     |err| {
+///         println!("{}", err);
+///         -1
+///     }
+///     |panic_val| { -1 }
+/// );
+///
+/// my_ffi_fn!(
+///     fn answer() -> Result<i32, String> {
+///         Ok(42)
+///     }
+/// );
+/// # fn main() {
+/// #     assert_eq!(-1, answer());
+/// #     assert_eq!(0, init());
+/// #     assert_eq!(42, answer());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_init {
+    ($prefix:ident) => {
+        static EASY_FFI_INITIALIZED: ::std::sync::atomic::AtomicBool =
+            ::std::sync::atomic::AtomicBool::new(false);
+
+        /// Marks the library as initialized.
+        #[allow(dead_code)]
+        pub fn set_initialized() {
+            EASY_FFI_INITIALIZED.store(true, ::std::sync::atomic::Ordering::Release);
+        }
+
+        /// Whether the library's been initialized.
+        #[allow(dead_code)]
+        pub fn is_initialized() -> bool {
+            EASY_FFI_INITIALIZED.load(::std::sync::atomic::Ordering::Acquire)
+        }
+
+        /// Fails if the library hasn't been initialized, for a `before` hook.
+        #[allow(dead_code)]
+        pub fn check_initialized(
+            fn_name: &'static str,
+        ) -> ::std::result::Result<(), $crate::NotInitialized> {
+            if is_initialized() {
+                Ok(())
+            } else {
+                Err($crate::NotInitialized { fn_name })
+            }
+        }
+
+        /// Initializes the library. Returns 0.
+        #[export_name = concat!(stringify!($prefix), "_init")]
+        pub extern "C" fn init() -> ::std::os::raw::c_int {
+            set_initialized();
+            0
+        }
+    };
+}
+
+/// Creates functions for C callers to check for and clear poisoning by a
+/// panic in a function wrapped with `poison = true`.
+///
+/// `easy_ffi_poison!(mylib)` defines, in the current module:
+///
+/// * `mylib_is_poisoned`, exported as an `extern "C" fn is_poisoned() ->
+///   c_int`, which returns `1` if the library's poisoned, or else `0`.
+/// * `mylib_reset_poison`, exported as an `extern "C" fn reset_poison()`,
+///   which lets the wrapped functions run again. It's up to the caller to
+///   have dealt with whatever state the panic left behind first.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// easy_ffi_poison!(mylib);
+///
+/// easy_ffi!(my_ffi_fn =>
+///     poison = true,
+///     |err| {
+///         println!("{}", err);
+///         -1
+///     }
+///     |panic_val| { -2 }
+/// );
+///
+/// my_ffi_fn!(
+///     fn check(n: i32) -> Result<i32, String> {
+///         assert!(n >= 0);
+///         Ok(n)
+///     }
+/// );
+/// # fn main() {
+/// #     assert_eq!(1, check(1));
+/// #     assert_eq!(-2, check(-1));
+/// #     assert_eq!(1, is_poisoned());
+/// #     assert_eq!(-1, check(1));
+/// #     reset_poison();
+/// #     assert_eq!(0, is_poisoned());
+/// #     assert_eq!(1, check(1));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_poison {
+    ($prefix:ident) => {
+        /// Returns 1 if a panic has poisoned the library, or else 0.
+        #[export_name = concat!(stringify!($prefix), "_is_poisoned")]
+        pub extern "C" fn is_poisoned() -> ::std::os::raw::c_int {
+            $crate::is_poisoned() as ::std::os::raw::c_int
+        }
+
+        /// Lets functions run again after a panic poisoned the library.
+        #[export_name = concat!(stringify!($prefix), "_reset_poison")]
+        pub extern "C" fn reset_poison() {
+            $crate::reset_poison()
+        }
+    };
+}
+
+/// Exports a function for C to register a callback for errors with.
+///
+/// `easy_ffi_error_callback!(mylib)` defines, in the current module:
+///
+/// * `mylib_set_error_callback`, exported as an `extern "C" fn
+///   set_error_callback(callback: Option<extern "C" fn(c_int, *const c_char,
+///   *mut c_void)>, user_data: *mut c_void)`, which calls
+///   [`set_error_callback`](fn.set_error_callback.html). From then on, the
+///   built-in handlers, including the default ones, call `callback` with each
+///   failure's code and message, which is only valid during the call, and
+///   `user_data`. A null `callback` unregisters it.
+/// * `mylib_set_thread_error_callback`, exported as an `extern "C" fn
+///   set_thread_error_callback` taking the same arguments, which calls
+///   [`set_thread_error_callback`](fn.set_thread_error_callback.html) to
+///   register a callback for the calling thread only. Multi-threaded hosts
+///   can use it to send each worker's errors somewhere different. A null
+///   `callback` goes back to the one for every thread.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::ffi::CStr;
+/// use std::os::raw::{c_char, c_int, c_void};
+///
+/// easy_ffi_error_callback!(mylib);
+///
+/// easy_ffi!(my_ffi_fn =>);
+///
+/// my_ffi_fn!(
+///     fn check(n: i32) -> Result<i32, String> {
+///         if n < 0 {
+///             return Err(format!("{} is negative", n));
+///         }
+///         Ok(n)
+///     }
+/// );
+///
+/// extern "C" fn on_error(code: c_int, message: *const c_char, user_data: *mut c_void) {
+///     let message = unsafe { CStr::from_ptr(message) };
+///     let errors = unsafe { &mut *(user_data as *mut Vec<String>) };
+///     errors.push(format!("{}: {}", code, message.to_str().unwrap()));
+/// }
+///
+/// # fn main() {
+/// let mut errors = Vec::<String>::new();
+/// set_error_callback(Some(on_error), &mut errors as *mut Vec<String> as *mut c_void);
+/// assert_eq!(-1, check(-1));
+/// set_error_callback(None, std::ptr::null_mut());
+/// assert_eq!(-1, check(-2));
+/// assert_eq!(vec!["-1: \"-1 is negative\""], errors);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_error_callback {
+    ($prefix:ident) => {
+        /// Registers a callback to be called with each error's code and
+        /// message, and `user_data`. Null unregisters it.
+        #[export_name = concat!(stringify!($prefix), "_set_error_callback")]
+        pub extern "C" fn set_error_callback(
+            callback: ::std::option::Option<$crate::ErrorCallback>,
+            user_data: *mut ::std::os::raw::c_void,
+        ) {
+            $crate::set_error_callback(callback, user_data)
+        }
+
+        /// Registers a callback to be called with each error on this thread
+        /// in place of the one for every thread. Null unregisters it.
+        #[export_name = concat!(stringify!($prefix), "_set_thread_error_callback")]
+        pub extern "C" fn set_thread_error_callback(
+            callback: ::std::option::Option<$crate::ErrorCallback>,
+            user_data: *mut ::std::os::raw::c_void,
+        ) {
+            $crate::set_thread_error_callback(callback, user_data)
+        }
+    };
+}
+
+/// Exports a function reporting the calls, errors and panics counted by
+/// functions wrapped with `stats = true`.
+///
+/// `easy_ffi_stats!(mylib)` defines `mylib_stats_json`, exported as an
+/// `extern "C" fn stats_json() -> *const c_char`, which returns
+/// [`fn_stats_json`](fn.fn_stats_json.html), e.g.
+/// `[{"name":"parse","calls":3,"errors":1,"panics":0}]`. The string is only
+/// valid until the next call to it on the same thread, and mustn't be
+/// freed. It's kept apart from strings returned with `returns = "scratch"`,
+/// so calling either doesn't affect the other's.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::ffi::CStr;
+///
+/// easy_ffi_stats!(mylib);
+///
+/// easy_ffi!(my_ffi_fn => stats = true);
+///
+/// my_ffi_fn!(
+///     fn parse(i: i32) -> Result<i32, String> {
+///         if i < 0 {
+///             return Err("negative".into());
+///         }
+///         Ok(i)
+///     }
+/// );
+/// # fn main() {
+/// assert_eq!(1, parse(1));
+/// assert_eq!(-1, parse(-1));
+/// let stats = unsafe { CStr::from_ptr(stats_json()) };
+/// assert_eq!(
+///     r#"[{"name":"parse","calls":2,"errors":1,"panics":0}]"#,
+///     stats.to_str().unwrap()
+/// );
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_stats {
+    ($prefix:ident) => {
+        /// Returns the counts for each function as JSON, valid until the
+        /// next call on this thread.
+        #[export_name = concat!(stringify!($prefix), "_stats_json")]
+        pub extern "C" fn stats_json() -> *const ::std::os::raw::c_char {
+            $crate::store_stats_json()
+        }
+    };
+}
+
+/// Exports a self-test, for checking that a built library works at all.
+///
+/// `easy_ffi_selftest!(mylib)` defines `mylib_selftest`, exported as an
+/// `extern "C" fn selftest() -> c_int`, which returns 0 if panics are
+/// caught in the build of the library it's in, and 1 if it was built with
+/// `panic = "abort"`. It's what
+/// [`selftest::check_library`](selftest/fn.check_library.html) calls once
+/// it's checked the library's symbols.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// easy_ffi_selftest!(mylib);
+/// # fn main() {
+/// assert_eq!(0, selftest());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_selftest {
+    ($prefix:ident) => {
+        /// Returns 0 if panics are caught in this build of the library.
+        #[export_name = concat!(stringify!($prefix), "_selftest")]
+        pub extern "C" fn selftest() -> ::std::os::raw::c_int {
+            $crate::self_test()
+        }
+    };
+}
+
+/// Exports functions for C to make, cancel and free
+/// [`FfiCancelToken`](struct.FfiCancelToken.html)s.
+///
+/// `easy_ffi_cancel_token!(mylib)` defines:
+///
+/// * `mylib_token_new`, exported as an `extern "C" fn token_new() -> *mut
+///   FfiCancelToken`, which makes a token that hasn't been cancelled.
+/// * `mylib_token_cancel`, exported as an `unsafe extern "C" fn
+///   token_cancel(token: *const FfiCancelToken)`, which cancels it, and can
+///   be called from any thread while a wrapped function uses it.
+/// * `mylib_token_free`, exported as an `unsafe extern "C" fn
+///   token_free(token: *mut FfiCancelToken)`, which frees it once nothing's
+///   using it. Debug builds abort if a token is freed twice.
+///
+/// The last two do nothing if `token` is null.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::FfiCancelToken;
+///
+/// easy_ffi_cancel_token!(mylib);
+///
+/// easy_ffi!(my_ffi_fn =>);
+///
+/// my_ffi_fn!(
+///     fn checked(cancel: &FfiCancelToken) -> Result<i32, String> {
+///         cancel.check()?;
+///         Ok(1)
+///     }
+/// );
+/// # fn main() {
+/// let token = token_new();
+/// unsafe {
+///     assert_eq!(1, checked(token));
+///     token_cancel(token);
+///     assert_eq!(-1, checked(token));
+///     token_free(token);
+/// }
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_cancel_token {
+    ($prefix:ident) => {
+        /// Makes a token that hasn't been cancelled.
+        #[export_name = concat!(stringify!($prefix), "_token_new")]
+        pub extern "C" fn token_new() -> *mut $crate::FfiCancelToken {
+            let token = Box::into_raw(Box::new($crate::FfiCancelToken::new()));
+            $crate::mark_allocated(concat!(stringify!($prefix), "_token_new"), token);
+            token
+        }
+
+        /// Cancels the token, unless it's null.
+        ///
+        /// # Safety
+        ///
+        /// `token` must be null or come from `token_new`, and not yet be
+        /// freed.
+        #[export_name = concat!(stringify!($prefix), "_token_cancel")]
+        pub unsafe extern "C" fn token_cancel(token: *const $crate::FfiCancelToken) {
+            if let Some(token) = token.as_ref() {
+                token.cancel();
+            }
+        }
+
+        /// Frees the token, unless it's null.
+        ///
+        /// # Safety
+        ///
+        /// `token` must be null or come from `token_new`, and mustn't be used
+        /// again afterwards.
+        #[export_name = concat!(stringify!($prefix), "_token_free")]
+        pub unsafe extern "C" fn token_free(token: *mut $crate::FfiCancelToken) {
+            $crate::drop_boxed(concat!(stringify!($prefix), "_token_free"), token, true);
+        }
+    };
+}
+
+/// Exports functions starting and stopping a runtime for wrapped `async
+/// fn`s to run on.
+///
+/// With the `tokio` feature, `easy_ffi_runtime!(tokio, mylib)` defines
+/// `mylib_runtime_init`, exported as an `extern "C" fn runtime_init() ->
+/// c_int`, which starts a multi-threaded tokio runtime, unless there's
+/// already one, and returns 0, or -1 if it couldn't. `mylib_runtime_shutdown`
+/// is exported as an `extern "C" fn runtime_shutdown()`, which shuts it down
+/// without waiting for its tasks.
+///
+/// Wrapped `async fn`s with `runtime = tokio` run on it, and go to the error
+/// handler with a [`NotInitialized`](struct.NotInitialized.html) error,
+/// converted to their error type with `From`, when it isn't running.
+/// [`tokio_runtime`](fn.tokio_runtime.html) gives a handle to it for other
+/// code.
+///
+/// ```text
+/// easy_ffi_runtime!(tokio, mylib);
+///
+/// easy_ffi!(my_ffi_fn => runtime = tokio);
+///
+/// my_ffi_fn!(
+///     async fn fetch(id: u32) -> Result<u32, String> {
+///         tokio::task::spawn(lookup(id)).await.map_err(|err| err.to_string())
+///     }
+/// );
+/// ```
+#[cfg(feature = "tokio")]
+#[macro_export]
+macro_rules! easy_ffi_runtime {
+    (tokio, $prefix:ident) => {
+        /// Starts the runtime, returning 0 if it's running afterwards.
+        #[export_name = concat!(stringify!($prefix), "_runtime_init")]
+        pub extern "C" fn runtime_init() -> ::std::os::raw::c_int {
+            $crate::tokio_runtime_init(concat!(stringify!($prefix), "_runtime_init"))
+        }
+
+        /// Shuts the runtime down, without waiting for its tasks.
+        #[export_name = concat!(stringify!($prefix), "_runtime_shutdown")]
+        pub extern "C" fn runtime_shutdown() {
+            $crate::tokio_runtime_shutdown(concat!(stringify!($prefix), "_runtime_shutdown"))
+        }
+    };
+    ($runtime:ident, $prefix:ident) => {
+        compile_error!(concat!(
+            "`easy_ffi_runtime!` only supports tokio, not ",
+            stringify!($runtime)
+        ));
+    };
+}
+
+/// Installs a panic hook that records each panic's message, location and
+/// backtrace for the thread it happened on.
+///
+/// A panic's payload is usually just its message, and not always that. With
+/// the hook installed, the panic handler can call
+/// [`take_panic_report`](fn.take_panic_report.html) for a
+/// [`PanicReport`](struct.PanicReport.html) of the panic it was given, and
+/// both the default handler and
+/// [`LastError::from_panic`](struct.LastError.html#method.from_panic) use
+/// it, so C callers see where the panic happened rather than just "panic".
+///
+/// The hook calls whichever hook was installed before it, so panics are
+/// still printed to stderr unless that's been turned off. Installing it
+/// more than once does nothing, so it's fine to do in an `init` function
+/// that may be called again, and it should be installed before any other
+/// hook is that would take its place.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// easy_ffi!(my_ffi_fn =>
+///     |err| {
+///         println!("{}", err);
+///         -1
+///     }
+///     |panic_val| {
+///         if let Some(report) = easy_ffi::take_panic_report() {
+///             // e.g. for a C logging callback taking a file and line.
+///             if let Some(location) = report.location() {
+///                 println!("panic at {} line {}", location.file(), location.line());
+///             }
+///             println!("{}", report.backtrace());
+///         }
+///         -2
+///     }
+/// );
+///
+/// my_ffi_fn!(
+///     fn check(n: i32) -> Result<i32, String> {
+///         assert!(n >= 0, "negative");
+///         Ok(n)
+///     }
+/// );
+/// # easy_ffi_last_error!(mylib);
+/// # easy_ffi!(recorded_ffi_fn => last_error = set_last_error);
+/// # recorded_ffi_fn!(
+/// #     fn recorded() -> Result<i32, String> {
+/// #         panic!("oh no")
+/// #     }
+/// # );
+/// # easy_ffi!(threaded_ffi_fn =>
+/// #     timeout = std::time::Duration::from_secs(10),
+/// #     stack_size = 1 << 20,
+/// #     |err| { -1 }
+/// #     |panic_val| {
+/// #         match easy_ffi::take_panic_report() {
+/// #             Some(report) if report.message() == Some("reported") => 1,
+/// #             Some(_) => 2,
+/// #             None => 0,
+/// #         }
+/// #     }
+/// # );
+/// # threaded_ffi_fn!(
+/// #     fn threaded(quiet: bool) -> Result<i32, String> {
+/// #         if quiet {
+/// #             // Doesn't go through the hook.
+/// #             std::panic::resume_unwind(Box::new("quiet"));
+/// #         }
+/// #         panic!("reported")
+/// #     }
+/// # );
+/// # fn main() {
+/// easy_ffi_panic_hook!();
+/// #     assert_eq!(-2, check(-1));
+/// #     assert!(easy_ffi::take_panic_report().is_none());
+/// #     let _ = std::panic::catch_unwind(|| panic!("again"));
+/// #     let report = easy_ffi::take_panic_report().unwrap();
+/// #     assert_eq!(Some("again"), report.message());
+/// #     let location = report.location().unwrap();
+/// #     assert_eq!("src/lib.rs", location.file());
+/// #     assert!(location.to_string().starts_with("src/lib.rs:"));
+/// #     assert_eq!(-1, recorded());
+/// #     let message = unsafe { std::ffi::CStr::from_ptr(last_error_message()) };
+/// #     assert!(message.to_str().unwrap().starts_with("oh no at src/lib.rs:"));
+/// #     assert_eq!(1, threaded(false));
+/// #     let _ = std::panic::catch_unwind(|| panic!("stale"));
+/// #     assert_eq!(0, threaded(true));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_panic_hook {
+    () => {
+        $crate::install_panic_hook()
+    };
+}
+
+/// Creates matching `init` and `shutdown` functions for C callers, which set
+/// up and tear down the library's global state.
+///
+/// ```text
+/// easy_ffi_lifecycle!(
+///     mylib(Config) -> State;
+///     init = |config: &Config| -> Result<State, E> { ... },
+///     shutdown = |state: State| { ... },
+/// );
+/// ```
+///
+/// defines, in the current module:
+///
+/// * `mylib_init`, exported as an `extern "C" fn init(config: *const Config)
+///   -> c_int`, which calls the `init` closure and keeps the state it
+///   returns. It returns `0`, or `-1` if `config` is null or the closure
+///   fails or panics, which is printed to stderr. If there's state already,
+///   it returns `0` without calling the closure again.
+/// * `mylib_shutdown`, exported as an `extern "C" fn shutdown()`, which
+///   passes the state to the `shutdown` closure, so `init` can be called
+///   again afterwards. A panic is printed to stderr. If there's no state, it
+///   does nothing. Without a `shutdown` closure, the state is just dropped.
+/// * `with_state(fn_name: &'static str, f: impl FnOnce(&mut State) -> R) ->
+///   Result<R, NotInitialized>`, which calls `f` with the state, for wrapped
+///   functions to use. `fn_name` is for the
+///   [`NotInitialized`](struct.NotInitialized.html) error if there isn't any.
+/// * `is_initialized() -> bool` and `check_initialized(fn_name: &'static str)
+///   -> Result<(), NotInitialized>`, like
+///   [`easy_ffi_init!`](macro.easy_ffi_init.html)'s.
+///
+/// The state is kept behind a lock, so `State` needs to be `Send`. The lock
+/// is held while `f` and the `init` and `shutdown` closures run, so that two
+/// threads can't set the state up at once, and none of them may call any of
+/// these functions, or a wrapped function that does, e.g. through a
+/// `before = check_initialized` hook. That would deadlock, since the lock
+/// isn't reentrant.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// #[repr(C)]
+/// pub struct Config {
+///     pub start: i32,
+/// }
+///
+/// pub struct Counter {
+///     next: i32,
+/// }
+///
+/// easy_ffi_lifecycle!(
+///     mylib(Config) -> Counter;
+///     init = |config: &Config| -> Result<Counter, String> {
+///         Ok(Counter { next: config.start })
+///     },
+///     shutdown = |counter: Counter| println!("stopped at {}", counter.next),
+/// );
+///
+/// easy_ffi!(my_ffi_fn =>
+///     |err| {
+///         println!("{}", err);
+///         -1
+///     }
+///     |panic_val| { -1 }
+/// );
+///
+/// my_ffi_fn!(
+///     fn next() -> Result<i32, String> {
+///         Ok(with_state("next", |counter| {
+///             counter.next += 1;
+///             counter.next - 1
+///         })?)
+///     }
+/// );
+/// # fn main() {
+/// #     assert_eq!(-1, next());
+/// #     assert_eq!(0, unsafe { init(&Config { start: 5 }) });
+/// #     assert_eq!(0, unsafe { init(&Config { start: 0 }) });
+/// #     assert_eq!(5, next());
+/// #     assert_eq!(6, next());
+/// #     shutdown();
+/// #     assert_eq!(-1, next());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_lifecycle {
+    (
+        $prefix:ident($config:ty) -> $state:ty;
+        init = $init:expr,
+        $(shutdown = $shutdown:expr $(,)?)?
+    ) => {
+        static EASY_FFI_STATE: ::std::sync::Mutex<::std::option::Option<$state>> =
+            ::std::sync::Mutex::new(::std::option::Option::None);
+
+        /// Calls `f` with the library's state, if it's been initialized.
+        #[allow(dead_code)]
+        pub fn with_state<R>(
+            fn_name: &'static str,
+            f: impl ::std::ops::FnOnce(&mut $state) -> R,
+        ) -> ::std::result::Result<R, $crate::NotInitialized> {
+            $crate::with_lifecycle_state(fn_name, &EASY_FFI_STATE, f)
+        }
+
+        /// Whether the library's been initialized.
+        #[allow(dead_code)]
+        pub fn is_initialized() -> bool {
+            with_state("is_initialized", |_| ()).is_ok()
+        }
+
+        /// Fails if the library hasn't been initialized, for a `before` hook.
+        #[allow(dead_code)]
+        pub fn check_initialized(
+            fn_name: &'static str,
+        ) -> ::std::result::Result<(), $crate::NotInitialized> {
+            with_state(fn_name, |_| ())
+        }
+
+        /// Initializes the library. Returns 0 on success.
+        ///
+        /// # Safety
+        ///
+        /// `config` must be null or point to a valid config.
+        #[export_name = concat!(stringify!($prefix), "_init")]
+        pub unsafe extern "C" fn init(config: *const $config) -> ::std::os::raw::c_int {
+            $crate::lifecycle_init(
+                concat!(stringify!($prefix), "_init"),
+                &EASY_FFI_STATE,
+                config,
+                $init,
+            )
+        }
+
+        /// Shuts the library down, if it's been initialized.
+        #[export_name = concat!(stringify!($prefix), "_shutdown")]
+        pub extern "C" fn shutdown() {
+            $crate::lifecycle_shutdown(
+                concat!(stringify!($prefix), "_shutdown"),
+                &EASY_FFI_STATE,
+                $crate::easy_ffi_lifecycle!(@shutdown $($shutdown)?),
+            )
+        }
+    };
+    (@shutdown) => {
+        ::std::mem::drop
+    };
+    (@shutdown $shutdown:expr) => {
+        $shutdown
+    };
+}
+
+/// Defines a type for handing Rust closures to C APIs that take callbacks.
+///
+/// C APIs usually take a callback as a function pointer along with a `void *`
+/// that's passed back to it, and often a destructor for the `void *` too.
+///
+/// ```text
+/// easy_ffi_closure!(
+///     pub struct LineCallback(FnMut(line: *const c_char) -> c_int);
+///     on_panic = -1,
+/// );
+/// ```
+///
+/// defines a `#[repr(C)]` struct `LineCallback` with those three fields, and
+/// `LineCallback::new(closure)`, which boxes up any `FnMut(*const c_char) ->
+/// c_int + Send + 'static` and fills them in:
+///
+/// * `data: *mut c_void`, the boxed closure.
+/// * `call: unsafe extern "C" fn(*mut c_void, *const c_char) -> c_int`, which
+///   calls the closure behind `data`. If it panics, or `data` is null, that's
+///   printed to stderr and `on_panic` is returned instead. This can be left
+///   out for callbacks returning a type with a `Default`, such as `()`, which
+///   then return that.
+/// * `free: unsafe extern "C" fn(*mut c_void)`, which drops the closure
+///   behind `data`, unless it's null, like a destructor made with
+///   [`easy_ffi_destructor!`](macro.easy_ffi_destructor.html).
+///
+/// Once it's been handed over, C must call `free` exactly once, and not
+/// concurrently with `call`, which isn't reentrant. The closure has to be
+/// `Send`, since it's up to C which thread calls it. A `LineCallback` that
+/// isn't handed over is leaked unless `free` is called on its `data`.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::os::raw::c_int;
+///
+/// easy_ffi_closure!(
+///     /// Called with each item in turn, stopping early if it returns 0.
+///     pub struct ItemCallback(FnMut(item: c_int) -> c_int);
+///     on_panic = 0,
+/// );
+///
+/// let mut total = 0;
+/// let callback = ItemCallback::new(move |item| {
+///     total += item;
+///     (total < 10) as c_int
+/// });
+/// # unsafe {
+/// #     assert_eq!(1, (callback.call)(callback.data, 5));
+/// #     assert_eq!(0, (callback.call)(callback.data, 5));
+/// #     (callback.free)(callback.data);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_closure {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident(FnMut($($arg:ident : $arg_ty:ty),* $(,)?) $(-> $ret:ty)?);
+        $(on_panic = $on_panic:expr $(,)?)?
+    ) => {
+        $(#[$attr])*
+        #[repr(C)]
+        $vis struct $name {
+            /// The boxed closure.
+            pub data: *mut ::std::os::raw::c_void,
+            /// Calls the closure behind `data`.
+            pub call: unsafe extern "C" fn(*mut ::std::os::raw::c_void, $($arg_ty),*) $(-> $ret)?,
+            /// Drops the closure behind `data`.
+            pub free: unsafe extern "C" fn(*mut ::std::os::raw::c_void),
+        }
+
+        // The closure is `Send`, and the rest are just function pointers.
+        unsafe impl ::std::marker::Send for $name {}
+
+        impl $name {
+            /// Boxes up `closure` to be handed to C.
+            pub fn new<F>(closure: F) -> $name
+            where
+                F: FnMut($($arg_ty),*) $(-> $ret)? + ::std::marker::Send + 'static,
+            {
+                unsafe extern "C" fn call<F>(
+                    data: *mut ::std::os::raw::c_void,
+                    $($arg: $arg_ty),*
+                ) $(-> $ret)?
+                where
+                    F: FnMut($($arg_ty),*) $(-> $ret)?,
+                {
+                    $crate::call_boxed(
+                        stringify!($name),
+                        data,
+                        $crate::easy_ffi_closure!(@on_panic $($on_panic)?),
+                        |closure: &mut F| closure($($arg),*),
+                    )
+                }
+
+                unsafe extern "C" fn free<F>(data: *mut ::std::os::raw::c_void) {
+                    $crate::drop_boxed(stringify!($name), data as *mut F, false);
+                }
+
+                $name {
+                    data: ::std::boxed::Box::into_raw(::std::boxed::Box::new(closure))
+                        as *mut ::std::os::raw::c_void,
+                    call: call::<F>,
+                    free: free::<F>,
+                }
+            }
+        }
+    };
+    (@on_panic) => (::std::default::Default::default());
+    (@on_panic $on_panic:expr) => ($on_panic);
+}
+
+/// Defines a C vtable for handing Rust trait objects to C, e.g. as plugins.
+///
+/// Macros can't look inside a trait, so its methods are listed again:
+///
+/// ```text
+/// easy_ffi_vtable!(
+///     pub struct PluginVtable: Plugin {
+///         fn name(&self) -> *const c_char = ptr::null();
+///         fn process(&mut self, input: c_int) -> c_int = -1;
+///         fn reset(&mut self);
+///     }
+/// );
+/// ```
+///
+/// defines a `#[repr(C)]` struct `PluginVtable`, and
+/// `PluginVtable::new(plugin)`, which boxes up any `Plugin + Send + 'static`
+/// and fills in the struct's fields:
+///
+/// * `data: *mut c_void`, the boxed object.
+/// * A field per method, such as `process: unsafe extern "C" fn(*mut c_void,
+///   c_int) -> c_int`, which calls the method on the object behind `data`. If
+///   it panics, or `data` is null, that's printed to stderr and the value
+///   after the `=` is returned instead. This can be left out for methods
+///   returning a type with a `Default`, such as `()`, which then return that.
+/// * `free: unsafe extern "C" fn(*mut c_void)`, which drops the object behind
+///   `data`, unless it's null.
+///
+/// As with [`easy_ffi_closure!`](macro.easy_ffi_closure.html), C must call
+/// `free` exactly once, and not call methods concurrently, even ones taking
+/// `&self`. Methods can only take and return types C understands, and traits
+/// with generic methods can't be used.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::os::raw::c_int;
+///
+/// pub trait Filter {
+///     fn filter(&mut self, sample: c_int) -> c_int;
+///     fn reset(&mut self);
+/// }
+///
+/// pub struct Gain(c_int);
+///
+/// impl Filter for Gain {
+///     fn filter(&mut self, sample: c_int) -> c_int {
+///         sample * self.0
+///     }
+///
+///     fn reset(&mut self) {}
+/// }
+///
+/// easy_ffi_vtable!(
+///     /// A filter, for C.
+///     pub struct FilterVtable: Filter {
+///         /// Filters one sample, returning 0 on panic.
+///         fn filter(&mut self, sample: c_int) -> c_int = 0;
+///         /// Resets the filter's state.
+///         fn reset(&mut self);
+///     }
+/// );
+///
+/// let vtable = FilterVtable::new(Gain(2));
+/// # unsafe {
+/// #     assert_eq!(6, (vtable.filter)(vtable.data, 3));
+/// #     (vtable.reset)(vtable.data);
+/// #     (vtable.free)(vtable.data);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_vtable {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: $trait_:path {
+            $(
+                $(#[$method_attr:meta])*
+                fn $method:ident(& $(mut)? self $(, $arg:ident : $arg_ty:ty)* $(,)?)
+                    $(-> $ret:ty $(= $on_panic:expr)?)?;
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        #[repr(C)]
+        $vis struct $name {
+            /// The boxed object.
+            pub data: *mut ::std::os::raw::c_void,
+            $(
+                $(#[$method_attr])*
+                pub $method: unsafe extern "C" fn(
+                    *mut ::std::os::raw::c_void,
+                    $($arg_ty),*
+                ) $(-> $ret)?,
+            )*
+            /// Drops the object behind `data`.
+            pub free: unsafe extern "C" fn(*mut ::std::os::raw::c_void),
+        }
+
+        // The object is `Send`, and the rest are just function pointers.
+        unsafe impl ::std::marker::Send for $name {}
+
+        impl $name {
+            /// Boxes up `object` to be handed to C.
+            pub fn new<T>(object: T) -> $name
+            where
+                T: $trait_ + ::std::marker::Send + 'static,
+            {
+                $(
+                    unsafe extern "C" fn $method<T: $trait_>(
+                        data: *mut ::std::os::raw::c_void,
+                        $($arg: $arg_ty),*
+                    ) $(-> $ret)? {
+                        $crate::call_boxed(
+                            concat!(stringify!($name), "::", stringify!($method)),
+                            data,
+                            $crate::easy_ffi_vtable!(@on_panic $($($on_panic)?)?),
+                            |object: &mut T| <T as $trait_>::$method(object, $($arg),*),
+                        )
+                    }
+                )*
+
+                unsafe extern "C" fn free<T>(data: *mut ::std::os::raw::c_void) {
+                    $crate::drop_boxed(stringify!($name), data as *mut T, false);
+                }
+
+                $name {
+                    data: ::std::boxed::Box::into_raw(::std::boxed::Box::new(object))
+                        as *mut ::std::os::raw::c_void,
+                    $($method: $method::<T>,)*
+                    free: free::<T>,
+                }
+            }
+        }
+    };
+    (@on_panic) => (::std::default::Default::default());
+    (@on_panic $on_panic:expr) => ($on_panic);
+}
+
+/// Defines a wrapper implementing a Rust trait with a vtable from C.
+///
+/// This is the other way around from
+/// [`easy_ffi_vtable!`](macro.easy_ffi_vtable.html), for C plugins.
+///
+/// ```text
+/// easy_ffi_foreign_vtable!(
+///     pub struct CPlugin(CPluginVtable): Plugin {
+///         fn process(&mut self, input: c_int) -> Result<c_int, ForeignError>;
+///         fn reset(&mut self) -> Result<(), ForeignError> = status;
+///     }
+/// );
+/// ```
+///
+/// defines a `#[repr(C)]` struct `CPluginVtable`, laid out like a vtable from
+/// `easy_ffi_vtable!`, which C fills in:
+///
+/// * `data: *mut c_void`, passed to each of the functions.
+/// * A nullable function pointer per method, such as `process: Option<unsafe
+///   extern "C" fn(*mut c_void, c_int) -> c_int>`. By default the function
+///   returns the method's ok value. With `= status`, it returns a `c_int`
+///   instead, 0 for success, and the method's ok type must be `()`.
+/// * `free: Option<unsafe extern "C" fn(*mut c_void)>`, called with `data`
+///   when the wrapper is dropped, unless it's null.
+///
+/// It also defines `CPlugin`, made with the unsafe `CPlugin::new(vtable)`,
+/// which implements `Plugin` by calling those functions. A null function
+/// gives a [`ForeignError::Missing`](enum.ForeignError.html), and a nonzero
+/// status a `ForeignError::Status`, converted to the method's error type with
+/// `From`. The wrapper isn't `Send` or `Sync`, since that depends on the C
+/// side, but can be declared so with an `unsafe impl`.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::ForeignError;
+/// use std::os::raw::{c_int, c_void};
+///
+/// pub trait Filter {
+///     fn filter(&mut self, sample: c_int) -> Result<c_int, ForeignError>;
+///     fn reset(&mut self) -> Result<(), ForeignError>;
+/// }
+///
+/// easy_ffi_foreign_vtable!(
+///     /// A filter written in C.
+///     pub struct CFilter(CFilterVtable): Filter {
+///         fn filter(&mut self, sample: c_int) -> Result<c_int, ForeignError>;
+///         fn reset(&mut self) -> Result<(), ForeignError> = status;
+///     }
+/// );
+///
+/// unsafe extern "C" fn invert(_data: *mut c_void, sample: c_int) -> c_int {
+///     -sample
+/// }
+///
+/// let mut filter = unsafe {
+///     CFilter::new(CFilterVtable {
+///         data: std::ptr::null_mut(),
+///         filter: Some(invert),
+///         reset: None,
+///         free: None,
+///     })
+/// };
+/// assert_eq!(Ok(-2), filter.filter(2));
+/// assert_eq!(Err(ForeignError::Missing("reset")), filter.reset());
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_foreign_vtable {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident($vtable:ident): $trait_:path {
+            $(
+                fn $method:ident(& $($recv:ident)+ $(, $arg:ident : $arg_ty:ty)* $(,)?)
+                    -> Result<$ok:ty, $err:ty> $(= $kind:ident)?;
+            )*
+        }
+    ) => {
+        /// The vtable for a
+        #[doc = concat!("[`", stringify!($name), "`].")]
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        $vis struct $vtable {
+            /// Passed to each of the functions.
+            pub data: *mut ::std::os::raw::c_void,
+            $(
+                #[doc = concat!("Implements `", stringify!($method), "`, or null if it's missing.")]
+                pub $method: ::std::option::Option<unsafe extern "C" fn(
+                    *mut ::std::os::raw::c_void,
+                    $($arg_ty),*
+                ) -> $crate::easy_ffi_foreign_vtable!(@ret [$($kind)?] $ok)>,
+            )*
+            /// Called with `data` when it's no longer needed.
+            pub free: ::std::option::Option<unsafe extern "C" fn(*mut ::std::os::raw::c_void)>,
+        }
+
+        $(#[$attr])*
+        $vis struct $name {
+            vtable: $vtable,
+        }
+
+        impl $name {
+            /// Wraps `vtable`, taking ownership of its `data`.
+            ///
+            /// # Safety
+            ///
+            /// Each of `vtable`'s functions must be safe to call with its
+            /// `data`, until `free` is called when the wrapper is dropped.
+            pub unsafe fn new(vtable: $vtable) -> $name {
+                $name { vtable }
+            }
+        }
+
+        impl $trait_ for $name {
+            $(
+                fn $method(& $($recv)+ $(, $arg: $arg_ty)*) -> ::std::result::Result<$ok, $err> {
+                    let vtable = $crate::easy_ffi_foreign_vtable!(@self $($recv)+).vtable;
+                    let method = vtable.$method.ok_or($crate::ForeignError::Missing(
+                        stringify!($method),
+                    ))?;
+                    let ret = unsafe { method(vtable.data, $($arg),*) };
+                    $crate::easy_ffi_foreign_vtable!(@convert [$($kind)?] $method ret)
+                }
+            )*
+        }
+
+        impl ::std::ops::Drop for $name {
+            fn drop(&mut self) {
+                if let Some(free) = self.vtable.free {
+                    unsafe { free(self.vtable.data) };
+                }
+            }
+        }
+    };
+    // `self` has to come from the caller's tokens to refer to the receiver.
+    (@self mut $self_:ident) => ($self_);
+    (@self $self_:ident) => ($self_);
+    (@ret [] $ok:ty) => ($ok);
+    (@ret [status] $ok:ty) => (::std::os::raw::c_int);
+    (@ret [$kind:ident] $ok:ty) => (
+        compile_error!(concat!("unknown kind `", stringify!($kind), "`, expected `status`"))
+    );
+    (@convert [] $method:ident $ret:ident) => (::std::result::Result::Ok($ret));
+    (@convert [status] $method:ident $ret:ident) => (
+        match $ret {
+            0 => ::std::result::Result::Ok(()),
+            status => ::std::result::Result::Err(::std::convert::From::from(
+                $crate::ForeignError::Status(stringify!($method), status),
+            )),
+        }
+    );
+    (@convert [$kind:ident] $method:ident $ret:ident) => (::std::unreachable!());
+}
+
+/// Defines an opaque handle type for passing a Rust value to C.
+///
+/// ```text
+/// easy_ffi_handle!(
+///     pub struct WidgetHandle(Widget);
+///     new = widget_new,
+///     free = widget_free,
+/// );
+/// ```
+///
+/// defines a `#[repr(C)]` struct `WidgetHandle` wrapping a `Widget`, which C
+/// only ever sees behind a pointer, along with:
+///
+/// * `WidgetHandle::new(widget)`, which boxes a `Widget` up as a handle. Wrapped
+///   functions returning `Result<Box<WidgetHandle>, E>` give C a
+///   `*mut WidgetHandle`.
+/// * `WidgetHandle::with(handle, |widget| ...)` and
+///   `WidgetHandle::with_mut(handle, |widget| ...)`, which borrow the `Widget`
+///   inside a handle from C, or give a
+///   [`HandleError`](enum.HandleError.html) if it can't be used, e.g. because
+///   it's null. These are unsafe, since the handle must otherwise be valid.
+/// * `widget_new`, if `new = ...` is given: an `extern "C" fn() ->
+///   *mut WidgetHandle` making a handle from `Widget::default()`, or null if
+///   that panics. To make it some other way, give its parameters and body
+///   after the name, e.g. `new = widget_new(size: i32) { Widget { size } }`.
+/// * `widget_free`: the handle's destructor, which does nothing with a null
+///   handle, and in debug builds reports one that's already been freed to
+///   stderr rather than freeing it again. Like
+///   [`free_boxed`](fn.free_boxed.html), that only works for handles from
+///   `widget_new` and wrapped functions, since freed addresses get reused.
+///
+/// With `magic = 0x...` before `new`, handles carry the given `u64` tag in
+/// debug builds, which `with`, `with_mut` and the destructor check before
+/// using them. This turns most wild or dangling pointers from C into a
+/// `HandleError::Invalid` rather than undefined behavior, though the check
+/// itself still reads through the pointer, and the destructor leaves them
+/// alone. Release builds leave the tag out.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::HandleError;
+///
+/// #[derive(Default)]
+/// pub struct Widget {
+///     size: i32,
+/// }
+///
+/// easy_ffi_handle!(
+///     /// A widget, for C.
+///     pub struct WidgetHandle(Widget);
+///     magic = 0x5749_4447_4554,
+///     new = widget_new(size: i32) { Widget { size } },
+///     free = widget_free,
+/// );
+///
+/// easy_ffi!(my_ffi_fn =>);
+///
+/// my_ffi_fn!(
+///     fn widget_with_size(size: i32) -> Result<Box<WidgetHandle>, HandleError> {
+///         Ok(WidgetHandle::new(Widget { size }))
+///     }
+///
+///     unsafe fn widget_size(widget: *const WidgetHandle) -> Result<i32, HandleError> {
+///         WidgetHandle::with(widget, |widget| widget.size)
+///     }
+/// );
+/// # fn main() {
+/// #     let widget = widget_with_size(2);
+/// #     assert_eq!(2, unsafe { widget_size(widget) });
+/// #     unsafe { widget_free(widget) };
+/// #     assert_eq!(-1, unsafe { widget_size(std::ptr::null()) });
+/// #     let widget = widget_new(3);
+/// #     assert_eq!(3, unsafe { widget_size(widget) });
+/// #     unsafe { widget_free(widget) };
+/// # }
+/// ```
+#[macro_export]
+macro_rules! easy_ffi_handle {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $handle:ident($ty:ty);
+        $(magic = $magic:expr,)?
+        $(new = $new:ident $(($($new_arg:ident: $new_ty:ty),* $(,)?) $new_body:block)?,)?
+        free = $free:ident $(,)?
+    ) => {
+        $(#[$attr])*
+        #[repr(C)]
+        $vis struct $handle {
+            #[cfg(debug_assertions)]
+            magic: u64,
+            inner: $ty,
+        }
+
+        #[allow(dead_code)]
+        impl $handle {
+            /// The tag checked in debug builds, or 0 for none.
+            const MAGIC: u64 = $crate::easy_ffi_handle!(@magic $($magic)?);
+
+            fn wrap(inner: $ty) -> $handle {
+                $handle {
+                    #[cfg(debug_assertions)]
+                    magic: Self::MAGIC,
+                    inner,
+                }
+            }
+
+            fn is_valid(&self) -> bool {
+                #[cfg(debug_assertions)]
+                let valid = Self::MAGIC == 0 || self.magic == Self::MAGIC;
+                #[cfg(not(debug_assertions))]
+                let valid = true;
+                valid
+            }
+
+            /// Boxes up `inner` as a handle.
+            pub fn new(inner: $ty) -> ::std::boxed::Box<$handle> {
+                ::std::boxed::Box::new($handle::wrap(inner))
+            }
+
+            /// Calls `f` with the value inside `handle`.
+            ///
+            /// # Safety
+            ///
+            /// `handle` must be null or point to a live handle.
+            pub unsafe fn with<R>(
+                handle: *const $handle,
+                f: impl FnOnce(&$ty) -> R,
+            ) -> ::std::result::Result<R, $crate::HandleError> {
+                match handle.as_ref() {
+                    Some(handle) if !handle.is_valid() => Err($crate::HandleError::Invalid),
+                    Some(handle) => Ok(f(&handle.inner)),
+                    None => Err($crate::HandleError::Null),
+                }
+            }
+
+            /// Calls `f` with the value inside `handle`, mutably.
+            ///
+            /// # Safety
+            ///
+            /// `handle` must be null or point to a live handle that nothing
+            /// else is using.
+            pub unsafe fn with_mut<R>(
+                handle: *mut $handle,
+                f: impl FnOnce(&mut $ty) -> R,
+            ) -> ::std::result::Result<R, $crate::HandleError> {
+                match handle.as_mut() {
+                    Some(handle) if !handle.is_valid() => Err($crate::HandleError::Invalid),
+                    Some(handle) => Ok(f(&mut handle.inner)),
+                    None => Err($crate::HandleError::Null),
+                }
+            }
+        }
+
+        // Clears the tag, so that a freed handle is caught if it's used again
+        // before its memory is reused.
+        impl ::std::ops::Drop for $handle {
+            fn drop(&mut self) {
+                #[cfg(debug_assertions)]
+                unsafe {
+                    ::std::ptr::write_volatile(&mut self.magic, 0)
+                };
+            }
+        }
+
+        $(
+            /// Makes a new handle, or returns null on failure.
+            #[no_mangle]
+            pub extern "C" fn $new($($($new_arg: $new_ty),*)?) -> *mut $handle {
+                $crate::new_boxed(
+                    stringify!($new),
+                    ::std::panic::AssertUnwindSafe(move || {
+                        $handle::wrap($crate::easy_ffi_handle!(@new $($new_body)?))
+                    }),
+                )
+            }
+        )?
+
+        /// Frees a handle. Does nothing if it's null.
+        #[no_mangle]
+        pub unsafe extern "C" fn $free(handle: *mut $handle) {
+            $crate::drop_handle(stringify!($free), handle, $handle::is_valid);
+        }
+    };
+    (@magic) => (0);
+    (@magic $magic:expr) => ($magic);
+    (@new) => (::std::default::Default::default());
+    (@new $body:block) => ($body);
+}
+
+// Registers an export for `manifest = true`, which needs `inventory` to collect
+// them for `exports()`.
+#[cfg(feature = "manifest")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __easy_ffi_manifest {
+    ($export:expr) => {
+        $crate::__inventory::submit!($export);
+    };
+}
+
+#[cfg(not(feature = "manifest"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __easy_ffi_manifest {
+    ($export:expr) => {
+        compile_error!("`manifest = true` needs easy_ffi's `manifest` feature");
+    };
+}
+
+// Registers functions wrapped with `panic_tests = true`, and the ones
+// `easy_ffi_tests!` lists, for its check that none are left out, which needs
+// `inventory` to collect them.
+#[cfg(feature = "manifest")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __easy_ffi_panic_test {
+    (@check) => {
+        #[test]
+        fn every_panic_test_is_listed() {
+            let unlisted = $crate::unlisted_panic_tests(module_path!());
+            assert!(
+                unlisted.is_empty(),
+                "`easy_ffi_tests!` doesn't list {:?}, wrapped with `panic_tests = true`",
+                unlisted,
+            );
+        }
+    };
+    ($fn_name:ident $listed:expr) => {
+        $crate::__inventory::submit!($crate::PanicTest {
+            module: module_path!(),
+            fn_name: stringify!($fn_name),
+            listed: $listed,
+        });
+    };
+}
+
+#[cfg(not(feature = "manifest"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __easy_ffi_panic_test {
+    ($($rest:tt)*) => {};
+}
+
+/// Parses wrapped functions and generates their `extern` wrappers.
+///
+/// Not public API: this is invoked by the macros that `easy_ffi!` generates.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __easy_ffi_fn {
+    ({ $($cfg:tt)* }) => ();
+    ({ $($cfg:tt)* } $($item:tt)+) => (
+        $crate::__easy_ffi_fn!(@attrs { $($cfg)* } [[] [] []] $($item)+);
+    );
+    // `#[export_name]` replaces the `#[no_mangle]` we'd otherwise add.
+    (@attrs $cfg:tt [$attrs:tt $old:tt $overrides:tt] #[export_name = $export_name:expr] $($rest:tt)+) => (
+        $crate::__easy_ffi_fn!(@attrs $cfg [$attrs [$export_name] $overrides] $($rest)+);
+    );
+    // `#[easy_ffi(...)]` overrides the wrapper macro's options and handlers.
+    (@attrs $cfg:tt [$attrs:tt $export_name:tt [$($overrides:tt)*]] #[easy_ffi($($new:tt)*)] $($rest:tt)+) => (
+        $crate::__easy_ffi_fn!(@attrs $cfg [$attrs $export_name [$($overrides)* $($new)*]] $($rest)+);
+    );
+    (@attrs $cfg:tt [[$($attrs:tt)*] $export_name:tt $overrides:tt] #[$($attr:tt)*] $($rest:tt)+) => (
+        $crate::__easy_ffi_fn!(@attrs $cfg [[$($attrs)* #[$($attr)*]] $export_name $overrides] $($rest)+);
+    );
+    (@attrs $cfg:tt $attrs:tt $($rest:tt)+) => (
+        $crate::__easy_ffi_fn!(@head $cfg $attrs [pub] [] $($rest)+);
+    );
+    // Generated functions are `pub` unless another visibility is given, so a
+    // private one has to be asked for with `pub(self)`.
+    (@head $cfg:tt $attrs:tt $vis:tt $quals:tt pub(self) $($rest:tt)+) => (
+        $crate::__easy_ffi_fn!(@head $cfg $attrs [] $quals $($rest)+);
+    );
+    (@head $cfg:tt $attrs:tt $vis:tt $quals:tt pub ($($restricted:tt)+) $($rest:tt)+) => (
+        $crate::__easy_ffi_fn!(@head $cfg $attrs [pub($($restricted)+)] $quals $($rest)+);
+    );
+    (@head $cfg:tt $attrs:tt $vis:tt $quals:tt pub $($rest:tt)+) => (
+        $crate::__easy_ffi_fn!(@head $cfg $attrs [pub] $quals $($rest)+);
+    );
+    // An `async fn` is blocked on, which the options have to know about.
+    (
+        @head $cfg:tt [$attrs:tt $export_name:tt [$($overrides:tt)*]] $vis:tt $quals:tt
+        async $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@head $cfg [$attrs $export_name [async_fn = true, $($overrides)*]]
+            $vis $quals $($rest)+
+        );
+    );
+    (@head $cfg:tt $attrs:tt $vis:tt [$($quals:tt)*] unsafe $($rest:tt)+) => (
+        $crate::__easy_ffi_fn!(@head $cfg $attrs $vis [$($quals)* unsafe] $($rest)+);
+    );
+    (
+        @head $cfg:tt $attrs:tt $vis:tt $quals:tt
+        fn $fn_name:ident $(<$($lt:lifetime $(: $lt_bound:lifetime $(+ $lt_bounds:lifetime)*)?),* $(,)?>)? (
+            $($args:tt)*
+        ) -> $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs
+            [$vis $fn_name [$(<$($lt $(: $lt_bound $(+ $lt_bounds)*)?),*>)?] [$quals [$($args)*]]]
+            $quals [] [] [] [[] [] []] [$($args)*] $($ret)+
+        );
+    );
+    // Arguments are munched one at a time, since some are converted from ffi
+    // types before the body runs. This collects the ffi parameters, the
+    // conversions, the argument names, and the raw pointer arguments along
+    // with the `#[zeroize]` slices and whether a slice's length is `len` yet.
+    (
+        @args $cfg:tt $attrs:tt [$vis:tt $fn_name:ident $generics:tt $decl:tt]
+        $quals:tt $params:tt $convs:tt $names:tt $ptrs:tt [] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@ret $cfg $attrs
+            [$vis $quals $fn_name $generics $params $convs $names $ptrs $decl]
+            $($ret)+
+        );
+    );
+    // A `&FfiCancelToken` is passed as a pointer from the functions made by
+    // `easy_ffi_cancel_token!`, and checked for null.
+    (
+        @args $cfg:tt $attrs:tt $head:tt
+        $quals:tt [$($params:tt)*] [$($convs:tt)*] [$($names:ident)*] $ptrs:tt
+        [$arg:ident : & $($lt:lifetime)? FfiCancelToken $(, $($rest:tt)*)?] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            [unsafe]
+            [$($params)* $arg: *const $crate::FfiCancelToken,]
+            [$($convs)*
+                #[allow(unused_unsafe)]
+                let $arg: & $($lt)? $crate::FfiCancelToken = unsafe {
+                    $crate::ref_arg(stringify!($arg), $arg)
+                }?;
+            ]
+            [$($names)* $arg] $ptrs
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    // A `&str` is passed as a NUL-terminated `*const c_char`, and checked
+    // for null and UTF-8.
+    (
+        @args $cfg:tt $attrs:tt $head:tt
+        $quals:tt [$($params:tt)*] [$($convs:tt)*] [$($names:ident)*] $ptrs:tt
+        [$arg:ident : & $($lt:lifetime)? str $(, $($rest:tt)*)?] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            [unsafe]
+            [$($params)* $arg: *const ::std::os::raw::c_char,]
+            [$($convs)*
+                #[allow(unused_unsafe)]
+                let $arg: & $($lt)? str = unsafe { $crate::str_arg(stringify!($arg), $arg) }?;
+            ]
+            [$($names)* $arg] $ptrs
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    // A `&[T]` is passed as a pointer to its first element, followed by its
+    // length. Macros can't make up a name like `{arg}_len`, so the length is
+    // `len` unless it's named with `as`, e.g. `data: &[u8] as data_len`,
+    // which all but one slice argument have to be.
+    (
+        @args $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt $ptrs:tt
+        [$arg:ident : & $($lt:lifetime)? [$elem:ty] $(as $len:ident)? $(, $($rest:tt)*)?] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@slice_len $cfg $attrs $head $quals $params $convs $names $ptrs
+            [$($len)?] [$arg const [& $($lt)? [$elem]] [$elem] slice_arg []]
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    (
+        @args $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt $ptrs:tt
+        [$arg:ident : & $($lt:lifetime)? mut [$elem:ty] $(as $len:ident)? $(, $($rest:tt)*)?] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@slice_len $cfg $attrs $head $quals $params $convs $names $ptrs
+            [$($len)?] [$arg mut [& $($lt)? mut [$elem]] [$elem] slice_mut_arg []]
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    (
+        @slice_len $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt $ptrs:tt
+        [$len:ident] $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@slice_arg $cfg $attrs $head $quals $params $convs $names $ptrs
+            $len $($rest)+
+        );
+    );
+    (
+        @slice_len $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt
+        [$ptrs:tt $wipes:tt []] [] $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@slice_arg $cfg $attrs $head $quals $params $convs $names
+            [$ptrs $wipes [len]] len $($rest)+
+        );
+    );
+    (
+        @slice_len $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt $ptrs:tt
+        [] [$arg:ident $($slice:tt)*] $($rest:tt)+
+    ) => (
+        compile_error!(concat!(
+            "another slice's length is already called `len`, so `", stringify!($arg),
+            "`'s needs a name of its own, e.g. `", stringify!($arg), ": &[T] as ",
+            stringify!($arg), "_len`"
+        ));
+    );
+    // A `#[zeroize]` slice is remembered, with its length, to be wiped if the
+    // function fails.
+    (
+        @slice_arg $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt
+        [$ptrs:tt [$($wipes:tt)*] $lens:tt] $len:ident
+        [$arg:ident $mutability:ident $slice_ty:tt $elem:tt $conv:ident [zeroize]] $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@slice_arg $cfg $attrs $head $quals $params $convs $names
+            [$ptrs [$($wipes)* [$arg $len]] $lens] $len
+            [$arg $mutability $slice_ty $elem $conv []] $($rest)+
+        );
+    );
+    (
+        @slice_arg $cfg:tt $attrs:tt $head:tt
+        $quals:tt [$($params:tt)*] [$($convs:tt)*] [$($names:ident)*] $ptrs:tt $len:ident
+        [$arg:ident $mutability:ident [$($slice_ty:tt)*] [$elem:ty] $conv:ident []]
+        $rest:tt $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            [unsafe]
+            [$($params)* $arg: *$mutability $elem, $len: usize,]
+            [$($convs)*
+                #[allow(unused_unsafe)]
+                let $arg: $($slice_ty)* = unsafe { $crate::$conv(stringify!($arg), $arg, $len) }?;
+            ]
+            [$($names)* $arg] $ptrs
+            $rest $($ret)+
+        );
+    );
+    // A function pointer can't be null, so it's taken as an `Option` and
+    // checked, in case C passes null anyway.
+    (
+        @args $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt $ptrs:tt
+        [$arg:ident : extern $abi:literal fn $fn_args:tt $(-> $fn_ret:ty)? $(, $($rest:tt)*)?]
+        $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@fn_ptr_arg $cfg $attrs $head $quals $params $convs $names $ptrs
+            $arg [extern $abi fn $fn_args $(-> $fn_ret)?]
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    (
+        @args $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt $ptrs:tt
+        [$arg:ident : unsafe extern $abi:literal fn $fn_args:tt $(-> $fn_ret:ty)? $(, $($rest:tt)*)?]
+        $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@fn_ptr_arg $cfg $attrs $head $quals $params $convs $names $ptrs
+            $arg [unsafe extern $abi fn $fn_args $(-> $fn_ret)?]
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    (
+        @fn_ptr_arg $cfg:tt $attrs:tt $head:tt
+        $quals:tt [$($params:tt)*] [$($convs:tt)*] [$($names:ident)*] $ptrs:tt
+        $arg:ident [$($fn_ty:tt)*] $rest:tt $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            $quals
+            [$($params)* $arg: ::std::option::Option<$($fn_ty)*>,]
+            [$($convs)*
+                let $arg: $($fn_ty)* = $arg.ok_or($crate::ArgError::Null(stringify!($arg)))?;
+            ]
+            [$($names)* $arg] $ptrs
+            $rest $($ret)+
+        );
+    );
+    // Raw pointers are collected for `null_checks = true`.
+    (
+        @args $cfg:tt $attrs:tt $head:tt
+        $quals:tt [$($params:tt)*] $convs:tt [$($names:ident)*] [[$($ptrs:ident)*] $wipes:tt $lens:tt]
+        [$arg:ident : * $mutability:ident $pointee:ty $(, $($rest:tt)*)?] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            $quals [$($params)* $arg: * $mutability $pointee,] $convs [$($names)* $arg]
+            [[$($ptrs)* $arg] $wipes $lens]
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    (
+        @args $cfg:tt $attrs:tt $head:tt
+        $quals:tt [$($params:tt)*] $convs:tt [$($names:ident)*] $ptrs:tt
+        [$arg:ident : $arg_ty:ty $(, $($rest:tt)*)?] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            $quals [$($params)* $arg: $arg_ty,] $convs [$($names)* $arg] $ptrs
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    // `#[nonnull]` checks a pointer whatever `null_checks` says, and
+    // `#[nullable]` doesn't.
+    (
+        @args $cfg:tt $attrs:tt $head:tt
+        $quals:tt [$($params:tt)*] [$($convs:tt)*] [$($names:ident)*] $ptrs:tt
+        [#[nonnull] $arg:ident : $arg_ty:ty $(, $($rest:tt)*)?] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            $quals [$($params)* $arg: $arg_ty,]
+            [$($convs)* $crate::check_non_null(stringify!($arg), $arg)?;]
+            [$($names)* $arg] $ptrs
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    (
+        @args $cfg:tt $attrs:tt $head:tt
+        $quals:tt [$($params:tt)*] $convs:tt [$($names:ident)*] $ptrs:tt
+        [#[nullable] $arg:ident : $arg_ty:ty $(, $($rest:tt)*)?] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            $quals [$($params)* $arg: $arg_ty,] $convs [$($names)* $arg] $ptrs
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    (
+        @args $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt $ptrs:tt
+        [#[zeroize] $arg:ident : & $($lt:lifetime)? mut [$elem:ty] $(as $len:ident)? $(, $($rest:tt)*)?]
+        $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@slice_len $cfg $attrs $head $quals $params $convs $names $ptrs
+            [$($len)?] [$arg mut [& $($lt)? mut [$elem]] [$elem] slice_mut_arg [zeroize]]
+            [$($($rest)*)?] $($ret)+
+        );
+    );
+    (
+        @args $cfg:tt $attrs:tt $head:tt $quals:tt $params:tt $convs:tt $names:tt $ptrs:tt
+        [#[zeroize] $($rest:tt)*] $($ret:tt)+
+    ) => (
+        compile_error!("`#[zeroize]` is only for `&mut [T]` arguments");
+    );
+    // Other attributes are checks, which are added one at a time. `#[utf8]`
+    // reads through the pointer, so the function is unsafe.
+    (
+        @args $cfg:tt $attrs:tt $head:tt
+        $quals:tt $params:tt [$($convs:tt)*] $names:tt $ptrs:tt
+        [#[utf8] $(# $more:tt)* $arg:ident : $($rest:tt)*] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            [unsafe] $params
+            [$($convs)* $crate::__easy_ffi_fn!(@check utf8 $arg []);]
+            $names $ptrs
+            [$(# $more)* $arg : $($rest)*] $($ret)+
+        );
+    );
+    (
+        @args $cfg:tt $attrs:tt $head:tt
+        $quals:tt $params:tt [$($convs:tt)*] $names:tt $ptrs:tt
+        [#[$check:ident $($check_args:tt)?] $(# $more:tt)* $arg:ident : $($rest:tt)*] $($ret:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@args $cfg $attrs $head
+            $quals $params
+            [$($convs)* $crate::__easy_ffi_fn!(@check $check $arg [$($check_args)?]);]
+            $names $ptrs
+            [$(# $more)* $arg : $($rest)*] $($ret)+
+        );
+    );
+    // Lifetimes are erased at the ABI level, but type and const parameters
+    // would need to be monomorphized, which `#[no_mangle]` can't do.
+    (@head $cfg:tt $attrs:tt $vis:tt $quals:tt fn $fn_name:ident < $($rest:tt)*) => (
+        compile_error!(concat!(
+            "`", stringify!($fn_name), "` can't be exported: ",
+            "wrapped ffi functions may only be generic over lifetimes",
+        ));
+    );
+    // An `Option<Box<T>>` ok type is returned as a nullable `*mut T`.
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        Result<Option<Box<$inner:ty>>, $err_ty:ty> $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [Option<Box<$inner>>] [Result<Option<Box<$inner>>, $err_ty>] [option_box *mut $inner]
+            [] $($rest)+
+        );
+    );
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        $(::)? $krate:ident :: result :: Result<Option<Box<$inner:ty>>, $err_ty:ty> $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [Option<Box<$inner>>] [::$krate::result::Result<Option<Box<$inner>>, $err_ty>]
+            [option_box *mut $inner]
+            [] $($rest)+
+        );
+    );
+    // As is a `Box<T>`, which is never null.
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        Result<Box<$inner:ty>, $err_ty:ty> $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [Box<$inner>] [Result<Box<$inner>, $err_ty>] [boxed *mut $inner]
+            [] $($rest)+
+        );
+    );
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        $(::)? $krate:ident :: result :: Result<Box<$inner:ty>, $err_ty:ty> $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [Box<$inner>] [::$krate::result::Result<Box<$inner>, $err_ty>] [boxed *mut $inner]
+            [] $($rest)+
+        );
+    );
+    // As is a `String`, as a NUL-terminated `*mut c_char`.
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        Result<String, $err_ty:ty> $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [String] [Result<String, $err_ty>] [string *mut ::std::os::raw::c_char]
+            [] $($rest)+
+        );
+    );
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        $(::)? $krate:ident :: result :: Result<String, $err_ty:ty> $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [String] [::$krate::result::Result<String, $err_ty>]
+            [string *mut ::std::os::raw::c_char]
+            [] $($rest)+
+        );
+    );
+    // A literal `Result<T, E>` return, possibly path-qualified: the ffi return
+    // type can be written out directly.
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        Result<$ok_ty:ty, $err_ty:ty> $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [$ok_ty] [Result<$ok_ty, $err_ty>] [value $ok_ty]
+            [] $($rest)+
+        );
+    );
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        $(::)? $krate:ident :: result :: Result<$ok_ty:ty, $err_ty:ty> $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [$ok_ty] [::$krate::result::Result<$ok_ty, $err_ty>] [value $ok_ty]
+            [] $($rest)+
+        );
+    );
+    // Anything else, e.g. `anyhow::Result<T>` or a user-defined alias, has its
+    // ok type resolved through `IntoResult`.
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        $res_ty:ty where $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [<$res_ty as $crate::IntoResult>::Ok] [$res_ty]
+            [value <$res_ty as $crate::IntoResult>::Ok]
+            [where] $($rest)+
+        );
+    );
+    (
+        @ret $cfg:tt $attrs:tt $sig:tt
+        $res_ty:ty $body:block $($more:tt)*
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig
+            [<$res_ty as $crate::IntoResult>::Ok] [$res_ty]
+            [value <$res_ty as $crate::IntoResult>::Ok]
+            [] $body $($more)*
+        );
+    );
+    // Everything between the return type and the body is the `where` clause,
+    // and anything after the body is the next function to wrap.
+    (
+        @where $cfg:tt $attrs:tt $sig:tt $ret_ty:tt $res_ty:tt $conv:tt
+        $where_clause:tt $body:block $($more:tt)*
+    ) => (
+        $crate::__easy_ffi_fn!(@override $cfg $attrs $sig $ret_ty $res_ty $conv $where_clause $body);
+        $crate::__easy_ffi_fn!($cfg $($more)*);
+    );
+    (@override $cfg:tt [$attrs:tt $export_name:tt []] $($state:tt)*) => (
+        $crate::__easy_ffi_fn!(@emit $cfg [$attrs $export_name []] $($state)*);
+    );
+    (@override $cfg:tt [$attrs:tt $export_name:tt [$($overrides:tt)+]] $($state:tt)*) => (
+        $crate::easy_ffi!(@opts [@emit [$attrs $export_name []] $($state)*] $cfg $($overrides)+);
+    );
+    (
+        @where $cfg:tt $attrs:tt $sig:tt $ret_ty:tt $res_ty:tt $conv:tt
+        [$($where_clause:tt)*] $next:tt $($rest:tt)+
+    ) => (
+        $crate::__easy_ffi_fn!(@where $cfg $attrs $sig $ret_ty $res_ty $conv
+            [$($where_clause)* $next] $($rest)+
+        );
+    );
+    (
+        @emit {
+            handlers: $handlers:tt
+            symbol: {
+                abi: $abi:tt prefix: $prefix:tt export: $export:tt
+                check_ffi_types: $check_ffi_types:tt improper_ctypes: $improper_ctypes:tt
+                manifest: $manifest:tt
+            }
+            errors: {
+                context: $context:tt fallback: $fallback:tt on_panic: $on_panic:tt
+                assert_unwind_safe: $assert_unwind_safe:tt last_error: $last_error:tt
+                poison: $poison:tt
+            }
+            output: {
+                returns: [$returns:tt] success: [$success:expr] true_value: [$true_value:expr]
+                false_value: [$false_value:expr] free_results: $free_results:tt
+            }
+            hooks: {
+                null_checks: $null_checks:tt before: $before:tt after: $after:tt
+                tracing: $tracing:tt stats: $stats:tt log_limit: $log_limit:tt
+                panic_tests: $panic_tests:tt
+            }
+            run: {
+                timeout: $timeout:tt stack_size: $stack_size:tt jobs: $jobs:tt
+                async_callback: $async_callback:tt runtime: $runtime:tt async_fn: $async_fn:tt
+                tokio_runtime: $tokio_runtime:tt
+            }
+            rust: { safe: $safe:tt inner: $inner:tt }
+        }
+        $attrs:tt
+        [$vis:tt $quals:tt $($sig:tt)*]
+        $ret_ty:tt $res_ty:tt [$conv:ident $val_ty:ty] $($rest:tt)*
+    ) => (
+        $crate::__easy_ffi_fn!(@shape $returns $conv [$success, $true_value, $false_value] $quals [$val_ty] {
+            handlers: $handlers abi: $abi prefix: $prefix context: $context
+            fallback: $fallback on_panic: $on_panic assert_unwind_safe: $assert_unwind_safe
+            last_error: $last_error conv: $conv
+            free_results: [$free_results $returns [$val_ty]] export: $export
+            null_checks: $null_checks before: $before after: $after poison: $poison
+            tracing: $tracing stats: $stats log_limit: $log_limit panic_tests: $panic_tests timeout: $timeout stack_size: $stack_size jobs: $jobs async_callback: $async_callback runtime: $runtime async_fn: $async_fn tokio_runtime: $tokio_runtime safe: $safe inner: $inner check_ffi_types: $check_ffi_types improper_ctypes: $improper_ctypes manifest: $manifest
+        } $attrs [$vis $($sig)*] $ret_ty $res_ty $($rest)*);
+    );
+    // The shape of the generated function: any extra parameters, the return
+    // type, and how the ok value is returned.
+    (@shape "value" $conv:ident $codes:tt $quals:tt [$ret_ty:ty] $($rest:tt)*) => (
+        $crate::__easy_ffi_fn!(@expand [] [$ret_ty] [value] $quals $($rest)*);
+    );
+    // The ok value is written through a pointer, so the function is unsafe.
+    (@shape "out_param" $conv:ident [$success:expr, $($codes:tt)*] $quals:tt [$ret_ty:ty] $($rest:tt)*) => (
+        $crate::__easy_ffi_fn!(@expand
+            [out: *mut $ret_ty]
+            [::std::os::raw::c_int] [out_param out $success]
+            [unsafe] $($rest)*
+        );
+    );
+    (@shape "tagged" $conv:ident $codes:tt $quals:tt [$ret_ty:ty] $($rest:tt)*) => (
+        $crate::__easy_ffi_fn!(@expand [] [$crate::FfiResult<$ret_ty>] [tagged] $quals $($rest)*);
+    );
+    (@shape "status" $conv:ident [$success:expr, $($codes:tt)*] $quals:tt [$ret_ty:ty] $($rest:tt)*) => (
+        $crate::__easy_ffi_fn!(@expand [] [::std::os::raw::c_int] [status $success] $quals $($rest)*);
+    );
+    (
+        @shape "bool" $conv:ident [$success:expr, $true_value:expr, $false_value:expr]
+        $quals:tt [$ret_ty:ty] $($rest:tt)*
+    ) => (
+        $crate::__easy_ffi_fn!(@expand
+            [] [::std::os::raw::c_int] [bool $true_value, $false_value]
+            $quals $($rest)*
+        );
+    );
+    // The string is copied into a buffer from the caller, which is unsafe just
+    // like an out parameter.
+    (@shape "buffer" string $codes:tt $quals:tt [$ret_ty:ty] $($rest:tt)*) => (
+        $crate::__easy_ffi_fn!(@expand
+            [buf: *mut ::std::os::raw::c_char, len: usize]
+            [::std::os::raw::c_int] [buffer buf len]
+            [unsafe] $($rest)*
+        );
+    );
+    (@shape "scratch" string $codes:tt $quals:tt [$ret_ty:ty] $($rest:tt)*) => (
+        $crate::__easy_ffi_fn!(@expand [] [*const ::std::os::raw::c_char] [scratch] $quals $($rest)*);
+    );
+    (@shape "buffer" $conv:ident $($rest:tt)*) => (
+        compile_error!("`returns = \"buffer\"` is only for functions returning `Result<String, E>`");
+    );
+    (@shape "scratch" $conv:ident $($rest:tt)*) => (
+        compile_error!("`returns = \"scratch\"` is only for functions returning `Result<String, E>`");
+    );
+    (@shape $returns:tt $($rest:tt)*) => (
+        compile_error!(concat!(
+            "`returns` must be \"value\", \"out_param\", \"tagged\", \"status\", \"bool\", ",
+            "\"buffer\" or \"scratch\", not ",
+            stringify!($returns)
+        ));
+    );
+    (
+        @expand [$($extra:tt)*] [$ffi_ty:ty] $ok:tt [$($quals:tt)*]
+        {
+            handlers: $handlers:tt
+            abi: [$abi:tt]
+            prefix: $prefix:tt
+            context: $context:tt
+            fallback: $fallback:tt
+            on_panic: $on_panic:tt
+            assert_unwind_safe: $assert_unwind_safe:tt
+            last_error: $last_error:tt
+            conv: $conv:ident
+            free_results: [$free_results:tt $returns:tt $free_ty:tt]
+            export: $export:tt
+            null_checks: $null_checks:tt
+            before: $before:tt
+            after: $after:tt
+            poison: $poison:tt
+            tracing: $tracing:tt
+            stats: $stats:tt
+            log_limit: $log_limit:tt
+            panic_tests: $panic_tests:tt
+            timeout: $timeout:tt
+            stack_size: $stack_size:tt
+            jobs: $jobs:tt
+            async_callback: $async_callback:tt
+            runtime: $runtime:tt
+            async_fn: $async_fn:tt
+            tokio_runtime: $tokio_runtime:tt
+            safe: $safe:tt
+            inner: $inner:tt
+            check_ffi_types: $check_ffi_types:tt
+            improper_ctypes: $improper_ctypes:tt
+            manifest: $manifest:tt
+        }
+        [[$($attr:tt)*] $export_name:tt $overrides:tt]
+        [
+            [$($vis:tt)*] $fn_name:ident [$($generics:tt)*]
+            [$($param:tt)*] [$($arg_conv:tt)*] [$($arg:ident)*] [[$($ptr:ident)*] [$($wipe:tt)*] $lens:tt]
+            $decl:tt
+        ]
+        [$ret_ty:ty] [$res_ty:ty] [$($where_clause:tt)*]
+        $body:block
+    ) => ($crate::__easy_ffi_fn!(@maybe_result_free $export $free_results $returns $conv $free_ty $export_name $prefix $fn_name);
+    $crate::__easy_ffi_fn!(@jobs $jobs $export [$($extra)*] [$ffi_ty] [$($param)*] $export_name $prefix $fn_name);
+    $crate::__easy_ffi_fn!(@async_callback $async_callback [$abi] $export [$($extra)*] [$ffi_ty] [$($param)*] $export_name $prefix $fn_name);
+    $crate::__easy_ffi_fn!(@manifest $manifest $export [$($attr)*] [$($param)* $($extra)*] [$ffi_ty]
+        $export_name $prefix $fn_name
+    );
+    $crate::__easy_ffi_fn!(@panic_test $panic_tests $fn_name);
+    $crate::__easy_ffi_fn!(@rust_fns [$safe $inner] $decl [$($vis)*] $fn_name [$($generics)*]
+        [$ret_ty] [$res_ty] [$($where_clause)*] [$poison $before $async_fn $runtime $tokio_runtime]
+        $body
+    );
+    $crate::__easy_ffi_fn!(@improper_ctypes $improper_ctypes [$export $export_name $prefix $fn_name] {
+        $($attr)*
+        $($vis)* $($quals)* extern $abi fn $fn_name $($generics)* (
+            $($param)* $($extra)*
+        ) -> $ffi_ty $($where_clause)* {
+            $crate::__easy_ffi_fn!(@check_ffi_types $check_ffi_types [$ffi_ty] [$($param)*]);
+            $crate::clear_panic_report();
+            // Everything from here on is caught too, so that a panic while
+            // formatting the arguments for the span, cloning them for the
+            // handlers, or in a handler, goes to the fallback rather than
+            // into C.
+            let call = move || -> $ffi_ty {
+                $crate::__easy_ffi_fn!(@trace_span $tracing $fn_name [$($arg)*]);
+                $crate::__easy_ffi_fn!(@count_call $stats stats $fn_name);
+                let metrics_started = $crate::metrics_call(stringify!($fn_name));
+                $crate::__easy_ffi_fn!(@capture [$($arg)*] $handlers);
+                $crate::__easy_ffi_fn!(@start_timer $after started);
+                // With `panic = "abort"` there's nothing to catch, so the
+                // `catch_unwind`s are left out.
+                #[cfg(not(panic = "abort"))]
+                let safe_res:
+                    ::std::result::Result<$ret_ty, $crate::FfiFailure<<$res_ty as $crate::IntoResult>::Err>> =
+                    $crate::__easy_ffi_fn!(@catch $assert_unwind_safe move || -> $res_ty {
+                        $crate::__easy_ffi_fn!(@inject_panic $panic_tests $fn_name);
+                        $crate::__easy_ffi_fn!(@check_poison $poison $fn_name);
+                        $crate::__easy_ffi_fn!(@before $before $fn_name);
+                        $crate::__easy_ffi_fn!(@null_checks $null_checks $($ptr)*);
+                        $crate::__easy_ffi_fn!(@timeout $timeout $fn_name $res_ty {
+                            $crate::__easy_ffi_fn!(@stack_size $stack_size $fn_name $res_ty {
+                                $($arg_conv)*
+                                $crate::__easy_ffi_fn!(@block_on $async_fn $runtime $tokio_runtime $fn_name $res_ty $body)
+                            })
+                        })
+                    })
+                        .map_err($crate::FfiFailure::Panic)
+                        .and_then(|ok| $crate::IntoResult::into_result(ok).map_err($crate::FfiFailure::Error));
+                #[cfg(panic = "abort")]
+                let safe_res:
+                    ::std::result::Result<$ret_ty, $crate::FfiFailure<<$res_ty as $crate::IntoResult>::Err>> =
+                    $crate::IntoResult::into_result((move || -> $res_ty {
+                        $crate::__easy_ffi_fn!(@check_poison $poison $fn_name);
+                        $crate::__easy_ffi_fn!(@before $before $fn_name);
+                        $crate::__easy_ffi_fn!(@null_checks $null_checks $($ptr)*);
+                        $crate::__easy_ffi_fn!(@timeout $timeout $fn_name $res_ty {
+                            $crate::__easy_ffi_fn!(@stack_size $stack_size $fn_name $res_ty {
+                                $($arg_conv)*
+                                $crate::__easy_ffi_fn!(@block_on $async_fn $runtime $tokio_runtime $fn_name $res_ty $body)
+                            })
+                        })
+                    })())
+                        .map_err($crate::FfiFailure::Error);
+                $crate::__easy_ffi_fn!(@trace_failure $tracing safe_res);
+                $crate::__easy_ffi_fn!(@poison_on_panic $poison safe_res);
+                $crate::__easy_ffi_fn!(@try_conv $conv $ok safe_res);
+                $crate::__easy_ffi_fn!(@count_result $stats stats safe_res);
+                $crate::metrics_result(stringify!($fn_name), metrics_started, &safe_res);
+                $crate::__easy_ffi_fn!(@zeroize safe_res $($wipe)*);
+                $crate::__easy_ffi_fn!(@on_panic $on_panic $fn_name safe_res);
+                $crate::__easy_ffi_fn!(@after $after $fn_name started safe_res);
+                $crate::__easy_ffi_fn!(@log_limit $log_limit $handlers _log_limit safe_res);
+                $crate::__easy_ffi_fn!(@err $ok
+                    $crate::__easy_ffi_fn!(@handle $fn_name safe_res $ok $conv $context $last_error $handlers)
+                )
+            };
+            #[cfg(panic = "abort")]
+            let ret = call();
+            #[cfg(not(panic = "abort"))]
+            let ret = match ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(call)) {
+                Ok(x) => x,
+                Err(panic_val) => {
+                    // Dropping the payload could panic again.
+                    ::std::mem::forget(panic_val);
+                    $crate::__easy_ffi_fn!(@fallback $fallback)
+                }
+            };
+            ret
+        }
+    }););
+    // Functions that aren't exported have nothing to export a destructor
+    // alongside.
+    (@maybe_result_free [true] $($rest:tt)*) => (
+        $crate::__easy_ffi_fn!(@result_free $($rest)*);
+    );
+    (@maybe_result_free [false] [false] $($rest:tt)*) => ();
+    (@maybe_result_free [false] $($rest:tt)*) => (
+        compile_error!("`free_results` is only for exported functions");
+    );
+    (@maybe_result_free $export:tt $($rest:tt)*) => ();
+    // With `free_results = true`, functions returning owned pointers get a
+    // destructor exported alongside them. It's only reachable from C, since
+    // there's no way to make up its Rust name.
+    (@result_free [false] $($rest:tt)*) => ();
+    (@result_free [true] "buffer" $($rest:tt)*) => ();
+    (@result_free [true] "scratch" $($rest:tt)*) => ();
+    (@result_free [true] $returns:tt value $($rest:tt)*) => ();
+    (@result_free [true] $returns:tt string $ptr_ty:tt $export_name:tt $prefix:tt $fn_name:ident) => (
+        const _: () = {
+            #[export_name = concat!($crate::__easy_ffi_fn!(@symbol_name $export_name $prefix $fn_name), "_result_free")]
+            pub unsafe extern "C" fn result_free(s: *mut ::std::os::raw::c_char) {
+                $crate::free_string(concat!(stringify!($fn_name), "_result_free"), s);
+            }
+        };
+    );
+    (@result_free [true] $returns:tt $conv:ident [$ptr_ty:ty] $export_name:tt $prefix:tt $fn_name:ident) => (
+        const _: () = {
+            #[export_name = concat!($crate::__easy_ffi_fn!(@symbol_name $export_name $prefix $fn_name), "_result_free")]
+            pub unsafe extern "C" fn result_free(ptr: $ptr_ty) {
+                $crate::drop_boxed(concat!(stringify!($fn_name), "_result_free"), ptr, true);
+            }
+        };
+    );
+    (@result_free [$free_results:tt] $($rest:tt)*) => (
+        compile_error!(concat!("`free_results` must be true or false, not ", stringify!($free_results)));
+    );
+    // With `manifest = true`, exported functions are listed by
+    // `easy_ffi::exports()`.
+    (@manifest [false] $($rest:tt)*) => ();
+    (@manifest [true] [false] $($rest:tt)*) => ();
+    (
+        @manifest [true] [true] [$($attr:tt)*] [$($param:ident : $param_ty:ty),* $(,)?] [$ffi_ty:ty]
+        $export_name:tt $prefix:tt $fn_name:ident
+    ) => (
+        $crate::__easy_ffi_manifest!($crate::FfiExport::new(
+            $crate::__easy_ffi_fn!(@symbol_name $export_name $prefix $fn_name),
+            stringify!($fn_name),
+            &[$($crate::FfiParam::new(stringify!($param), stringify!($param_ty))),*],
+            stringify!($ffi_ty),
+            $crate::__easy_ffi_fn!(@doc [] $($attr)*),
+        ));
+    );
+    (@manifest [$manifest:tt] $($rest:tt)*) => (
+        compile_error!(concat!("`manifest` must be true or false, not ", stringify!($manifest)));
+    );
+    // The doc comment's lines, joined up.
+    (@doc [$($line:literal)*] #[doc = $more:literal] $($rest:tt)*) => (
+        $crate::__easy_ffi_fn!(@doc [$($line)* $more] $($rest)*)
+    );
+    (@doc $lines:tt #[$($attr:tt)*] $($rest:tt)*) => (
+        $crate::__easy_ffi_fn!(@doc $lines $($rest)*)
+    );
+    (@doc []) => ("");
+    (@doc [$first:literal $($line:literal)*]) => (concat!($first $(, "\n", $line)*));
+    // With `safe = true` or `inner = true`, the body is also put in Rust
+    // functions, in a module named after the function, since there's no
+    // making up names for them.
+    (@rust_fns [[false] [false]] $($rest:tt)*) => ();
+    (@rust_fns [$safe:tt $inner:tt] $decl:tt [$($vis:tt)*] $fn_name:ident $($rest:tt)*) => (
+        // The functions are as visible as the module, whatever their own
+        // visibility says.
+        #[allow(private_interfaces)]
+        $($vis)* mod $fn_name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::__easy_ffi_fn!(@safe $safe $decl $fn_name $($rest)*);
+            $crate::__easy_ffi_fn!(@inner $inner $decl $fn_name $($rest)*);
+        }
+    );
+    // `safe` takes the arguments as declared, and does what the generated
+    // function does with them, short of calling the handlers.
+    (@safe [false] $($rest:tt)*) => ();
+    (
+        @safe [true] [[$($quals:tt)*] [$($(#[$($arg_attr:tt)*])* $arg:ident : $arg_ty:ty),* $(,)?]]
+        $fn_name:ident [$($generics:tt)*]
+        [$ret_ty:ty] [$res_ty:ty] [$($where_clause:tt)*]
+        [$poison:tt $before:tt $async_fn:tt $runtime:tt $tokio_runtime:tt]
+        $body:block
+    ) => (
+        /// Runs the body like the generated function, but with Rust
+        /// arguments, returning its result instead of passing failures to the
+        /// handlers.
+        pub $($quals)* fn safe $($generics)* ($($arg: $arg_ty),*) -> ::std::result::Result<
+            $ret_ty,
+            $crate::FfiFailure<<$res_ty as $crate::IntoResult>::Err>,
+        > $($where_clause)* {
+            ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(move || -> $res_ty {
+                $crate::__easy_ffi_fn!(@check_poison $poison $fn_name);
+                $crate::__easy_ffi_fn!(@before $before $fn_name);
+                $crate::__easy_ffi_fn!(@block_on $async_fn $runtime $tokio_runtime $fn_name $res_ty $body)
+            }))
+                .map_err($crate::FfiFailure::Panic)
+                .and_then(|ok| $crate::IntoResult::into_result(ok).map_err($crate::FfiFailure::Error))
+        }
+    );
+    (@safe [$safe:tt] $($rest:tt)*) => (
+        compile_error!(concat!("`safe` must be true or false, not ", stringify!($safe)));
+    );
+    // `inner` is just the body, as it was written, for the crate's own tests.
+    (@inner [false] $($rest:tt)*) => ();
+    (
+        @inner [true] [[$($quals:tt)*] [$($(#[$($arg_attr:tt)*])* $arg:ident : $arg_ty:ty),* $(,)?]]
+        $fn_name:ident [$($generics:tt)*]
+        [$ret_ty:ty] [$res_ty:ty] [$($where_clause:tt)*]
+        [$poison:tt $before:tt [false] $runtime:tt $tokio_runtime:tt]
+        $body:block
+    ) => (
+        /// The body of the generated function, with Rust arguments.
+        // It's usually only called from tests.
+        #[allow(dead_code)]
+        pub(super) $($quals)* fn inner $($generics)* ($($arg: $arg_ty),*) -> $res_ty
+        $($where_clause)* $body
+    );
+    (
+        @inner [true] [[$($quals:tt)*] [$($(#[$($arg_attr:tt)*])* $arg:ident : $arg_ty:ty),* $(,)?]]
+        $fn_name:ident [$($generics:tt)*]
+        [$ret_ty:ty] [$res_ty:ty] [$($where_clause:tt)*]
+        [$poison:tt $before:tt [true] $runtime:tt $tokio_runtime:tt]
+        $body:block
+    ) => (
+        /// The body of the generated function, with Rust arguments.
+        // It's usually only called from tests.
+        #[allow(dead_code)]
+        pub(super) async $($quals)* fn inner $($generics)* ($($arg: $arg_ty),*) -> $res_ty
+        $($where_clause)* $body
+    );
+    (@inner [$inner:tt] $($rest:tt)*) => (
+        compile_error!(concat!("`inner` must be true or false, not ", stringify!($inner)));
+    );
+    // With `jobs = true`, the function can also be run on the job pool, with
+    // companions exported under its name with suffixes. Like destructors,
+    // they're only reachable from C.
+    (@jobs [false] $($rest:tt)*) => ();
+    (
+        @jobs [true] [true] [] [$ffi_ty:ty] [$($param:ident : $param_ty:ty,)*]
+        $export_name:tt $prefix:tt $fn_name:ident
+    ) => (
+        const _: () = {
+            #[export_name = concat!($crate::__easy_ffi_fn!(@symbol_name $export_name $prefix $fn_name), "_start")]
+            pub unsafe extern "C" fn start($($param: $param_ty),*) -> *mut $crate::FfiJob<$ffi_ty> {
+                $crate::job_start(concat!(stringify!($fn_name), "_start"), move || {
+                    #[allow(unused_unsafe)]
+                    unsafe { $fn_name($($param),*) }
+                })
+            }
+
+            #[export_name = concat!($crate::__easy_ffi_fn!(@symbol_name $export_name $prefix $fn_name), "_poll")]
+            pub unsafe extern "C" fn poll(job: *const $crate::FfiJob<$ffi_ty>) -> ::std::os::raw::c_int {
+                $crate::job_poll(job)
+            }
+
+            #[export_name = concat!($crate::__easy_ffi_fn!(@symbol_name $export_name $prefix $fn_name), "_result")]
+            pub unsafe extern "C" fn result(
+                job: *const $crate::FfiJob<$ffi_ty>,
+                out: *mut $ffi_ty,
+            ) -> ::std::os::raw::c_int {
+                $crate::job_result(job, out)
+            }
+
+            #[export_name = concat!($crate::__easy_ffi_fn!(@symbol_name $export_name $prefix $fn_name), "_cancel")]
+            pub unsafe extern "C" fn cancel(job: *const $crate::FfiJob<$ffi_ty>) -> ::std::os::raw::c_int {
+                $crate::job_cancel(job)
+            }
+
+            #[export_name = concat!($crate::__easy_ffi_fn!(@symbol_name $export_name $prefix $fn_name), "_free")]
+            pub unsafe extern "C" fn free(job: *mut $crate::FfiJob<$ffi_ty>) {
+                $crate::job_free(concat!(stringify!($fn_name), "_free"), job)
+            }
+        };
+    );
+    (@jobs [true] [true] $extra:tt $($rest:tt)*) => (
+        compile_error!("`jobs` is only for functions that return their results, not through parameters");
+    );
+    (@jobs [true] $export:tt $($rest:tt)*) => (
+        compile_error!("`jobs` is only for exported functions");
+    );
+    (@jobs [$jobs:tt] $($rest:tt)*) => (
+        compile_error!(concat!("`jobs` must be true or false, not ", stringify!($jobs)));
+    );
+    // With `async_callback = true`, there's another companion that runs it
+    // on the job pool, and calls back with the result.
+    (@async_callback [false] $($rest:tt)*) => ();
+    (
+        @async_callback [true] [$abi:tt] [true] [] [$ffi_ty:ty] [$($param:ident : $param_ty:ty,)*]
+        $export_name:tt $prefix:tt $fn_name:ident
+    ) => (
+        const _: () = {
+            #[export_name = concat!($crate::__easy_ffi_fn!(@symbol_name $export_name $prefix $fn_name), "_async")]
+            pub unsafe extern $abi fn run_async(
+                $($param: $param_ty,)*
+                done: ::std::option::Option<extern $abi fn($ffi_ty, *mut ::std::os::raw::c_void)>,
+                user_data: *mut ::std::os::raw::c_void,
+            ) -> bool {
+                let f = move || {
+                    #[allow(unused_unsafe)]
+                    unsafe { $fn_name($($param),*) }
+                };
+                // `done` has the function's ABI, which the pool doesn't know.
+                let done = done.map(|done| move |ret, user_data| done(ret, user_data));
+                $crate::run_with_callback(concat!(stringify!($fn_name), "_async"), f, done, user_data)
+            }
+        };
+    );
+    (@async_callback [true] $abi:tt [true] $extra:tt $($rest:tt)*) => (
+        compile_error!("`async_callback` is only for functions that return their results, not through parameters");
+    );
+    (@async_callback [true] $abi:tt $export:tt $($rest:tt)*) => (
+        compile_error!("`async_callback` is only for exported functions");
+    );
+    (@async_callback [$async_callback:tt] $($rest:tt)*) => (
+        compile_error!(concat!("`async_callback` must be true or false, not ", stringify!($async_callback)));
+    );
+    // How the ok value is returned, and what's done with the handler's
+    // return value.
+    (@ok [value] $x:ident) => ($x);
+    (@ok [tagged] $x:ident) => ($crate::FfiResult::ok($x));
+    (@err [tagged] $failed:expr) => ($crate::FfiResult::err($failed));
+    (@err $ok:tt $failed:expr) => ($failed);
+    (@ok [out_param $out:ident $success:expr] $x:ident) => ({
+        if !$out.is_null() {
+            ::std::ptr::write($out, $x);
+        }
+        $success
+    });
+    (@ok [status $success:expr] $x:ident) => ({
+        let () = $x;
+        $success
+    });
+    (@ok [bool $true_value:expr, $false_value:expr] $x:ident) => (
+        if $x { $true_value } else { $false_value }
+    );
+    // Conversions that can fail happen before the handlers run, so that they
+    // see the failures.
+    // A buffer gets the string's bytes as they are, so it isn't converted.
+    (@try_conv string [buffer $($buffer:tt)*] $safe_res:ident) => ();
+    (@try_conv string $ok:tt $safe_res:ident) => (
+        let $safe_res = $safe_res.and_then(|s| {
+            ::std::ffi::CString::new(s)
+                .map_err(|err| $crate::FfiFailure::Error(::std::convert::From::from(err)))
+        });
+    );
+    (@try_conv $conv:ident $ok:tt $safe_res:ident) => ();
+    // How the ok value becomes an ffi type, and is then returned.
+    (@convert string [buffer $buf:ident $len:ident] $fn_name:ident $x:ident) => (
+        $crate::fill_buffer(&$x, $buf, $len)
+    );
+    (@convert string [scratch] $fn_name:ident $x:ident) => ($crate::store_scratch($x));
+    (@convert $conv:ident $ok:tt $fn_name:ident $x:ident) => ({
+        let $x = $crate::__easy_ffi_fn!(@conv $conv $fn_name $x);
+        $crate::__easy_ffi_fn!(@ok $ok $x)
+    });
+    (@conv value $fn_name:ident $x:ident) => ($x);
+    (@conv string $fn_name:ident $x:ident) => ({
+        let ptr = ::std::ffi::CString::into_raw($x);
+        $crate::mark_allocated(stringify!($fn_name), ptr);
+        ptr
+    });
+    (@conv boxed $fn_name:ident $x:ident) => ({
+        let ptr = ::std::boxed::Box::into_raw($x);
+        $crate::mark_allocated(stringify!($fn_name), ptr);
+        ptr
+    });
+    (@conv option_box $fn_name:ident $x:ident) => ({
+        let ptr = $x.map_or(::std::ptr::null_mut(), ::std::boxed::Box::into_raw);
+        $crate::mark_allocated(stringify!($fn_name), ptr);
+        ptr
+    });
+    // Handlers that take the arguments get clones of them, made before the
+    // body can consume them.
+    (
+        @capture $args:tt [
+            |$err:ident $err_fn_name:tt [$($err_args:ident)?]| $err_body:tt
+            |$panic:ident $panic_fn_name:tt [$($panic_args:ident)?]| $panic_body:tt
+        ]
+    ) => (
+        $(#[allow(unused_variables)] let $err_args = $crate::__easy_ffi_fn!(@clone $args);)?
+        $(let $panic_args = $crate::__easy_ffi_fn!(@clone $args);)?
+    );
+    (@capture $args:tt [|$failure:ident $failure_fn_name:tt [$($failure_args:ident)?]| $failure_body:tt]) => (
+        $(let $failure_args = $crate::__easy_ffi_fn!(@clone $args);)?
+    );
+    (@capture $args:tt [default]) => ();
+    (@capture $args:tt [anyhow]) => ();
+    (@capture $args:tt [eyre]) => ();
+    (@capture $args:tt [log $log_target:tt]) => ();
+    (@capture $args:tt [error_code]) => ();
+    (@capture $args:tt [error_chain]) => ();
+    (@clone [$($arg:ident)*]) => (
+        ($(::std::clone::Clone::clone(&$arg),)*)
+    );
+    (
+        @handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt $last_error:tt [
+            |$err:ident [$($err_fn_name:ident)?] $err_args:tt| $err_body:tt
+            |$panic:ident [$($panic_fn_name:ident)?] $panic_args:tt| $panic_body:tt
+        ]
+    ) => ({
+        // Both handlers may use the same names, in which case one shadows the
+        // other.
+        $(#[allow(unused_variables)] let $err_fn_name: &'static str = stringify!($fn_name);)?
+        $(let $panic_fn_name: &'static str = stringify!($fn_name);)?
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err($crate::FfiFailure::Error($err)) => $crate::__easy_ffi_fn!(@context $context $err_body),
+            Err($crate::FfiFailure::Panic($panic)) => $crate::__easy_ffi_fn!(@context $context $panic_body),
+        }
+    });
+    (
+        @handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt $last_error:tt [
+            |$failure:ident [$($failure_fn_name:ident)?] $failure_args:tt| $failure_body:tt
+        ]
+    ) => ({
+        $(let $failure_fn_name: &'static str = stringify!($fn_name);)?
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err($failure) => $crate::__easy_ffi_fn!(@context $context $failure_body),
+        }
+    });
+    // Without handlers, failures are logged, or recorded as the last error,
+    // and `FfiDefault` is returned.
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [] [default]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::default_handler(stringify!($fn_name), failure),
+        }
+    );
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [$last_error:path] [default]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::last_error_handler(stringify!($fn_name), $last_error, failure),
+        }
+    );
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [] [error_code]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::error_code_default_handler(
+                stringify!($fn_name),
+                failure,
+                $crate::__easy_ffi_fn!(@warning $ok),
+            ),
+        }
+    );
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [$last_error:path] [error_code]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::error_code_handler(
+                stringify!($fn_name),
+                $last_error,
+                failure,
+                $crate::__easy_ffi_fn!(@warning $ok),
+            ),
+        }
+    );
+    // What `handlers = "error_code"` returns for warnings: only a status has
+    // a success value to give.
+    (@warning [status $success:expr]) => (::std::option::Option::Some($success));
+    (@warning $ok:tt) => (::std::option::Option::None);
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [] [error_chain]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::error_chain_handler(stringify!($fn_name), failure),
+        }
+    );
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [$last_error:path] [error_chain]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::error_chain_last_error_handler(stringify!($fn_name), $last_error, failure),
+        }
+    );
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [] [anyhow]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::anyhow_handler(stringify!($fn_name), failure),
+        }
+    );
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [$last_error:path] [anyhow]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::anyhow_last_error_handler(stringify!($fn_name), $last_error, failure),
+        }
+    );
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [] [eyre]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::eyre_handler(stringify!($fn_name), failure),
+        }
+    );
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [$last_error:path] [eyre]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::eyre_last_error_handler(stringify!($fn_name), $last_error, failure),
+        }
+    );
+    (@handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt [] [log $log_target:tt]) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::log_handler(
+                $crate::__easy_ffi_fn!(@log_target $log_target),
+                stringify!($fn_name),
+                failure,
+            ),
+        }
+    );
+    (
+        @handle $fn_name:ident $safe_res:ident $ok:tt $conv:ident $context:tt
+        [$last_error:path] [log $log_target:tt]
+    ) => (
+        match $safe_res {
+            Ok(x) => return $crate::__easy_ffi_fn!(@convert $conv $ok $fn_name x),
+            Err(failure) => $crate::log_last_error_handler(
+                $crate::__easy_ffi_fn!(@log_target $log_target),
+                stringify!($fn_name),
+                $last_error,
+                failure,
+            ),
+        }
+    );
+    // Like `log!` itself, the target defaults to the caller's module.
+    (@log_target []) => (module_path!());
+    (@log_target [$log_target:literal]) => ($log_target);
+    (@context [] $body:tt) => ($body);
+    (@context [$ctx:ident $ctx_expr:expr] { $($body:tt)* }) => ({
+        let $ctx = $ctx_expr;
+        $($body)*
+    });
+    (@context [$ctx:ident $ctx_expr:expr] $body:tt) => ({
+        let $ctx = $ctx_expr;
+        $body
+    });
+    (@catch [false] $closure:expr) => (::std::panic::catch_unwind($closure));
+    (@catch [true] $closure:expr) => (::std::panic::catch_unwind(::std::panic::AssertUnwindSafe($closure)));
+    (@catch [$assert_unwind_safe:tt] $closure:expr) => (
+        compile_error!(concat!(
+            "`assert_unwind_safe` must be true or false, not ",
+            stringify!($assert_unwind_safe)
+        ))
+    );
+    (@on_panic ["handle"] $fn_name:ident $safe_res:ident) => ();
+    (@on_panic ["abort"] $fn_name:ident $safe_res:ident) => (
+        let $safe_res = match $safe_res {
+            Err($crate::FfiFailure::Panic(panic_val)) => {
+                $crate::abort_on_panic(stringify!($fn_name), panic_val)
+            }
+            other => other,
+        };
+    );
+    (@on_panic ["defer"] $fn_name:ident $safe_res:ident) => (
+        let $safe_res = match $safe_res {
+            Err($crate::FfiFailure::Panic(panic_val)) => {
+                Err($crate::FfiFailure::Panic($crate::defer_panic(panic_val)))
+            }
+            other => other,
+        };
+    );
+    (@on_panic ["error"] $fn_name:ident $safe_res:ident) => (
+        let $safe_res = match $safe_res {
+            Err($crate::FfiFailure::Panic(panic_val)) => {
+                Err($crate::FfiFailure::Error(::std::convert::From::from(panic_val)))
+            }
+            other => other,
+        };
+    );
+    (@on_panic [["error" $panic_error:expr]] $fn_name:ident $safe_res:ident) => (
+        let $safe_res = match $safe_res {
+            Err($crate::FfiFailure::Panic(panic_val)) => {
+                Err($crate::FfiFailure::Error($crate::panic_to_error(panic_val, $panic_error)))
+            }
+            other => other,
+        };
+    );
+    (@on_panic [$on_panic:tt] $fn_name:ident $safe_res:ident) => (
+        compile_error!(concat!(
+            "`on_panic` must be \"handle\", \"abort\", \"defer\" or \"error\", not ",
+            stringify!($on_panic)
+        ));
+    );
+    (@check nonnull $arg:ident []) => (
+        $crate::check_non_null(stringify!($arg), $arg)?;
+    );
+    (@check nonzero $arg:ident []) => (
+        $crate::check_non_zero(stringify!($arg), &$arg)?;
+    );
+    (@check utf8 $arg:ident []) => (
+        #[allow(unused_unsafe)]
+        unsafe { $crate::str_arg(stringify!($arg), $arg) }?;
+    );
+    (@check range $arg:ident [($($range:tt)+)]) => (
+        $crate::check_range(stringify!($arg), stringify!($($range)+), &$arg, $($range)+)?;
+    );
+    (@check $check:ident $arg:ident $check_args:tt) => (
+        compile_error!(concat!(
+            "unknown check `#[", stringify!($check), "]` on `", stringify!($arg), "`, expected ",
+            "`#[nonnull]`, `#[nullable]`, `#[nonzero]`, `#[utf8]`, `#[range(...)]` or `#[zeroize]`",
+        ));
+    );
+    (@before [] $fn_name:ident) => ();
+    (@before [$before:expr] $fn_name:ident) => (
+        ($before)(stringify!($fn_name))?;
+    );
+    (@start_timer [] $started:ident) => ();
+    (@start_timer [$after:expr] $started:ident) => (
+        let $started = ::std::time::Instant::now();
+    );
+    (@after [] $fn_name:ident $started:ident $safe_res:ident) => ();
+    (@after [$after:expr] $fn_name:ident $started:ident $safe_res:ident) => (
+        ($after)(stringify!($fn_name), &$safe_res, $started.elapsed());
+    );
+    // The types are checked by functions that only take `FfiSafe` types, so
+    // the errors point at the types themselves. Each parameter gets a type
+    // named after it, which the error for it mentions.
+    (@check_ffi_types [false] $($rest:tt)*) => ();
+    (@check_ffi_types [true] [$ffi_ty:ty] [$($param:ident : $param_ty:ty,)*]) => (
+        $crate::check_ffi_safe::<$ffi_ty>();
+        $({
+            #[allow(non_camel_case_types, dead_code)]
+            struct $param;
+            $crate::check_ffi_arg::<$param_ty, $param>();
+        })*
+    );
+    (@check_ffi_types [$check_ffi_types:tt] $($rest:tt)*) => (
+        compile_error!(concat!(
+            "`check_ffi_types` must be true or false, not ", stringify!($check_ffi_types),
+        ));
+    );
+    (@null_checks [false] $($ptr:ident)*) => ();
+    (@null_checks [true] $($ptr:ident)*) => (
+        $($crate::check_non_null(stringify!($ptr), $ptr)?;)*
+    );
+    (@null_checks [$null_checks:tt] $($ptr:ident)*) => (
+        compile_error!(concat!("`null_checks` must be true or false, not ", stringify!($null_checks)));
+    );
+    (@trace_span [false] $fn_name:ident $args:tt) => ();
+    (@trace_span [true] $fn_name:ident [$($arg:ident)*]) => (
+        let mut args = ::std::string::String::new();
+        {
+            #[allow(unused_imports)]
+            use $crate::{SummarizeAny as _, SummarizeDebug as _};
+            $($crate::push_arg(&mut args, stringify!($arg), (&$crate::Summarize(&$arg)).summarize());)*
+        }
+        // Entered for the rest of the call, handlers included.
+        let _span = $crate::__tracing::info_span!(stringify!($fn_name), args = %args).entered();
+    );
+    (@trace_span [$tracing:tt] $fn_name:ident $args:tt) => (
+        compile_error!(concat!("`tracing` must be true or false, not ", stringify!($tracing)));
+    );
+    (@trace_failure [true] $safe_res:ident) => (
+        match $safe_res {
+            Err($crate::FfiFailure::Error(ref err)) => {
+                #[allow(unused_imports)]
+                use $crate::{SummarizeAny as _, SummarizeDebug as _};
+                let error = (&$crate::Summarize(err)).summarize();
+                $crate::__tracing::error!(error = %$crate::Redacted(&error), "returned an error");
+            }
+            Err($crate::FfiFailure::Panic(ref panic_val)) => {
+                let panic = $crate::panic_message(&**panic_val);
+                $crate::__tracing::error!(panic = %$crate::Redacted(&panic), "panicked");
+            }
+            Ok(_) => {}
+        }
+    );
+    (@trace_failure $tracing:tt $safe_res:ident) => ();
+    (@zeroize $safe_res:ident) => ();
+    (@zeroize $safe_res:ident $([$wipe:ident $len:ident])+) => (
+        if $safe_res.is_err() {
+            $(
+                #[allow(unused_unsafe)]
+                unsafe { $crate::zeroize_slice($wipe, $len) };
+            )+
+        }
+    );
+    (@count_call [false] $stats:ident $fn_name:ident) => ();
+    (@count_call [true] $stats:ident $fn_name:ident) => (
+        let $stats: &'static $crate::FnStats = {
+            static STATS: $crate::FnStats = $crate::FnStats::new(stringify!($fn_name));
+            &STATS
+        };
+        $stats.record_call();
+    );
+    (@count_call [$stats_opt:tt] $stats:ident $fn_name:ident) => (
+        compile_error!(concat!("`stats` must be true or false, not ", stringify!($stats_opt)));
+    );
+    (@count_result [true] $stats:ident $safe_res:ident) => ($stats.record_result(&$safe_res););
+    (@count_result $stats_opt:tt $stats:ident $safe_res:ident) => ();
+    (@timeout [] $fn_name:ident $res_ty:ty $body:block) => ($body);
+    (@timeout [$timeout:expr] $fn_name:ident $res_ty:ty $body:block) => (
+        $crate::run_with_timeout(stringify!($fn_name), $timeout, move || -> $res_ty { $body })?
+    );
+    (@block_on [false] [] [false] $fn_name:ident $res_ty:ty $body:block) => ($body);
+    (@block_on [false] $($rest:tt)*) => (
+        compile_error!("`runtime` is only for `async fn`s");
+    );
+    (@block_on [true] [] [false] $fn_name:ident $res_ty:ty $body:block) => (
+        $crate::block_on::<_, $res_ty, _>(&$crate::CurrentThread, async move { $body })
+    );
+    (@block_on [true] [$runtime:expr] [false] $fn_name:ident $res_ty:ty $body:block) => (
+        $crate::block_on::<_, $res_ty, _>(&$runtime, async move { $body })
+    );
+    (@block_on [true] [] [true] $fn_name:ident $res_ty:ty $body:block) => (
+        $crate::block_on::<_, $res_ty, _>(
+            &$crate::tokio_runtime(stringify!($fn_name))?,
+            async move { $body },
+        )
+    );
+    (@block_on [true] [$runtime:expr] [true] $($rest:tt)*) => (
+        compile_error!("`runtime = tokio` can't be combined with another `runtime`");
+    );
+    (@block_on [true] $runtime:tt [$tokio_runtime:tt] $($rest:tt)*) => (
+        compile_error!(concat!("`tokio_runtime` must be true or false, not ", stringify!($tokio_runtime)));
+    );
+    (@stack_size [] $fn_name:ident $res_ty:ty $body:block) => ($body);
+    (@stack_size [$stack_size:expr] $fn_name:ident $res_ty:ty $body:block) => (
+        {
+            let stack_size = $stack_size;
+            let body = move || -> $res_ty { $body };
+            // The body only has the function's arguments, which this thread
+            // doesn't touch until the other one's been joined, so they're
+            // never used from both at once.
+            unsafe { $crate::run_on_stack(stringify!($fn_name), stack_size, body) }
+        }
+    );
+    (@panic_test [true] $fn_name:ident) => (
+        #[cfg(test)]
+        $crate::__easy_ffi_panic_test!($fn_name false);
+    );
+    (@panic_test $panic_tests:tt $fn_name:ident) => ();
+    (@inject_panic [false] $fn_name:ident) => ();
+    (@inject_panic [true] $fn_name:ident) => (
+        #[cfg(test)]
+        $crate::inject_panic(stringify!($fn_name));
+    );
+    (@inject_panic [$panic_tests:tt] $fn_name:ident) => (
+        compile_error!(concat!("`panic_tests` must be true or false, not ", stringify!($panic_tests)));
+    );
+    (@log_limit [] $handlers:tt $guard:ident $safe_res:ident) => ();
+    (@log_limit [$max:expr] [|$($handlers:tt)*] $guard:ident $safe_res:ident) => (
+        compile_error!(
+            "`log_limit` only limits the built-in handlers, so handlers of your own need a `LogLimit`"
+        );
+    );
+    (@log_limit [$max:expr] $handlers:tt $guard:ident $safe_res:ident) => (
+        // Held until the handlers are done with the failure.
+        let $guard = if $safe_res.is_err() {
+            static LIMIT: $crate::LogLimit = $crate::LogLimit::new();
+            Some(LIMIT.enter($max))
+        } else {
+            None
+        };
+    );
+    (@check_poison [false] $fn_name:ident) => ();
+    (@check_poison [true] $fn_name:ident) => (
+        $crate::check_poisoned(stringify!($fn_name))?;
+    );
+    (@check_poison [$poison:tt] $fn_name:ident) => (
+        compile_error!(concat!("`poison` must be true or false, not ", stringify!($poison)));
+    );
+    (@poison_on_panic [true] $safe_res:ident) => (
+        if let Err($crate::FfiFailure::Panic(_)) = $safe_res {
+            $crate::poison();
+        }
+    );
+    (@poison_on_panic $poison:tt $safe_res:ident) => ();
+    (@fallback []) => (::std::process::abort());
+    (@fallback [$fallback:expr]) => ($fallback);
+    // `improper_ctypes` sets the lint's level on the function, rather than
+    // leaving it to whatever the crate has.
+    (@improper_ctypes [] [$($symbol:tt)*] $item:tt) => (
+        $crate::__easy_ffi_fn!(@symbol $($symbol)* $item);
+    );
+    (@improper_ctypes ["deny"] [$($symbol:tt)*] { $($item:tt)* }) => (
+        $crate::__easy_ffi_fn!(@symbol $($symbol)* { #[deny(improper_ctypes_definitions)] $($item)* });
+    );
+    (@improper_ctypes ["warn"] [$($symbol:tt)*] { $($item:tt)* }) => (
+        $crate::__easy_ffi_fn!(@symbol $($symbol)* { #[warn(improper_ctypes_definitions)] $($item)* });
+    );
+    (@improper_ctypes ["allow"] [$($symbol:tt)*] { $($item:tt)* }) => (
+        $crate::__easy_ffi_fn!(@symbol $($symbol)* { #[allow(improper_ctypes_definitions)] $($item)* });
+    );
+    (@improper_ctypes [$improper_ctypes:tt] $($rest:tt)*) => (
+        compile_error!(concat!(
+            "`improper_ctypes` must be \"deny\", \"warn\" or \"allow\", not ",
+            stringify!($improper_ctypes),
+        ));
+    );
+    (@symbol [true] [] [] $fn_name:ident { $($item:tt)* }) => (
+        #[no_mangle]
+        $($item)*
+    );
+    (@symbol [true] [] [$prefix:expr] $fn_name:ident { $($item:tt)* }) => (
+        #[export_name = concat!($prefix, stringify!($fn_name))]
+        $($item)*
+    );
+    (@symbol [true] [$export_name:expr] $prefix:tt $fn_name:ident { $($item:tt)* }) => (
+        #[export_name = $export_name]
+        $($item)*
+    );
+    (@symbol [false] $export_name:tt $prefix:tt $fn_name:ident { $($item:tt)* }) => (
+        $($item)*
+    );
+    (@symbol [$export:tt] $($rest:tt)*) => (
+        compile_error!(concat!("`export` must be true or false, not ", stringify!($export)));
+    );
+    (@symbol_name [] [] $fn_name:ident) => (stringify!($fn_name));
+    (@symbol_name [] [$prefix:expr] $fn_name:ident) => (concat!($prefix, stringify!($fn_name)));
+    (@symbol_name [$export_name:expr] $prefix:tt $fn_name:ident) => ($export_name);
+}
+
+#[cfg(test)]
+mod tests {
+    easy_ffi!(my_ffi_fn =>
+        |err| {
+            println!("{}", err);
+            -1
+        }
+        |panic_val| {
+            match panic_val.downcast_ref::<&'static str>() {
+                Some(s) => println!("panic: {}", s),
+                None => println!("unknown panic!"),
+            };
+            -1
+        }
+    );
+
+    my_ffi_fn!(
+        /// Foo: do stuff
+        fn foo(i: i32) -> Result<i32, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i <= 0 => Err("already <= 0, can't go lower"),
+                i => Ok(i - 1),
+            }
+        }
+    );
+
+    type MyResult<T> = Result<T, &'static str>;
+
+    my_ffi_fn!(
+        fn bar(i: i32) -> MyResult<i32> {
+            if i < 0 {
+                Err("negative")
+            } else {
+                Ok(i * 2)
+            }
+        }
+    );
+
+    my_ffi_fn!(
+        fn baz(i: i32) -> ::std::result::Result<i32, String> {
+            if i < 0 {
+                Err(format!("{} is negative", i))
+            } else {
+                Ok(i * 3)
+            }
+        }
+    );
+
+    my_ffi_fn!(
+        fn deref<'a, 'b: 'a>(p: Option<&'a i32>, _q: Option<&'b i32>) -> Result<i32, &'static str> {
+            p.cloned().ok_or("null pointer")
+        }
+    );
+
+    my_ffi_fn!(
+        fn deref_where<'a, 'b>(p: Option<&'a i32>, _q: Option<&'b i32>) -> Result<i32, &'static str>
+        where
+            'b: 'a,
+        {
+            p.cloned().ok_or("null pointer")
+        }
+    );
+
+    my_ffi_fn!(
+        fn deref_alias_where<'a, 'b>(p: Option<&'a i32>, _q: Option<&'b i32>) -> MyResult<i32>
+        where
+            'b: 'a + 'static,
+        {
+            p.cloned().ok_or("null pointer")
+        }
+    );
+
+    easy_ffi!(system_ffi_fn =>
+        abi = "system",
+        |err| {
+            println!("{}", err);
+            -1
+        }
+        |_panic_val| {
+            -2
+        }
+    );
+
+    system_ffi_fn!(
+        fn sys(i: i32) -> Result<i32, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i <= 0 => Err("already <= 0, can't go lower"),
+                i => Ok(i - 1),
+            }
+        }
+    );
+
+    easy_ffi!(unwind_ffi_fn =>
+        abi = "C-unwind",
+        |_err| { -1 }
+        |_panic_val| { -2 }
+    );
+
+    unwind_ffi_fn!(
+        fn unwinds(i: i32) -> Result<i32, ()> {
+            if i == 5 {
+                panic!("I'm afraid of 5's!")
+            } else {
+                Ok(i)
+            }
+        }
+    );
+
+    my_ffi_fn!(
+        /// Reads through a raw pointer, so callers must uphold the usual rules.
+        unsafe fn read_ptr(p: *const i32) -> Result<i32, &'static str> {
+            if p.is_null() {
+                Err("null pointer")
+            } else {
+                Ok(*p)
+            }
+        }
+    );
+
+    mod vis {
+        my_ffi_fn!(
+            pub(crate) fn crate_visible(i: i32) -> Result<i32, &'static str> {
+                Ok(i)
+            }
+        );
+
+        my_ffi_fn!(
+            pub(super) fn super_visible(i: i32) -> Result<i32, &'static str> {
+                Ok(i)
+            }
+        );
+
+        my_ffi_fn!(
+            pub(self) fn private(i: i32) -> Result<i32, &'static str> {
+                Ok(i)
+            }
+        );
+
+        pub fn call_private(i: i32) -> i32 {
+            private(i)
+        }
+    }
+
+    my_ffi_fn!(
+        #[export_name = "easy_ffi_test_renamed_v2"]
+        fn renamed(i: i32) -> Result<i32, &'static str> {
+            Ok(i + 2)
+        }
+    );
+
+    easy_ffi!(prefixed_ffi_fn =>
+        prefix = "easy_ffi_test_",
+        |_err| { -1 }
+        |_panic_val| { -2 }
+    );
+
+    prefixed_ffi_fn!(
+        fn prefixed(i: i32) -> Result<i32, ()> {
+            Ok(i * 10)
+        }
+    );
+
+    prefixed_ffi_fn!(
+        #[export_name = "easy_ffi_test_unprefixed"]
+        fn explicitly_named(i: i32) -> Result<i32, ()> {
+            Ok(i * 100)
+        }
+    );
+
+    my_ffi_fn! {
+        /// The first of several functions.
+        fn first(i: i32) -> Result<i32, &'static str> { Ok(i + 1) }
+
+        fn second<'a>(p: Option<&'a i32>) -> MyResult<i32> {
+            p.cloned().ok_or("null pointer")
+        }
+
+        pub(crate) unsafe fn third(p: *const i32) -> Result<i32, &'static str> {
+            Ok(*p)
+        }
+    }
+
+    fn mod_err_code() -> i32 {
+        -3
+    }
+
+    easy_ffi_mod!(
+        prefix = "easy_ffi_test_mod_",
+        |_err| { mod_err_code() }
+        |_panic_val| { -4 }
+        /// A whole module of exports.
+        mod exports {
+            fn halve(i: i32) -> Result<i32, &'static str> {
+                if i % 2 == 0 { Ok(i / 2) } else { Err("odd") }
+            }
+
+            fn double(i: i32) -> Result<i32, &'static str> {
+                if i == 5 { panic!("I'm afraid of 5's!") } else { Ok(i * 2) }
+            }
+        }
+    );
+
+    easy_ffi!(unified_ffi_fn =>
+        |failure| {
+            match failure {
+                crate::FfiFailure::Error(e) => println!("error: {}", e),
+                crate::FfiFailure::Panic(_) => println!("panic!"),
+            }
+            -1
+        }
+    );
+
+    unified_ffi_fn!(
+        fn unified(i: i32) -> Result<i32, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i <= 0 => Err("already <= 0, can't go lower"),
+                i => Ok(i - 1),
+            }
+        }
+    );
+
+    my_ffi_fn! {
+        #[easy_ffi(
+            |_err| { ::std::ptr::null() }
+            |_panic_val| { ::std::ptr::null() }
+        )]
+        fn null_on_failure(i: i32) -> Result<*const i32, &'static str> {
+            static FIVE: i32 = 5;
+            match i {
+                5 => Ok(&FIVE),
+                6 => panic!("I'm afraid of 6's!"),
+                _ => Err("only have a 5"),
+            }
+        }
+
+        #[easy_ffi(prefix = "easy_ffi_test_")]
+        fn overridden_prefix(i: i32) -> Result<i32, &'static str> {
+            if i < 0 { Err("negative") } else { Ok(i) }
+        }
+
+        // Overrides only apply to the function they're on.
+        fn not_overridden(i: i32) -> Result<i32, &'static str> {
+            if i < 0 { Err("negative") } else { Ok(i) }
+        }
+    }
+
+    easy_ffi!(named_ffi_fn =>
+        |_err, fn_name| {
+            assert_eq!("named", fn_name);
+            -1
+        }
+        |_panic_val, fn_name| {
+            assert_eq!("named", fn_name);
+            -2
+        }
+    );
+
+    named_ffi_fn!(
+        fn named(i: i32) -> Result<i32, ()> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err(()),
+                i => Ok(i),
+            }
+        }
+
+        #[easy_ffi(|_failure, fn_name| { fn_name.len() as i32 })]
+        fn named_single(_i: i32) -> Result<i32, ()> {
+            Err(())
+        }
+    );
+
+    easy_ffi!(args_ffi_fn =>
+        |_err, _fn_name, args| {
+            let (a, b) = args;
+            a - b
+        }
+        |_panic_val, _fn_name, args| {
+            let (a, b) = args;
+            a * b
+        }
+    );
+
+    args_ffi_fn!(
+        fn with_args(a: i32, b: i32) -> Result<i32, ()> {
+            match (a, b) {
+                (5, _) => panic!("I'm afraid of 5's!"),
+                (a, b) if a < b => Err(()),
+                (a, b) => Ok(a + b),
+            }
+        }
+
+        #[easy_ffi(|_failure, _fn_name, args| { args.0.map_or(0, |b| *b) })]
+        fn with_owned_args(p: Option<Box<i32>>) -> Result<i32, ()> {
+            drop(p);
+            Err(())
+        }
+    );
+
+    #[repr(C)]
+    pub struct BadClone(i32);
+
+    impl Clone for BadClone {
+        fn clone(&self) -> Self {
+            if self.0 == 5 {
+                panic!("I'm afraid of 5's!");
+            }
+            BadClone(self.0)
+        }
+    }
+
+    easy_ffi!(clone_ffi_fn =>
+        fallback = -99,
+        |_err, _fn_name, args| { (args.0).0 }
+        |_panic_val, _fn_name, args| { -(args.0).0 }
+    );
+
+    clone_ffi_fn!(
+        fn with_bad_clone(b: BadClone) -> Result<i32, ()> {
+            if b.0 < 0 {
+                Err(())
+            } else {
+                Ok(b.0)
+            }
+        }
+    );
+
+    struct Config {
+        err_code: i32,
+        panic_code: i32,
+    }
+
+    static CONFIG: Config = Config {
+        err_code: -10,
+        panic_code: -20,
+    };
+
+    easy_ffi!(context_ffi_fn =>
+        context = config: &CONFIG,
+        |_err| { config.err_code }
+        |_panic_val| { config.panic_code }
+    );
+
+    context_ffi_fn!(
+        fn with_context(i: i32) -> Result<i32, ()> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err(()),
+                i => Ok(i),
+            }
+        }
+
+        #[easy_ffi(context = code: 7, |_err| { code } |_panic_val| { -code })]
+        fn with_other_context(_i: i32) -> Result<i32, ()> {
+            Err(())
+        }
+    );
+
+    easy_ffi!(default_ffi_fn =>);
+
+    default_ffi_fn!(
+        fn default_int(i: i32) -> Result<u32, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err("negative"),
+                i => Ok(i as u32 + 1),
+            }
+        }
+
+        fn default_ptr(fail: bool) -> Result<*const i32, &'static str> {
+            if fail {
+                Err("failed")
+            } else {
+                Ok(&1)
+            }
+        }
+
+        fn default_unit(fail: bool) -> Result<(), &'static str> {
+            if fail {
+                Err("failed")
+            } else {
+                Ok(())
+            }
+        }
+    );
+
+    easy_ffi!(panicky_ffi_fn =>
+        fallback = -99,
+        |_err| { panic!("error handler panicked") }
+        |_panic_val| { panic!("panic handler panicked") }
+    );
+
+    panicky_ffi_fn!(
+        fn panicky_handlers(i: i32) -> Result<i32, ()> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err(()),
+                i => Ok(i),
+            }
+        }
+    );
+
+    easy_ffi!(abort_ffi_fn =>
+        on_panic = "abort",
+        |failure| {
+            match failure {
+                crate::FfiFailure::Error(code) => code,
+                crate::FfiFailure::Panic(_) => unreachable!(),
+            }
+        }
+    );
+
+    abort_ffi_fn!(
+        fn abort_on_panic(i: i32) -> Result<i32, i32> {
+            if i < 0 {
+                Err(-1)
+            } else {
+                Ok(i)
+            }
+        }
+
+        #[easy_ffi(on_panic = "handle", |_failure| { -2 })]
+        fn handle_panic(_i: i32) -> Result<i32, i32> {
+            panic!("handled")
+        }
+    );
+
+    easy_ffi!(unwind_safe_ffi_fn =>
+        assert_unwind_safe = true,
+        |_err| { -1 }
+        |_panic_val| { -2 }
+    );
+
+    unwind_safe_ffi_fn!(
+        fn increment(counter: &mut i32) -> Result<i32, ()> {
+            *counter += 1;
+            if *counter == 3 {
+                panic!("three")
+            }
+            Ok(*counter)
+        }
+
+        #[easy_ffi(assert_unwind_safe = false)]
+        fn not_asserted(i: i32) -> Result<i32, ()> {
+            Ok(i)
+        }
+    );
+
+    easy_ffi_last_error!(easy_ffi_test);
+
+    easy_ffi!(last_error_ffi_fn =>
+        |failure| {
+            set_last_error(crate::LastError::from_failure(-3, &failure));
+            -3
+        }
+    );
+
+    last_error_ffi_fn!(
+        fn records_errors(i: i32) -> Result<i32, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err("negative"),
+                i => Ok(i),
+            }
+        }
+    );
+
+    #[cfg(feature = "errno")]
+    #[derive(Debug)]
+    struct Code(i32);
+
+    #[cfg(feature = "errno")]
+    impl crate::errno::AsErrno for Code {
+        fn as_errno(&self) -> i32 {
+            self.0
+        }
+    }
+
+    #[cfg(feature = "errno")]
+    easy_ffi!(errno_ffi_fn =>
+        |err| {
+            crate::errno::set_errno_from(&err);
+            -1
+        }
+        |_panic_val| {
+            crate::errno::set_errno(crate::errno::EIO);
+            -1
+        }
+    );
+
+    #[cfg(feature = "errno")]
+    errno_ffi_fn!(
+        fn sets_errno(i: i32) -> Result<i32, Code> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err(Code(-i)),
+                i => Ok(i),
+            }
+        }
+    );
+
+    #[cfg(windows)]
+    #[derive(Debug)]
+    struct Win32Code(u32);
+
+    #[cfg(windows)]
+    impl crate::windows::AsWin32Error for Win32Code {
+        fn as_win32_error(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[cfg(windows)]
+    easy_ffi!(win32_ffi_fn =>
+        abi = "system",
+        |err| {
+            crate::windows::set_last_error_from(&err);
+            0
+        }
+        |_panic_val| {
+            crate::windows::set_last_error(crate::windows::ERROR_INTERNAL_ERROR);
+            0
+        }
+    );
+
+    #[cfg(windows)]
+    win32_ffi_fn!(
+        fn sets_last_error(i: i32) -> Result<i32, Win32Code> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err(Win32Code(-i as u32)),
+                i => Ok(i),
+            }
+        }
+    );
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[repr(C)]
+    pub struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    easy_ffi!(out_param_ffi_fn =>
+        returns = "out_param",
+        |_err| { -1 }
+        |_panic_val| { -2 }
+    );
+
+    out_param_ffi_fn!(
+        fn make_point(x: i32, y: i32) -> Result<Point, ()> {
+            match x {
+                5 => panic!("I'm afraid of 5's!"),
+                x if x < 0 => Err(()),
+                x => Ok(Point { x, y }),
+            }
+        }
+
+        fn no_args() -> Result<Point, ()> {
+            Ok(Point { x: 0, y: 0 })
+        }
+    );
+
+    easy_ffi!(null_ffi_fn => last_error = set_last_error);
+
+    null_ffi_fn!(
+        fn find_static(i: i32) -> Result<*const i32, &'static str> {
+            static FOUND: i32 = 1;
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                1 => Ok(&FOUND),
+                _ => Err("not found"),
+            }
+        }
+    );
+
+    my_ffi_fn!(
+        #[easy_ffi(handlers = "default")]
+        fn find_default(_i: i32) -> Result<*mut i32, &'static str> {
+            Err("not found")
+        }
+    );
+
+    null_ffi_fn!(
+        fn new_boxed(i: i32) -> Result<Box<i32>, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err("negative"),
+                i => Ok(Box::new(i)),
+            }
+        }
+
+        fn find_boxed(i: i32) -> Result<Option<Box<i32>>, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err("negative"),
+                0 => Ok(None),
+                i => Ok(Some(Box::new(i))),
+            }
+        }
+    );
+
+    pub struct Tracked(bool);
+
+    static DROPPED: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+            if self.0 {
+                panic!("I'm afraid of drops!");
+            }
+        }
+    }
+
+    null_ffi_fn!(
+        fn tracked_new(panics: bool) -> Result<Box<Tracked>, &'static str> {
+            Ok(Box::new(Tracked(panics)))
+        }
+    );
+
+    easy_ffi_destructor!(tracked_free, Tracked, double_free_guard = true);
+
+    null_ffi_fn!(
+        #[easy_ffi(free_results = true)]
+        fn boxed_result(i: i32) -> Result<Box<i32>, &'static str> {
+            Ok(Box::new(i))
+        }
+
+        #[easy_ffi(free_results = true, prefix = "easy_ffi_test_")]
+        fn maybe_boxed_result(i: i32) -> Result<Option<Box<i32>>, &'static str> {
+            Ok(Some(Box::new(i)))
+        }
+    );
+
+    null_ffi_fn!(
+        #[easy_ffi(free_results = true)]
+        fn stringify(i: i32) -> Result<String, ::std::io::Error> {
+            stringify_impl(i)
+        }
+    );
+
+    fn stringify_impl(i: i32) -> Result<String, ::std::io::Error> {
+        match i {
+            5 => panic!("I'm afraid of 5's!"),
+            0 => Ok("nul\0".to_string()),
+            i => Ok(i.to_string()),
+        }
+    }
+
+    easy_ffi_string_free!(easy_ffi_test);
+
+    null_ffi_fn!(
+        #[easy_ffi(returns = "buffer")]
+        fn stringify_into(i: i32) -> Result<String, ::std::io::Error> {
+            stringify_impl(i)
+        }
+
+        #[easy_ffi(returns = "scratch")]
+        fn stringify_scratch(i: i32) -> Result<String, ::std::io::Error> {
+            stringify_impl(i)
+        }
+
+        // A `String` error has no `From<NulError>`, which buffers don't need.
+        #[easy_ffi(returns = "buffer")]
+        fn describe_into(i: i32) -> Result<String, String> {
+            if i < 0 {
+                return Err(format!("{} is negative", i));
+            }
+            Ok(i.to_string())
+        }
+    );
+
+    extern "C" {
+        fn stringify_result_free(s: *mut ::std::os::raw::c_char);
+        fn boxed_result_result_free(ptr: *mut i32);
+        fn easy_ffi_test_maybe_boxed_result_result_free(ptr: *mut i32);
+    }
+
+    null_ffi_fn!(
+        #[easy_ffi(returns = "status", |_failure| { -1 })]
+        unsafe fn release_boxed(boxed: *mut i32) -> Result<(), crate::HandleError> {
+            crate::free_boxed(boxed)
+        }
+    );
+
+    #[derive(Default)]
+    pub struct Counter {
+        count: i32,
+    }
+
+    easy_ffi_handle! {
+        pub struct CounterHandle(Counter);
+        magic = 0xC041_7E12,
+        new = counter_new,
+        free = counter_free,
+    }
+
+    easy_ffi_handle! {
+        pub struct StepHandle(Counter);
+        new = step_new(count: i32) {
+            if count < 0 {
+                panic!("negative count");
+            }
+            Counter { count }
+        },
+        free = step_free,
+    }
+
+    null_ffi_fn!(
+        fn counter_starting_at(count: i32) -> Result<Box<CounterHandle>, crate::HandleError> {
+            Ok(CounterHandle::new(Counter { count }))
+        }
+
+        unsafe fn counter_incr(counter: *mut CounterHandle) -> Result<i32, crate::HandleError> {
+            CounterHandle::with_mut(counter, |counter| {
+                counter.count += 1;
+                counter.count
+            })
+        }
+    );
+
+    easy_ffi!(tagged_ffi_fn =>
+        returns = "tagged",
+        |_err| { -1 }
+        |_panic_val| { -2 }
+    );
+
+    tagged_ffi_fn!(
+        fn tagged(i: i32) -> Result<u32, ()> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err(()),
+                i => Ok(i as u32),
+            }
+        }
+    );
+
+    easy_ffi!(sentinel_ffi_fn =>
+        |_err| { crate::FfiDefault::ffi_default() }
+        |_panic_val| { crate::FfiDefault::ffi_default() }
+    );
+
+    #[derive(Debug, PartialEq)]
+    #[repr(C)]
+    pub struct Handle(u64);
+
+    impl crate::FfiDefault for Handle {
+        fn ffi_default() -> Self {
+            Handle(0)
+        }
+    }
+
+    sentinel_ffi_fn!(
+        fn sentinel_float(f: f64) -> Result<f64, ()> {
+            if f < 0.0 {
+                Err(())
+            } else {
+                Ok(f)
+            }
+        }
+
+        fn sentinel_handle(i: u64) -> Result<Handle, ()> {
+            if i == 5 {
+                panic!("I'm afraid of 5's!")
+            } else {
+                Ok(Handle(i))
+            }
+        }
+    );
+
+    easy_ffi!(status_ffi_fn =>
+        returns = "status",
+        |_err| { 1 }
+        |_panic_val| { 2 }
+    );
+
+    status_ffi_fn!(
+        fn status(i: i32) -> Result<(), ()> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err(()),
+                _ => Ok(()),
+            }
+        }
+
+        #[easy_ffi(success = 100)]
+        fn other_status() -> Result<(), ()> {
+            Ok(())
+        }
+    );
+
+    easy_ffi!(bool_ffi_fn =>
+        returns = "bool",
+        |_err| { -1 }
+        |_panic_val| { -2 }
+    );
+
+    bool_ffi_fn!(
+        fn is_even(i: i32) -> Result<bool, ()> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err(()),
+                i => Ok(i % 2 == 0),
+            }
+        }
+
+        #[easy_ffi(true_value = b'y' as ::std::os::raw::c_int, false_value = b'n' as ::std::os::raw::c_int)]
+        fn is_odd(i: i32) -> Result<bool, ()> {
+            Ok(i % 2 != 0)
+        }
+    );
+
+    #[test]
+    fn it_works() {
+        assert_eq!(-1, foo(5));
+        assert_eq!(-1, foo(0));
+        assert_eq!(0, foo(1));
+        assert_eq!(1, foo(2));
+    }
+
+    #[test]
+    fn custom_abi() {
+        let f: extern "system" fn(i32) -> i32 = sys;
+        assert_eq!(-2, f(5));
+        assert_eq!(-1, f(0));
+        assert_eq!(0, f(1));
+    }
+
+    #[test]
+    fn c_unwind_abi() {
+        let f: extern "C-unwind" fn(i32) -> i32 = unwinds;
+        assert_eq!(-2, f(5));
+        assert_eq!(1, f(1));
+    }
+
+    #[test]
+    fn unsafe_fns() {
+        let f: unsafe extern "C" fn(*const i32) -> i32 = read_ptr;
+        unsafe {
+            assert_eq!(7, f(&7));
+            assert_eq!(-1, f(::std::ptr::null()));
+        }
+    }
+
+    #[test]
+    fn visibility() {
+        assert_eq!(1, vis::crate_visible(1));
+        assert_eq!(2, vis::super_visible(2));
+        assert_eq!(3, vis::call_private(3));
+    }
+
+    #[test]
+    fn export_name() {
+        extern "C" {
+            fn easy_ffi_test_renamed_v2(i: i32) -> i32;
+        }
+        assert_eq!(3, renamed(1));
+        assert_eq!(4, unsafe { easy_ffi_test_renamed_v2(2) });
+    }
+
+    #[test]
+    fn symbol_prefix() {
+        extern "C" {
+            fn easy_ffi_test_prefixed(i: i32) -> i32;
+            fn easy_ffi_test_unprefixed(i: i32) -> i32;
+        }
+        assert_eq!(10, prefixed(1));
+        assert_eq!(20, unsafe { easy_ffi_test_prefixed(2) });
+        assert_eq!(100, explicitly_named(1));
+        assert_eq!(200, unsafe { easy_ffi_test_unprefixed(2) });
+    }
+
+    #[test]
+    fn multiple_fns() {
+        assert_eq!(2, first(1));
+        assert_eq!(-1, second(None));
+        assert_eq!(3, unsafe { third(&3) });
+    }
+
+    #[test]
+    fn wrapped_mod() {
+        extern "C" {
+            fn easy_ffi_test_mod_halve(i: i32) -> i32;
+        }
+        assert_eq!(2, exports::halve(4));
+        assert_eq!(-3, exports::halve(3));
+        assert_eq!(4, exports::double(2));
+        assert_eq!(-4, exports::double(5));
+        assert_eq!(3, unsafe { easy_ffi_test_mod_halve(6) });
+    }
+
+    #[test]
+    fn single_handler() {
+        assert_eq!(-1, unified(5));
+        assert_eq!(-1, unified(0));
+        assert_eq!(1, unified(2));
+    }
+
+    #[test]
+    fn per_fn_overrides() {
+        extern "C" {
+            fn easy_ffi_test_overridden_prefix(i: i32) -> i32;
+        }
+        assert_eq!(5, unsafe { *null_on_failure(5) });
+        assert!(null_on_failure(4).is_null());
+        assert!(null_on_failure(6).is_null());
+        assert_eq!(-1, unsafe { easy_ffi_test_overridden_prefix(-1) });
+        assert_eq!(-1, not_overridden(-1));
+    }
+
+    #[test]
+    fn handler_fn_names() {
+        assert_eq!(-1, named(-1));
+        assert_eq!(-2, named(5));
+        assert_eq!(12, named_single(0));
+    }
+
+    #[test]
+    fn handler_args() {
+        assert_eq!(3, with_args(2, 1));
+        assert_eq!(-1, with_args(1, 2));
+        assert_eq!(10, with_args(5, 2));
+        assert_eq!(5, with_owned_args(Some(Box::new(5))));
+        assert_eq!(1, with_bad_clone(BadClone(1)));
+        assert_eq!(-1, with_bad_clone(BadClone(-1)));
+        assert_eq!(-99, with_bad_clone(BadClone(5)));
+    }
+
+    #[test]
+    fn handler_context() {
+        assert_eq!(1, with_context(1));
+        assert_eq!(-10, with_context(-1));
+        assert_eq!(-20, with_context(5));
+        assert_eq!(7, with_other_context(1));
+    }
+
+    #[test]
+    fn default_handlers() {
+        assert_eq!(2, default_int(1));
+        assert_eq!(0, default_int(-1));
+        assert_eq!(0, default_int(5));
+        assert!(!default_ptr(false).is_null());
+        assert!(default_ptr(true).is_null());
+        default_unit(true);
+    }
+
+    #[test]
+    fn handler_fallback() {
+        assert_eq!(1, panicky_handlers(1));
+        assert_eq!(-99, panicky_handlers(-1));
+        assert_eq!(-99, panicky_handlers(5));
+    }
+
+    #[test]
+    fn abort_mode() {
+        assert_eq!(1, abort_on_panic(1));
+        assert_eq!(-1, abort_on_panic(-1));
+        assert_eq!(-2, handle_panic(1));
+    }
+
+    #[test]
+    fn assert_unwind_safe() {
+        let mut counter = 0;
+        assert_eq!(1, increment(&mut counter));
+        assert_eq!(2, increment(&mut counter));
+        assert_eq!(-2, increment(&mut counter));
+        assert_eq!(3, counter);
+        assert_eq!(1, not_asserted(1));
+    }
+
+    #[test]
+    fn last_error() {
+        use std::ffi::CStr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+
+        assert!(last_error_message().is_null());
+        assert!(!has_error());
+        assert_eq!(0, last_error_code());
+        assert_eq!(1, records_errors(1));
+        assert!(last_error_message().is_null());
+
+        assert_eq!(-3, records_errors(-1));
+        assert!(has_error());
+        assert_eq!(-3, last_error_code());
+        assert_eq!(8, last_error_length());
+        assert_eq!("negative", message());
+
+        assert_eq!(-3, records_errors(5));
+        assert_eq!("I'm afraid of 5's!", message());
+        // Only the built-in handlers know the function.
+        assert!(last_error_function().is_null());
+        let info = last_error_info();
+        assert_eq!(-3, info.code);
+        assert_eq!(last_error_message(), info.message);
+
+        clear_last_error();
+        assert!(!has_error());
+        assert!(last_error_message().is_null());
+        assert_eq!(0, last_error_length());
+    }
+
+    #[test]
+    fn last_error_backtraces() {
+        assert_eq!(-3, records_errors(-1));
+        let backtrace = last_error_backtrace();
+        if cfg!(feature = "backtrace") {
+            let backtrace = unsafe { ::std::ffi::CStr::from_ptr(backtrace) };
+            assert!(backtrace.to_str().unwrap().contains("records_errors"));
+        } else {
+            assert!(backtrace.is_null());
+        }
+    }
+
+    easy_ffi!(error_chain_ffi_fn => handlers = "error_chain", last_error = set_last_error);
+
+    #[derive(Debug)]
+    enum ParseError {
+        Arg(crate::ArgError),
+        Int(::std::num::ParseIntError),
+    }
+
+    impl ::std::fmt::Display for ParseError {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            match *self {
+                ParseError::Arg(ref err) => write!(f, "{}", err),
+                ParseError::Int(_) => f.write_str("parsing"),
+            }
+        }
+    }
+
+    impl ::std::error::Error for ParseError {
+        fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+            match *self {
+                ParseError::Arg(_) => None,
+                ParseError::Int(ref err) => Some(err),
+            }
+        }
+    }
+
+    impl From<crate::ArgError> for ParseError {
+        fn from(err: crate::ArgError) -> ParseError {
+            ParseError::Arg(err)
+        }
+    }
+
+    error_chain_ffi_fn!(
+        fn parse_chained(s: &str) -> Result<i32, ParseError> {
+            s.parse().map_err(ParseError::Int)
+        }
+    );
+
+    #[test]
+    fn error_chains() {
+        use std::ffi::CStr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+
+        unsafe {
+            assert_eq!(5, parse_chained(b"5\0".as_ptr() as *const _));
+            assert_eq!(-1, parse_chained(b"x\0".as_ptr() as *const _));
+            assert_eq!(
+                "parsing: caused by invalid digit found in string",
+                message()
+            );
+            let function = CStr::from_ptr(last_error_function());
+            assert_eq!("parse_chained", function.to_str().unwrap());
+            assert_eq!(-1, parse_chained(::std::ptr::null()));
+            assert_eq!("`s` is null", message());
+        }
+    }
+
+    #[cfg(feature = "anyhow")]
+    easy_ffi!(anyhow_ffi_fn => handlers = "anyhow", last_error = set_last_error);
+
+    #[cfg(feature = "anyhow")]
+    anyhow_ffi_fn!(
+        fn parse_positive(s: &str) -> anyhow::Result<u32> {
+            use anyhow::Context;
+
+            let n: i32 = s.parse().with_context(|| format!("parsing {:?}", s))?;
+            anyhow::ensure!(n > 0, "{} isn't positive", n);
+            Ok(n as u32)
+        }
+    );
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn anyhow_errors() {
+        use std::ffi::CStr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+
+        unsafe {
+            assert_eq!(5, parse_positive(b"5\0".as_ptr() as *const _));
+            assert_eq!(0, parse_positive(b"x\0".as_ptr() as *const _));
+            assert_eq!("parsing \"x\": invalid digit found in string", message());
+            assert_eq!(0, parse_positive(b"-1\0".as_ptr() as *const _));
+            assert_eq!("-1 isn't positive", message());
+            assert_eq!(0, parse_positive(::std::ptr::null()));
+            assert_eq!("`s` is null", message());
+        }
+    }
+
+    #[cfg(feature = "eyre")]
+    struct ColorHandler;
+
+    #[cfg(feature = "eyre")]
+    impl eyre::EyreHandler for ColorHandler {
+        fn debug(
+            &self,
+            error: &(dyn ::std::error::Error + 'static),
+            f: &mut ::std::fmt::Formatter,
+        ) -> ::std::fmt::Result {
+            write!(f, "\x1b[31m{}\x1b[0m", error)?;
+            let mut source = error.source();
+            while let Some(cause) = source {
+                write!(f, "\n  caused by: \x1b[1;33m{}\x1b[0m", cause)?;
+                source = cause.source();
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "eyre")]
+    easy_ffi!(eyre_ffi_fn => handlers = "eyre", last_error = set_last_error);
+
+    #[cfg(feature = "eyre")]
+    eyre_ffi_fn!(
+        fn parse_even(s: &str) -> eyre::Result<u32> {
+            use eyre::WrapErr;
+
+            let n: u32 = s.parse().wrap_err_with(|| format!("parsing {:?}", s))?;
+            eyre::ensure!(n % 2 == 0, "{} isn't even", n);
+            Ok(n)
+        }
+    );
+
+    #[cfg(feature = "eyre")]
+    #[test]
+    fn eyre_reports() {
+        use std::ffi::CStr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+
+        eyre::set_hook(Box::new(|_| Box::new(ColorHandler))).unwrap();
+        unsafe {
+            assert_eq!(4, parse_even(b"4\0".as_ptr() as *const _));
+            assert_eq!(0, parse_even(b"x\0".as_ptr() as *const _));
+            assert_eq!(
+                "parsing \"x\"\n  caused by: invalid digit found in string",
+                message()
+            );
+            assert_eq!(0, parse_even(b"3\0".as_ptr() as *const _));
+            assert_eq!("3 isn't even", message());
+        }
+    }
+
+    #[cfg(feature = "log")]
+    struct TestLogger;
+
+    #[cfg(feature = "log")]
+    static LOGGED: ::std::sync::Mutex<Vec<(String, String)>> = ::std::sync::Mutex::new(Vec::new());
+
+    #[cfg(feature = "log")]
+    fn install_test_logger() {
+        static INSTALL: ::std::sync::Once = ::std::sync::Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&TestLogger).unwrap();
+            log::set_max_level(log::LevelFilter::Error);
+        });
+    }
+
+    #[cfg(feature = "log")]
+    impl log::Log for TestLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            if record.level() == log::Level::Error {
+                let logged = (record.target().to_string(), record.args().to_string());
+                LOGGED.lock().unwrap().push(logged);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[cfg(feature = "log")]
+    easy_ffi!(log_ffi_fn => handlers = "log");
+
+    #[cfg(feature = "log")]
+    log_ffi_fn!(
+        fn logs_errors(i: i32) -> Result<i32, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err("negative"),
+                i => Ok(i),
+            }
+        }
+
+        #[easy_ffi(log_target = "easy_ffi_test")]
+        fn logs_to_target(i: i32) -> Result<i32, &'static str> {
+            logs_errors(i);
+            Err("always")
+        }
+    );
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_handlers() {
+        install_test_logger();
+
+        assert_eq!(1, logs_errors(1));
+        assert_eq!(-1, logs_errors(-1));
+        assert_eq!(-1, logs_errors(5));
+        assert_eq!(-1, logs_to_target(-1));
+        let here = module_path!().to_string();
+        assert_eq!(
+            vec![
+                (here.clone(), "logs_errors: negative".to_string()),
+                (
+                    here.clone(),
+                    "logs_errors: panic: I'm afraid of 5's!".to_string()
+                ),
+                (here, "logs_errors: negative".to_string()),
+                (
+                    "easy_ffi_test".to_string(),
+                    "logs_to_target: always".to_string()
+                ),
+            ],
+            LOGGED
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(target, _)| target != "easy_ffi_limited")
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct TraceRecorder(::std::sync::Arc<::std::sync::Mutex<Vec<String>>>);
+
+    #[cfg(feature = "tracing")]
+    struct TraceFields(String);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for TraceFields {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn ::std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for TraceRecorder {
+        fn enabled(&self, _: &tracing::Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes) -> tracing::span::Id {
+            let mut fields = TraceFields(format!("span {}", span.metadata().name()));
+            span.record(&mut fields);
+            let mut recorded = self.0.lock().unwrap();
+            recorded.push(fields.0);
+            tracing::span::Id::from_u64(recorded.len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event) {
+            let mut fields = TraceFields(format!("{}", event.metadata().level()));
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "tracing")]
+    easy_ffi!(traced_ffi_fn => tracing = true);
+
+    #[cfg(feature = "tracing")]
+    pub struct Opaque;
+
+    #[cfg(feature = "tracing")]
+    traced_ffi_fn!(
+        fn traced(i: i32, flag: bool, _opaque: *const Opaque) -> Result<i32, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err("negative"),
+                i => Ok(i + flag as i32),
+            }
+        }
+    );
+
+    #[cfg(feature = "tracing")]
+    #[repr(C)]
+    pub struct BadDebug(i32);
+
+    #[cfg(feature = "tracing")]
+    impl ::std::fmt::Debug for BadDebug {
+        fn fmt(&self, _: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            panic!("I'm afraid of Debug!")
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    easy_ffi!(traced_fallback_ffi_fn => tracing = true, fallback = -99);
+
+    #[cfg(feature = "tracing")]
+    traced_fallback_ffi_fn!(
+        fn traced_bad_debug(b: BadDebug) -> Result<i32, ()> {
+            Ok(b.0)
+        }
+    );
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_spans() {
+        let recorder = TraceRecorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            assert_eq!(2, traced(1, true, ::std::ptr::null()));
+            assert_eq!(-1, traced(-1, false, ::std::ptr::null()));
+            assert_eq!(-1, traced(5, false, ::std::ptr::null()));
+            assert_eq!(-99, traced_bad_debug(BadDebug(1)));
+        });
+        assert_eq!(
+            vec![
+                "span traced args=i=1, flag=true, _opaque=0x0",
+                "span traced args=i=-1, flag=false, _opaque=0x0",
+                "ERROR message=returned an error error=\"negative\"",
+                "span traced args=i=5, flag=false, _opaque=0x0",
+                "ERROR message=panicked panic=I'm afraid of 5's!",
+            ],
+            *recorder.0.lock().unwrap()
+        );
+    }
+
+    extern "C" fn record_thread_error(
+        code: ::std::os::raw::c_int,
+        message: *const ::std::os::raw::c_char,
+        user_data: *mut ::std::os::raw::c_void,
+    ) {
+        let message = unsafe { ::std::ffi::CStr::from_ptr(message) };
+        let errors = unsafe { &mut *(user_data as *mut Vec<String>) };
+        errors.push(format!("{}: {}", code, message.to_str().unwrap()));
+    }
+
+    easy_ffi!(thread_callback_ffi_fn =>
+        last_error = thread_callback_errors::set_last_error,
+        handlers = "error_code",
+    );
+
+    mod thread_callback_errors {
+        easy_ffi_last_error!(easy_ffi_test_thread_callback);
+    }
+
+    #[derive(Debug)]
+    struct Negative;
+
+    impl ::std::fmt::Display for Negative {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            f.write_str("negative")
+        }
+    }
+
+    impl crate::FfiErrorCode for Negative {
+        fn error_code(&self) -> ::std::os::raw::c_int {
+            -7
+        }
+    }
+
+    thread_callback_ffi_fn!(
+        fn called_back(i: i32) -> Result<i32, Negative> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err(Negative),
+                i => Ok(i),
+            }
+        }
+    );
+
+    #[derive(Debug)]
+    struct Busy;
+
+    impl ::std::fmt::Display for Busy {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            f.write_str("busy")
+        }
+    }
+
+    impl crate::FfiErrorCode for Busy {
+        fn error_code(&self) -> ::std::os::raw::c_int {
+            -8
+        }
+
+        fn severity(&self) -> crate::Severity {
+            crate::Severity::Warning
+        }
+    }
+
+    thread_callback_ffi_fn!(
+        #[easy_ffi(returns = "status")]
+        fn warned(busy: bool) -> Result<(), Busy> {
+            if busy {
+                Err(Busy)
+            } else {
+                Ok(())
+            }
+        }
+
+        fn warned_value(busy: bool) -> Result<i32, Busy> {
+            if busy {
+                Err(Busy)
+            } else {
+                Ok(1)
+            }
+        }
+    );
+
+    #[test]
+    fn severities() {
+        use self::thread_callback_errors::*;
+
+        assert_eq!(0, warned(false));
+        assert!(!has_error());
+        // Warnings still succeed, but are recorded.
+        assert_eq!(0, warned(true));
+        assert_eq!(-8, last_error_code());
+        assert_eq!(
+            crate::Severity::Warning as ::std::os::raw::c_int,
+            last_error_severity()
+        );
+        // Without a success value, there's only the failure value.
+        assert_eq!(-1, warned_value(true));
+        assert_eq!(-1, called_back(-1));
+        assert_eq!(
+            crate::Severity::Error as ::std::os::raw::c_int,
+            last_error_severity()
+        );
+    }
+
+    #[test]
+    fn thread_error_callbacks() {
+        let worker = ::std::thread::spawn(|| {
+            let mut errors = Vec::<String>::new();
+            let user_data = &mut errors as *mut Vec<String> as *mut ::std::os::raw::c_void;
+            crate::set_thread_error_callback(Some(record_thread_error), user_data);
+            assert_eq!(1, called_back(1));
+            assert_eq!(-1, called_back(-1));
+            assert_eq!(-1, called_back(5));
+            crate::set_thread_error_callback(None, ::std::ptr::null_mut());
+            assert_eq!(-1, called_back(-2));
+            errors
+        });
+        // Nothing is registered for this thread, or every thread.
+        assert_eq!(-1, called_back(-3));
+        assert_eq!(
+            vec![
+                "-7: negative".to_owned(),
+                format!("{}: I'm afraid of 5's!", crate::PANIC_CODE),
+            ],
+            worker.join().unwrap()
+        );
+    }
+
+    my_ffi_fn!(
+        fn decrypt(#[zeroize] key: &mut [u8], fail: bool) -> Result<i32, String> {
+            let sum = key.iter().map(|&b| b as i32).sum();
+            if key.len() == 1 {
+                panic!("key too short");
+            }
+            if fail {
+                Err("bad key".into())
+            } else {
+                Ok(sum)
+            }
+        }
+    );
+
+    #[test]
+    fn zeroized_args() {
+        let mut key = [1, 2, 3];
+        assert_eq!(6, unsafe { decrypt(key.as_mut_ptr(), key.len(), false) });
+        assert_eq!([1, 2, 3], key);
+        assert_eq!(-1, unsafe { decrypt(key.as_mut_ptr(), key.len(), true) });
+        assert_eq!([0, 0, 0], key);
+        let mut key = [4];
+        assert_eq!(-1, unsafe { decrypt(key.as_mut_ptr(), key.len(), false) });
+        assert_eq!([0], key);
+    }
+
+    easy_ffi!(counted_ffi_fn => stats = true);
+
+    counted_ffi_fn!(
+        fn counted(i: i32) -> Result<i32, String> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err("negative".into()),
+                i => Ok(i),
+            }
+        }
+    );
+
+    #[test]
+    fn call_stats() {
+        assert_eq!(1, counted(1));
+        assert_eq!(2, counted(2));
+        assert_eq!(-1, counted(-1));
+        assert_eq!(-1, counted(5));
+        let stats = crate::fn_stats();
+        let stats = stats
+            .iter()
+            .find(|stats| stats.name() == "counted")
+            .unwrap();
+        assert_eq!((4, 1, 1), (stats.calls(), stats.errors(), stats.panics()));
+        assert!(crate::fn_stats_json()
+            .contains(r#"{"name":"counted","calls":4,"errors":1,"panics":1}"#));
+    }
+
+    #[cfg(feature = "log")]
+    easy_ffi!(limited_ffi_fn => handlers = "log", log_limit = 2);
+
+    #[cfg(feature = "log")]
+    limited_ffi_fn!(
+        #[easy_ffi(log_target = "easy_ffi_limited")]
+        fn limited(fail: bool) -> Result<i32, &'static str> {
+            if fail {
+                Err("failed")
+            } else {
+                Ok(2)
+            }
+        }
+    );
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_limits() {
+        install_test_logger();
+        let logged = || -> Vec<String> {
+            let logged = LOGGED.lock().unwrap();
+            let limited = logged
+                .iter()
+                .filter(|(target, _)| target == "easy_ffi_limited");
+            limited.map(|(_, message)| message.clone()).collect()
+        };
+
+        assert_eq!(2, limited(false));
+        for _ in 0..5 {
+            assert_eq!(-1, limited(true));
+        }
+        assert_eq!(vec!["limited: failed"; 2], logged());
+        ::std::thread::sleep(::std::time::Duration::from_millis(1100));
+        assert_eq!(-1, limited(true));
+        assert_eq!(
+            vec![
+                "limited: failed",
+                "limited: failed",
+                "limited: suppressed 3 messages",
+                "limited: failed",
+            ],
+            logged()
+        );
+        // Without a limit, everything is logged.
+        assert!(crate::rate_limit::may_log(|_| panic!(
+            "nothing was suppressed"
+        )));
+    }
+
+    #[test]
+    fn own_log_limits() {
+        static LIMIT: crate::LogLimit = crate::LogLimit::new();
+        let mut summaries = Vec::new();
+        let mut logged = Vec::new();
+        for _ in 0..5 {
+            logged.push(LIMIT.may_log(2, |suppressed| summaries.push(suppressed)));
+        }
+        assert_eq!(vec![true, true, false, false, false], logged);
+        assert!(summaries.is_empty());
+        ::std::thread::sleep(::std::time::Duration::from_millis(1100));
+        assert!(LIMIT.may_log(2, |suppressed| summaries.push(suppressed)));
+        assert_eq!(vec![3], summaries);
+    }
+
+    easy_ffi!(timeout_ffi_fn =>
+        timeout = ::std::time::Duration::from_millis(100),
+        handlers = "error_code",
+    );
+
+    timeout_ffi_fn!(
+        fn slow(millis: u64) -> Result<u64, crate::TimedOut> {
+            if millis == 5 {
+                panic!("I'm afraid of 5's!");
+            }
+            ::std::thread::sleep(::std::time::Duration::from_millis(millis));
+            Ok(millis)
+        }
+    );
+
+    #[test]
+    fn timeouts() {
+        assert_eq!(1, slow(1));
+        assert_eq!(0, slow(1000));
+        assert_eq!(0, slow(5));
+    }
+
+    easy_ffi!(big_stack_ffi_fn => stack_size = 64 << 20);
+
+    fn depth(n: u64) -> u64 {
+        // Big enough frames that the default stack couldn't take them.
+        let frame = [n; 64];
+        if n == 0 {
+            0
+        } else {
+            ::std::hint::black_box(frame)[0] - n + 1 + depth(n - 1)
+        }
+    }
+
+    big_stack_ffi_fn!(
+        fn recurse(n: u64) -> Result<u64, ()> {
+            if n == 5 {
+                panic!("I'm afraid of 5's!");
+            }
+            Ok(depth(n))
+        }
+    );
+
+    #[test]
+    fn big_stacks() {
+        assert_eq!(20_000, recurse(20_000));
+        assert_eq!(0, recurse(5));
+    }
+
+    easy_ffi!(job_ffi_fn => jobs = true);
+
+    job_ffi_fn!(
+        #[easy_ffi(prefix = "easy_ffi_test_")]
+        fn sleepy(millis: u64, out: *mut u64) -> Result<u64, ()> {
+            ::std::thread::sleep(::std::time::Duration::from_millis(millis));
+            if millis == 5 {
+                panic!("I'm afraid of 5's!");
+            }
+            if !out.is_null() {
+                unsafe { *out = millis };
+            }
+            Ok(millis)
+        }
+    );
+
+    extern "C" {
+        fn easy_ffi_test_sleepy_start(millis: u64, out: *mut u64) -> *mut ::std::os::raw::c_void;
+        fn easy_ffi_test_sleepy_poll(job: *const ::std::os::raw::c_void) -> ::std::os::raw::c_int;
+        fn easy_ffi_test_sleepy_result(
+            job: *const ::std::os::raw::c_void,
+            out: *mut u64,
+        ) -> ::std::os::raw::c_int;
+        fn easy_ffi_test_sleepy_cancel(job: *const ::std::os::raw::c_void)
+            -> ::std::os::raw::c_int;
+        fn easy_ffi_test_sleepy_free(job: *mut ::std::os::raw::c_void);
+    }
+
+    #[test]
+    fn jobs() {
+        use crate::JobStatus;
+
+        let mut written = 0;
+        let mut ret = 0;
+        unsafe {
+            let job = easy_ffi_test_sleepy_start(50, &mut written);
+            assert_eq!(JobStatus::Pending as i32, easy_ffi_test_sleepy_poll(job));
+            assert_eq!(
+                JobStatus::Done as i32,
+                easy_ffi_test_sleepy_result(job, &mut ret)
+            );
+            assert_eq!((50, 50), (written, ret));
+            assert_eq!(
+                JobStatus::Invalid as i32,
+                easy_ffi_test_sleepy_result(job, &mut ret)
+            );
+            easy_ffi_test_sleepy_free(job);
+
+            let job = easy_ffi_test_sleepy_start(5, ::std::ptr::null_mut());
+            assert_eq!(
+                JobStatus::Done as i32,
+                easy_ffi_test_sleepy_result(job, &mut ret)
+            );
+            assert_eq!(0, ret);
+            easy_ffi_test_sleepy_free(job);
+
+            let job = easy_ffi_test_sleepy_start(1000, ::std::ptr::null_mut());
+            assert_eq!(
+                JobStatus::Cancelled as i32,
+                easy_ffi_test_sleepy_cancel(job)
+            );
+            assert_eq!(
+                JobStatus::Cancelled as i32,
+                easy_ffi_test_sleepy_result(job, &mut ret)
+            );
+            easy_ffi_test_sleepy_free(job);
+
+            assert_eq!(
+                JobStatus::Invalid as i32,
+                easy_ffi_test_sleepy_poll(::std::ptr::null())
+            );
+        }
+    }
+
+    easy_ffi!(async_ffi_fn => async_callback = true);
+
+    async_ffi_fn!(
+        #[easy_ffi(prefix = "easy_ffi_test_")]
+        fn doubled(i: i32) -> Result<i32, &'static str> {
+            match i {
+                5 => panic!("I'm afraid of 5's!"),
+                i if i < 0 => Err("negative"),
+                i => Ok(i * 2),
+            }
+        }
+    );
+
+    extern "C" {
+        fn easy_ffi_test_doubled_async(
+            i: i32,
+            done: Option<extern "C" fn(i32, *mut ::std::os::raw::c_void)>,
+            user_data: *mut ::std::os::raw::c_void,
+        ) -> bool;
+    }
+
+    extern "C" fn doubled_done(ret: i32, user_data: *mut ::std::os::raw::c_void) {
+        let sender = unsafe { Box::from_raw(user_data as *mut ::std::sync::mpsc::Sender<i32>) };
+        sender.send(ret).unwrap();
+    }
+
+    #[test]
+    fn async_callbacks() {
+        let (sender, receiver) = ::std::sync::mpsc::channel();
+        for i in &[2, -1, 5] {
+            let user_data = Box::into_raw(Box::new(sender.clone())) as *mut ::std::os::raw::c_void;
+            assert!(unsafe { easy_ffi_test_doubled_async(*i, Some(doubled_done), user_data) });
+        }
+        let mut rets: Vec<i32> = receiver.iter().take(3).collect();
+        rets.sort();
+        assert_eq!(vec![-1, -1, 4], rets);
+        assert!(!unsafe { easy_ffi_test_doubled_async(1, None, ::std::ptr::null_mut()) });
+
+        let user_data = Box::into_raw(Box::new(sender)) as *mut ::std::os::raw::c_void;
+        assert!(unsafe { easy_ffi_test_tripled_async(2, Some(tripled_done), user_data) });
+        assert_eq!(Ok(6), receiver.recv());
+    }
+
+    easy_ffi!(system_async_ffi_fn => abi = "system", async_callback = true);
+
+    system_async_ffi_fn!(
+        #[easy_ffi(prefix = "easy_ffi_test_")]
+        fn tripled(i: i32) -> Result<i32, &'static str> {
+            i.checked_mul(3).ok_or("overflow")
+        }
+    );
+
+    extern "system" {
+        fn easy_ffi_test_tripled_async(
+            i: i32,
+            done: Option<extern "system" fn(i32, *mut ::std::os::raw::c_void)>,
+            user_data: *mut ::std::os::raw::c_void,
+        ) -> bool;
+    }
+
+    extern "system" fn tripled_done(ret: i32, user_data: *mut ::std::os::raw::c_void) {
+        let sender = unsafe { Box::from_raw(user_data as *mut ::std::sync::mpsc::Sender<i32>) };
+        sender.send(ret).unwrap();
+    }
+
+    easy_ffi!(async_fn_ffi_fn =>);
+
+    async_fn_ffi_fn!(
+        async fn received(i: i32) -> Result<i32, &'static str> {
+            let (sender, receiver) = ::std::sync::mpsc::sync_channel(1);
+            ::std::thread::spawn(move || sender.send(i * 2).unwrap());
+            let doubled = Doubled(receiver).await;
+            if doubled < 0 {
+                Err("negative")
+            } else {
+                Ok(doubled)
+            }
+        }
+    );
+
+    /// Resolves once the thread's sent its result, polling again whenever
+    /// it's woken.
+    struct Doubled(::std::sync::mpsc::Receiver<i32>);
+
+    impl ::std::future::Future for Doubled {
+        type Output = i32;
+
+        fn poll(
+            self: ::std::pin::Pin<&mut Self>,
+            cx: &mut ::std::task::Context<'_>,
+        ) -> ::std::task::Poll<i32> {
+            match self.0.try_recv() {
+                Ok(i) => ::std::task::Poll::Ready(i),
+                Err(_) => {
+                    cx.waker().wake_by_ref();
+                    ::std::task::Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn async_fns() {
+        assert_eq!(4, received(2));
+        assert_eq!(-1, received(-1));
+    }
+
+    #[cfg(feature = "tokio")]
+    mod tokio_runtime {
+        easy_ffi_runtime!(tokio, easy_ffi_test);
+
+        easy_ffi!(tokio_ffi_fn => runtime = tokio);
+
+        tokio_ffi_fn!(
+            async fn spawned(i: i32) -> Result<i32, String> {
+                let handle = crate::tokio_runtime("spawned")?;
+                handle
+                    .handle()
+                    .spawn(async move { i * 2 })
+                    .await
+                    .map_err(|err| err.to_string())
+            }
+        );
+
+        #[test]
+        fn tokio_runtime() {
+            assert_eq!(-1, spawned(2));
+            assert_eq!(0, runtime_init());
+            assert_eq!(0, runtime_init());
+            assert_eq!(4, spawned(2));
+            runtime_shutdown();
+            assert_eq!(-1, spawned(2));
+        }
+    }
+
+    use crate::FfiCancelToken;
+
+    null_ffi_fn!(
+        fn cancellable(cancel: &FfiCancelToken) -> Result<i32, ::std::io::Error> {
+            cancel.check()?;
+            Ok(1)
+        }
+    );
+
+    #[test]
+    fn cancel_tokens() {
+        let token = FfiCancelToken::new();
+        assert_eq!(1, unsafe { cancellable(&token) });
+        token.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(-1, unsafe { cancellable(&token) });
+        assert_eq!(-1, unsafe { cancellable(::std::ptr::null()) });
+    }
+
+    easy_ffi!(safe_ffi_fn => safe = true);
+
+    safe_ffi_fn!(
+        fn parse_safe(s: &str, bias: i32) -> Result<i32, String> {
+            if bias == 5 {
+                panic!("I'm afraid of 5's!");
+            }
+            s.parse::<i32>()
+                .map(|i| i + bias)
+                .map_err(|err| err.to_string())
+        }
+    );
+
+    #[test]
+    fn safe_wrappers() {
+        assert_eq!(Some(3), parse_safe::safe("2", 1).ok());
+        match parse_safe::safe("two", 1) {
+            Err(crate::FfiFailure::Error(err)) => assert_eq!("invalid digit found in string", err),
+            _ => panic!("expected an error"),
+        }
+        match parse_safe::safe("2", 5) {
+            Err(crate::FfiFailure::Panic(panic_val)) => {
+                assert_eq!("I'm afraid of 5's!", crate::panic_message(&*panic_val))
+            }
+            _ => panic!("expected a panic"),
+        }
+        assert_eq!(-1, unsafe { parse_safe(::std::ptr::null(), 1) });
+    }
+
+    easy_ffi!(inner_ffi_fn => inner = true, safe = true);
+
+    #[derive(Debug, PartialEq)]
+    struct Odd(i32);
+
+    impl ::std::fmt::Display for Odd {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            write!(f, "{} is odd", self.0)
+        }
+    }
+
+    inner_ffi_fn!(
+        fn halve(n: i32) -> Result<i32, Odd> {
+            if n % 2 == 0 {
+                Ok(n / 2)
+            } else {
+                Err(Odd(n))
+            }
+        }
+    );
+
+    inner_ffi_fn!(
+        async fn halve_async(n: i32) -> Result<i32, Odd> {
+            if n % 2 == 0 {
+                Ok(n / 2)
+            } else {
+                Err(Odd(n))
+            }
+        }
+    );
+
+    #[test]
+    fn inner_fns() {
+        use crate::FfiRuntime;
+
+        assert_eq!(Ok(2), halve::inner(4));
+        assert_eq!(Err(Odd(3)), halve::inner(3));
+        match halve::safe(3) {
+            Err(crate::FfiFailure::Error(err)) => assert_eq!(Odd(3), err),
+            _ => panic!("expected an error"),
+        }
+        assert_eq!(-1, halve(3));
+        assert_eq!(
+            Err(Odd(5)),
+            crate::CurrentThread.block_on(halve_async::inner(5))
+        );
+        assert_eq!(Some(3), halve_async::safe(6).ok());
+        assert_eq!(3, halve_async(6));
+    }
+
+    easy_ffi!(injected_ffi_fn =>
+        panic_tests = true,
+        |err| {
+            println!("{}", err);
+            -1
+        }
+        |_panic_val| {
+            -2
+        }
+    );
+
+    injected_ffi_fn!(
+        fn injected_add(a: i32, b: i32) -> Result<i32, &'static str> {
+            a.checked_add(b).ok_or("overflow")
+        }
+    );
+
+    injected_ffi_fn!(
+        fn injected_len(s: &str) -> Result<i32, String> {
+            Ok(s.len() as i32)
+        }
+    );
+
+    easy_ffi_tests! {
+        injected_add(1, 2) => -2,
+        injected_len(b"hi\0".as_ptr() as *const _) => -2,
+    }
+
+    easy_ffi_tests! {
+        mod more_easy_ffi_tests;
+        injected_add_overflowing: injected_add(i32::MAX, 1) => -2,
+        injected_add_negative: injected_add(-1, -2) => -2,
+    }
+
+    mod unlisted {
+        easy_ffi!(unlisted_ffi_fn =>
+            panic_tests = true,
+            |err| {
+                println!("{}", err);
+                -1
+            }
+            |_panic_val| {
+                -2
+            }
+        );
+
+        unlisted_ffi_fn!(
+            fn unlisted_sub(a: i32, b: i32) -> Result<i32, &'static str> {
+                a.checked_sub(b).ok_or("overflow")
+            }
+        );
+    }
+
+    #[cfg(feature = "manifest")]
+    #[test]
+    fn unlisted_panic_tests() {
+        assert!(
+            crate::unlisted_panic_tests(concat!(module_path!(), "::easy_ffi_tests")).is_empty()
+        );
+        assert_eq!(
+            vec!["unlisted_sub"],
+            crate::unlisted_panic_tests(concat!(module_path!(), "::unlisted::easy_ffi_tests"))
+        );
+    }
+
+    #[test]
+    fn panics_need_panic_tests() {
+        let (ret, panicked) = crate::with_injected_panic("str_len", || unsafe {
+            str_len(b"hi\0".as_ptr() as *const _, 1)
+        });
+        assert!(!panicked);
+        assert_eq!(3, ret);
+    }
+
+    #[test]
+    fn injected_panics_are_once() {
+        let (ret, panicked) =
+            crate::with_injected_panic("injected_add", || (injected_add(1, 2), injected_add(1, 2)));
+        assert!(panicked);
+        assert_eq!((-2, 3), ret);
+        assert_eq!(3, injected_add(1, 2));
+    }
+
+    easy_ffi_selftest!(tests);
+
+    #[test]
+    fn self_tests() {
+        assert_eq!(0, selftest());
+    }
+
+    #[cfg(all(feature = "selftest", target_os = "linux"))]
+    #[test]
+    fn check_library() {
+        use crate::selftest::{self, SelftestError};
+
+        let err = unsafe { selftest::check_library("no/such/lib.so", "tests", &[]) };
+        assert!(matches!(err, Err(SelftestError::Open(..))));
+        let err =
+            unsafe { selftest::check_library("libc.so.6", "tests", &["strlen", "no_such_fn"]) };
+        match err {
+            Err(SelftestError::Missing(symbol, _)) => assert_eq!("no_such_fn", symbol),
+            _ => panic!("expected a missing symbol"),
+        }
+        let err = unsafe { selftest::check_library("libc.so.6", "tests", &["strlen"]) };
+        match err {
+            Err(SelftestError::Missing(symbol, _)) => assert_eq!("tests_selftest", symbol),
+            _ => panic!("expected a missing self-test"),
+        }
+        assert!(selftest::built_library("mylib").ends_with(libloading::library_filename("mylib")));
+    }
+
+    #[test]
+    fn fuzzed_calls() {
+        let inputs: [&[u8]; 4] = [&[], &[2, 0, 0, 0], &[0xff; 32], b"easy_ffi fuzzing"];
+        for data in inputs {
+            easy_ffi_fuzz!(data =>
+                halve(i32),
+                sum(&[i32], &[i32]),
+                self::double_all(&mut [i32]),
+            );
+        }
+        let mut input = crate::FuzzInput::new(&[2, 1, 0, 0xff]);
+        assert_eq!(vec![1u8, 0], input.slice::<u8>());
+        assert!(input.arg::<bool>());
+        assert_eq!(0, input.arg::<u64>());
+    }
+
+    easy_ffi!(typed_ffi_fn => check_ffi_types = true);
+
+    typed_ffi_fn!(
+        #[easy_ffi(returns = "tagged")]
+        fn checked_tagged(n: i32) -> Result<i32, String> {
+            Ok(n)
+        }
+    );
+
+    typed_ffi_fn!(
+        fn checked_name() -> Result<String, ::std::io::Error> {
+            Ok("checked".to_string())
+        }
+    );
+
+    typed_ffi_fn!(
+        fn checked_args(
+            s: &str,
+            nums: &[i32],
+            n: &i32,
+            callback: extern "C" fn(i32) -> i32,
+            ptr: *const Vec<u8>,
+            flag: bool,
+        ) -> Result<i32, String> {
+            let _ = (ptr, flag);
+            Ok(callback(s.len() as i32 + nums.len() as i32 + n))
+        }
+    );
+
+    #[test]
+    fn checked_ffi_types() {
+        assert_eq!(2, checked_tagged(2).value);
+        let nums = [1, 2];
+        let ret = unsafe {
+            checked_args(
+                b"ab\0".as_ptr() as *const _,
+                nums.as_ptr(),
+                2,
+                &3,
+                Some(add_one),
+                ::std::ptr::null(),
+                true,
+            )
+        };
+        assert_eq!(8, ret);
+        let name = checked_name();
+        assert!(!name.is_null());
+        unsafe { drop(::std::ffi::CString::from_raw(name)) };
+    }
+
+    easy_ffi!(strict_ffi_fn => improper_ctypes = "deny");
+
+    strict_ffi_fn!(
+        fn strict_add(a: i32, b: i32) -> Result<i32, String> {
+            a.checked_add(b).ok_or_else(|| "overflow".to_string())
+        }
+    );
+
+    strict_ffi_fn!(
+        #[easy_ffi(improper_ctypes = "allow")]
+        fn lenient_marker(marker: ::std::marker::PhantomData<i32>, a: i32) -> Result<i32, String> {
+            let _ = marker;
+            Ok(a)
+        }
+    );
+
+    #[test]
+    fn improper_ctypes() {
+        assert_eq!(3, strict_add(1, 2));
+        assert_eq!(-1, strict_add(i32::MAX, 1));
+        assert_eq!(1, lenient_marker(::std::marker::PhantomData, 1));
+    }
+
+    #[cfg(feature = "manifest")]
+    mod manifest {
+        easy_ffi!(listed_ffi_fn => manifest = true, prefix = "listed_");
+
+        listed_ffi_fn!(
+            /// Sums some numbers.
+            ///
+            /// Overflows are errors.
+            fn sum(nums: &[i32]) -> Result<i32, String> {
+                let sum = nums.iter().try_fold(0i32, |sum, &n| sum.checked_add(n));
+                sum.ok_or_else(|| "overflow".to_string())
+            }
+        );
+
+        listed_ffi_fn!(
+            fn dot(a: &[i32], b: &[i32] as b_len) -> Result<i32, String> {
+                if a.len() != b_len {
+                    return Err("different lengths".to_string());
+                }
+                Ok(a.iter().zip(b).map(|(a, b)| a * b).sum())
+            }
+        );
+
+        listed_ffi_fn!(
+            #[easy_ffi(export = false)]
+            fn unlisted() -> Result<i32, String> {
+                Ok(0)
+            }
+        );
+
+        #[test]
+        fn exports() {
+            let exports = crate::exports();
+            assert!(exports.iter().all(|export| export.name() != "unlisted"));
+            let sum = exports
+                .iter()
+                .find(|export| export.symbol() == "listed_sum")
+                .unwrap();
+            assert_eq!("sum", sum.name());
+            assert_eq!(" Sums some numbers.\n\n Overflows are errors.", sum.doc());
+            // How types are spaced out is up to `stringify!`.
+            let params: Vec<_> = sum
+                .params()
+                .iter()
+                .map(|param| (param.name(), param.ty().replace(' ', "")))
+                .collect();
+            assert_eq!(
+                vec![
+                    ("nums", "*consti32".to_string()),
+                    ("len", "usize".to_string())
+                ],
+                params
+            );
+            assert_eq!("i32", sum.ret());
+            let _ = unlisted;
+        }
+
+        #[test]
+        fn slice_lengths() {
+            let export = crate::exports()
+                .iter()
+                .find(|export| export.name() == "dot")
+                .unwrap();
+            let names: Vec<_> = export.params().iter().map(|param| param.name()).collect();
+            assert_eq!(vec!["a", "len", "b", "b_len"], names);
+            let json = crate::api_json(&[]);
+            assert!(json.contains(r#"{"name":"len","type":"usize"}"#));
+            assert!(json.contains(r#"{"name":"b_len","type":"usize"}"#));
+            let nums = [1, 2, 3];
+            assert_eq!(14, unsafe { dot(nums.as_ptr(), 3, nums.as_ptr(), 3) });
+            assert_eq!(-1, unsafe { dot(nums.as_ptr(), 3, nums.as_ptr(), 2) });
+        }
+
+        easy_ffi_api_json!(listed);
+
+        #[test]
+        fn api_descriptor() {
+            use std::ffi::CStr;
+
+            let json = unsafe { CStr::from_ptr(api_json()) }.to_str().unwrap();
+            assert!(json.starts_with(r#"{"exports":["#));
+            assert!(json.contains(r#""doc":" Sums some numbers.\n\n Overflows are errors."}"#));
+            assert!(json.ends_with(r#""error_codes":[]}"#));
+            assert_eq!(
+                json.as_ptr(),
+                unsafe { CStr::from_ptr(api_json()) }.as_ptr() as *const u8
+            );
+            let codes = [(-1, "a \"quoted\"\tthing\u{1}")];
+            assert!(crate::api_json(&codes)
+                .ends_with(r#"[{"code":-1,"description":"a \"quoted\"\tthing\u0001"}]}"#));
+        }
+    }
+
+    // Options late in the config, all at once, so that setting them stays
+    // well under the recursion limit.
+    easy_ffi!(many_options_ffi_fn =>
+        prefix = "many_", export = true, check_ffi_types = true, improper_ctypes = "deny",
+        assert_unwind_safe = true, poison = false, returns = "status", success = 7,
+        free_results = false, null_checks = true, before = |_| Ok::<(), String>(()),
+        after = |_, _, _| (), safe = true, inner = false,
+    );
+
+    many_options_ffi_fn!(
+        fn many_options(i: i32) -> Result<(), String> {
+            if i < 0 {
+                return Err("negative".to_string());
+            }
+            Ok(())
+        }
+    );
+
+    #[test]
+    fn many_options_at_once() {
+        assert_eq!(7, many_options(1));
+        assert_eq!(-1, many_options(-1));
+        assert!(many_options::safe(1).is_ok());
+    }
+
+    #[cfg(feature = "errno")]
+    #[test]
+    fn errno() {
+        assert_eq!(-1, sets_errno(-22));
+        assert_eq!(22, crate::errno::errno());
+        assert_eq!(-1, sets_errno(5));
+        assert_eq!(crate::errno::EIO, crate::errno::errno());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn win32_last_error() {
+        assert_eq!(0, sets_last_error(-2));
+        assert_eq!(2, crate::windows::get_last_error());
+        assert_eq!(0, sets_last_error(5));
+        assert_eq!(
+            crate::windows::ERROR_INTERNAL_ERROR,
+            crate::windows::get_last_error()
+        );
+    }
+
+    #[test]
+    fn out_param() {
+        let mut point = Point { x: 0, y: 0 };
+        assert_eq!(0, unsafe { make_point(1, 2, &mut point) });
+        assert_eq!(Point { x: 1, y: 2 }, point);
+        assert_eq!(-1, unsafe { make_point(-1, 3, &mut point) });
+        assert_eq!(-2, unsafe { make_point(5, 3, &mut point) });
+        assert_eq!(Point { x: 1, y: 2 }, point);
+        assert_eq!(0, unsafe { make_point(1, 2, ::std::ptr::null_mut()) });
+        assert_eq!(0, unsafe { no_args(&mut point) });
+    }
+
+    #[test]
+    fn null_on_error() {
+        use std::ffi::CStr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+
+        assert_eq!(1, unsafe { *find_static(1) });
+        assert!(last_error_message().is_null());
+        assert!(find_static(2).is_null());
+        assert_eq!("not found", message());
+        assert_eq!(-1, last_error_code());
+        assert!(find_static(5).is_null());
+        assert_eq!("I'm afraid of 5's!", message());
+        assert!(find_default(1).is_null());
+    }
+
+    #[test]
+    fn box_returns() {
+        let boxed = new_boxed(3);
+        assert_eq!(3, *unsafe { Box::from_raw(boxed) });
+        assert!(new_boxed(-1).is_null());
+        assert!(new_boxed(5).is_null());
+    }
+
+    #[test]
+    fn destructor() {
+        use std::sync::atomic::Ordering;
+
+        unsafe {
+            tracked_free(::std::ptr::null_mut());
+            assert_eq!(0, DROPPED.load(Ordering::SeqCst));
+            tracked_free(tracked_new(false));
+            assert_eq!(1, DROPPED.load(Ordering::SeqCst));
+            tracked_free(tracked_new(true));
+            assert_eq!(2, DROPPED.load(Ordering::SeqCst));
+        }
+    }
+
+    #[test]
+    fn string_returns() {
+        use std::ffi::CStr;
+
+        unsafe {
+            let s = stringify(12);
+            assert_eq!(Ok("12"), CStr::from_ptr(s).to_str());
+            string_free(s);
+            string_free(::std::ptr::null_mut());
+            stringify_result_free(stringify(12));
+
+            assert!(stringify(0).is_null());
+            let message = CStr::from_ptr(last_error_message()).to_str();
+            assert!(message.unwrap().contains("nul byte"));
+            assert!(stringify(5).is_null());
+        }
+    }
+
+    null_ffi_fn!(
+        fn str_len(s: &str, extra: i32) -> Result<i32, String> {
+            Ok(s.len() as i32 + extra)
+        }
+    );
+
+    null_ffi_fn!(
+        fn sum(first: &[i32], second: &[i32] as second_len) -> Result<i32, String> {
+            Ok(first.iter().chain(second).sum())
+        }
+    );
+
+    #[test]
+    fn slice_args() {
+        use std::ffi::CStr;
+        use std::ptr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+        let nums = [1, 2, 3];
+
+        unsafe {
+            assert_eq!(9, sum(nums.as_ptr(), 3, nums.as_ptr(), 2));
+            assert_eq!(6, sum(nums.as_ptr(), 3, ptr::null(), 0));
+            assert_eq!(-1, sum(nums.as_ptr(), 3, ptr::null(), 1));
+            assert_eq!("`second` is null", message());
+            assert_eq!(-1, sum(nums.as_ptr(), usize::MAX, nums.as_ptr(), 0));
+            assert_eq!("`first` is too long", message());
+            let misaligned = (nums.as_ptr() as *const u8).add(1) as *const i32;
+            assert_eq!(-1, sum(misaligned, 1, nums.as_ptr(), 0));
+            assert_eq!("`first` is misaligned", message());
+        }
+    }
+
+    null_ffi_fn!(
+        fn double_all(nums: &mut [i32]) -> Result<i32, String> {
+            nums.iter_mut().for_each(|num| *num *= 2);
+            Ok(nums.len() as i32)
+        }
+    );
+
+    #[test]
+    fn mut_slice_args() {
+        use std::ffi::CStr;
+        use std::ptr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+        let mut nums = [1, 2, 3];
+
+        unsafe {
+            assert_eq!(2, double_all(nums.as_mut_ptr(), 2));
+            assert_eq!([2, 4, 3], nums);
+            assert_eq!(0, double_all(ptr::null_mut(), 0));
+            assert_eq!(-1, double_all(ptr::null_mut(), 1));
+            assert_eq!("`nums` is null", message());
+        }
+    }
+
+    extern "C" fn add_one(num: i32) -> i32 {
+        num + 1
+    }
+
+    null_ffi_fn!(
+        fn apply(f: extern "C" fn(i32) -> i32, num: i32) -> Result<i32, String> {
+            Ok(f(num))
+        }
+
+        fn maybe_apply(f: Option<extern "C" fn(i32) -> i32>, num: i32) -> Result<i32, String> {
+            Ok(f.map_or(num, |f| f(num)))
+        }
+    );
+
+    #[test]
+    fn fn_ptr_args() {
+        use std::ffi::CStr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+
+        assert_eq!(2, apply(Some(add_one), 1));
+        assert_eq!(-1, apply(None, 1));
+        assert_eq!("`f` is null", message());
+        assert_eq!(2, maybe_apply(Some(add_one), 1));
+        assert_eq!(1, maybe_apply(None, 1));
+    }
+
+    easy_ffi_closure!(
+        struct AddCallback(FnMut(num: i32) -> i32);
+        on_panic = -1,
+    );
+
+    easy_ffi_closure!(
+        struct DoneCallback(FnMut());
+    );
+
+    #[test]
+    fn closures() {
+        use std::sync::Arc;
+
+        let captured = Arc::new(());
+        let mut total = 0;
+        let add = {
+            let captured = captured.clone();
+            AddCallback::new(move |num| {
+                let _ = &captured;
+                assert!(num >= 0, "negative");
+                total += num;
+                total
+            })
+        };
+        let done = DoneCallback::new(|| ());
+
+        unsafe {
+            assert_eq!(1, (add.call)(add.data, 1));
+            assert_eq!(3, (add.call)(add.data, 2));
+            assert_eq!(-1, (add.call)(add.data, -1));
+            assert_eq!(-1, (add.call)(::std::ptr::null_mut(), 1));
+            assert_eq!(2, Arc::strong_count(&captured));
+            (add.free)(add.data);
+            (done.call)(done.data);
+            (done.free)(done.data);
+        }
+        assert_eq!(1, Arc::strong_count(&captured));
+    }
+
+    trait Accumulator {
+        fn add(&mut self, num: i32) -> i32;
+        fn total(&self) -> i32;
+        fn clear(&mut self);
+    }
+
+    struct Sum(i32);
+
+    impl Accumulator for Sum {
+        fn add(&mut self, num: i32) -> i32 {
+            assert!(num >= 0, "negative");
+            self.0 += num;
+            self.0
+        }
+
+        fn total(&self) -> i32 {
+            self.0
+        }
+
+        fn clear(&mut self) {
+            self.0 = 0;
+        }
+    }
+
+    easy_ffi_vtable!(
+        struct AccumulatorVtable: Accumulator {
+            fn add(&mut self, num: i32) -> i32 = -1;
+            fn total(&self) -> i32 = -1;
+            fn clear(&mut self);
+        }
+    );
+
+    #[test]
+    fn vtables() {
+        let vtable = AccumulatorVtable::new(Sum(1));
+
+        unsafe {
+            assert_eq!(3, (vtable.add)(vtable.data, 2));
+            assert_eq!(-1, (vtable.add)(vtable.data, -2));
+            assert_eq!(3, (vtable.total)(vtable.data));
+            (vtable.clear)(vtable.data);
+            assert_eq!(0, (vtable.total)(vtable.data));
+            assert_eq!(-1, (vtable.total)(::std::ptr::null_mut()));
+            (vtable.free)(vtable.data);
+        }
+    }
+
+    trait Store {
+        fn get(&self, key: i32) -> Result<i32, String>;
+        fn set(&mut self, key: i32, value: i32) -> Result<(), String>;
+        fn clear(&mut self) -> Result<(), String>;
+    }
+
+    easy_ffi_foreign_vtable!(
+        struct CStore(CStoreVtable): Store {
+            fn get(&self, key: i32) -> Result<i32, String>;
+            fn set(&mut self, key: i32, value: i32) -> Result<(), String> = status;
+            fn clear(&mut self) -> Result<(), String> = status;
+        }
+    );
+
+    static FREED_STORES: ::std::sync::atomic::AtomicUsize =
+        ::std::sync::atomic::AtomicUsize::new(0);
+
+    unsafe extern "C" fn c_store_get(data: *mut ::std::os::raw::c_void, key: i32) -> i32 {
+        (*(data as *mut [i32; 4]))[key as usize]
+    }
+
+    unsafe extern "C" fn c_store_set(
+        data: *mut ::std::os::raw::c_void,
+        key: i32,
+        value: i32,
+    ) -> i32 {
+        match (*(data as *mut [i32; 4])).get_mut(key as usize) {
+            Some(slot) => {
+                *slot = value;
+                0
+            }
+            None => 22,
+        }
+    }
+
+    unsafe extern "C" fn c_store_free(data: *mut ::std::os::raw::c_void) {
+        drop(Box::from_raw(data as *mut [i32; 4]));
+        FREED_STORES.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+    }
+
+    #[test]
+    fn foreign_vtables() {
+        use std::sync::atomic::Ordering;
+
+        let mut store = unsafe {
+            CStore::new(CStoreVtable {
+                data: Box::into_raw(Box::new([0; 4])) as *mut _,
+                get: Some(c_store_get),
+                set: Some(c_store_set),
+                clear: None,
+                free: Some(c_store_free),
+            })
+        };
+
+        assert_eq!(Ok(()), store.set(1, 5));
+        assert_eq!(Ok(5), store.get(1));
+        assert_eq!(
+            Err("`set` failed with status 22".to_string()),
+            store.set(4, 5)
+        );
+        assert_eq!(Err("`clear` is null".to_string()), store.clear());
+        drop(store);
+        assert_eq!(1, FREED_STORES.load(Ordering::SeqCst));
+    }
+
+    easy_ffi_callback!(null_callback => last_error = set_last_error);
+
+    null_callback!(
+        pub(self) fn checked_double(num: i32) -> Result<i32, String> {
+            assert!(num >= 0, "negative");
+            num.checked_mul(2).ok_or_else(|| "overflow".to_string())
+        }
+    );
+
+    #[test]
+    fn callbacks() {
+        let f: extern "C" fn(i32) -> i32 = checked_double;
+        assert_eq!(4, f(2));
+        assert_eq!(-1, f(i32::MAX));
+        assert_eq!(-1, f(-1));
+    }
+
+    easy_ffi_callback!(deferring_callback =>
+        on_panic = "defer",
+        |err| { err }
+        |panic_val| {
+            assert_eq!(Some(&"negative".to_string()), panic_val.downcast_ref::<String>());
+            -2
+        }
+    );
+
+    deferring_callback!(
+        pub(self) fn checked_halve(num: i32) -> Result<i32, i32> {
+            assert!(num >= 0, "negative");
+            Ok(num / 2)
+        }
+    );
+
+    #[test]
+    fn deferred_panics() {
+        let f: extern "C" fn(i32) -> i32 = checked_halve;
+        crate::resume_deferred_panic();
+        assert_eq!(-2, f(-1));
+        assert_eq!(-2, f(-2));
+        assert_eq!(1, f(2));
+        let panic_val = ::std::panic::catch_unwind(crate::resume_deferred_panic).unwrap_err();
+        assert_eq!(Some(&"negative"), panic_val.downcast_ref::<&str>());
+        crate::resume_deferred_panic();
+        assert_eq!(1, crate::call_foreign(|| f(2)));
+        let panic_val = ::std::panic::catch_unwind(|| crate::call_foreign(|| f(-1))).unwrap_err();
+        assert_eq!(Some(&"negative"), panic_val.downcast_ref::<&str>());
+    }
+
+    #[derive(Debug)]
+    struct Panicked(String);
+
+    impl From<Box<dyn ::std::any::Any + Send>> for Panicked {
+        fn from(panic_val: Box<dyn ::std::any::Any + Send>) -> Panicked {
+            Panicked(crate::panic_message(&*panic_val).into_owned())
+        }
+    }
+
+    easy_ffi!(panic_error_ffi_fn =>
+        on_panic = "error",
+        |err| {
+            assert_eq!("negative", err.0);
+            -1
+        }
+        |_panic_val| { -2 }
+    );
+
+    panic_error_ffi_fn!(
+        fn checked_neg(n: i32) -> Result<i32, Panicked> {
+            assert!(n >= 0, "negative");
+            Ok(-n)
+        }
+    );
+
+    easy_ffi!(panic_string_ffi_fn =>
+        panic_error = |panic_val| {
+            crate::panic_message(&*panic_val).into_owned()
+        },
+        |failure| {
+            match failure {
+                crate::FfiFailure::Error(err) => assert_eq!("negative", err),
+                crate::FfiFailure::Panic(_) => unreachable!(),
+            }
+            -1
+        }
+    );
+
+    panic_string_ffi_fn!(
+        fn checked_sqrt(n: i32) -> Result<i32, String> {
+            assert!(n >= 0, "negative");
+            Ok((n as f64).sqrt() as i32)
+        }
+    );
+
+    #[test]
+    fn panic_errors() {
+        assert_eq!(-2, checked_neg(2));
+        assert_eq!(-1, checked_neg(-2));
+        assert_eq!(3, checked_sqrt(9));
+        assert_eq!(-1, checked_sqrt(-9));
+    }
+
+    easy_ffi!(checked_ffi_fn => null_checks = true, last_error = set_last_error);
+
+    checked_ffi_fn!(
+        unsafe fn read_or(ptr: *const i32, #[nullable] default: *const i32) -> Result<i32, String> {
+            Ok(*ptr + default.as_ref().copied().unwrap_or(0))
+        }
+    );
+
+    null_ffi_fn!(
+        unsafe fn read_checked(
+            #[nonnull] ptr: *const i32,
+            unchecked: *const i32,
+        ) -> Result<i32, String> {
+            Ok(*ptr + unchecked.as_ref().copied().unwrap_or(0))
+        }
+    );
+
+    #[test]
+    fn null_checks() {
+        use std::ffi::CStr;
+        use std::ptr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+
+        unsafe {
+            assert_eq!(3, read_or(&1, &2));
+            assert_eq!(1, read_or(&1, ptr::null()));
+            assert_eq!(-1, read_or(ptr::null(), &2));
+            assert_eq!("`ptr` is null", message());
+            assert_eq!(1, read_checked(&1, ptr::null()));
+            assert_eq!(-1, read_checked(ptr::null(), &2));
+            assert_eq!("`ptr` is null", message());
+        }
+    }
+
+    null_ffi_fn!(
+        fn percent_of(
+            #[nonzero] total: u32,
+            #[range(0..=100)]
+            #[nonzero]
+            percent: u32,
+        ) -> Result<u32, String> {
+            Ok(total * percent / 100)
+        }
+
+        fn name_len(#[utf8] name: *const ::std::os::raw::c_char) -> Result<usize, String> {
+            Ok(unsafe { ::std::ffi::CStr::from_ptr(name) }.to_bytes().len())
+        }
+    );
+
+    #[test]
+    fn arg_checks() {
+        use std::ffi::CStr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+
+        assert_eq!(50, percent_of(200, 25));
+        assert_eq!(0, percent_of(0, 25));
+        assert_eq!("`total` is zero", message());
+        assert_eq!(0, percent_of(200, 0));
+        assert_eq!("`percent` is zero", message());
+        assert_eq!(0, percent_of(200, 101));
+        assert_eq!("`percent` isn't in 0..=100", message());
+        unsafe {
+            assert_eq!(5, name_len(b"hello\0".as_ptr() as *const _));
+            assert_eq!(0, name_len(::std::ptr::null()));
+            assert_eq!("`name` is null", message());
+        }
+    }
+
+    thread_local! {
+        static CALLED: ::std::cell::RefCell<Vec<&'static str>> = const { ::std::cell::RefCell::new(Vec::new()) };
+    }
+
+    fn record_call(fn_name: &'static str) -> Result<(), String> {
+        CALLED.with(|called| called.borrow_mut().push(fn_name));
+        Ok(())
+    }
+
+    easy_ffi!(recorded_ffi_fn =>
+        before = record_call,
+        last_error = set_last_error,
+    );
+
+    recorded_ffi_fn!(
+        fn recorded(n: i32) -> Result<i32, String> {
+            Ok(n)
+        }
+
+        #[easy_ffi(before = |_| Err("refused"))]
+        fn refused(n: i32) -> Result<i32, String> {
+            Ok(n)
+        }
+    );
+
+    #[test]
+    fn before_hook() {
+        use std::ffi::CStr;
+
+        assert_eq!(1, recorded(1));
+        assert_eq!(-1, refused(1));
+        assert_eq!(
+            "refused",
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        );
+        assert_eq!(
+            vec!["recorded"],
+            CALLED.with(|called| called.borrow().clone())
+        );
+    }
+
+    thread_local! {
+        static FINISHED: ::std::cell::RefCell<Vec<(&'static str, bool)>> = const { ::std::cell::RefCell::new(Vec::new()) };
+    }
+
+    fn record_result<T, E>(
+        fn_name: &'static str,
+        res: &Result<T, crate::FfiFailure<E>>,
+        _: ::std::time::Duration,
+    ) {
+        FINISHED.with(|finished| finished.borrow_mut().push((fn_name, res.is_ok())));
+    }
+
+    easy_ffi!(timed_ffi_fn =>
+        after = record_result,
+        |_err| { -1 }
+        |_panic_val| { -2 }
+    );
+
+    timed_ffi_fn!(
+        fn timed(n: i32) -> Result<i32, String> {
+            assert!(n != 0, "zero");
+            if n < 0 {
+                Err("negative".to_string())
+            } else {
+                Ok(n)
+            }
+        }
+    );
+
+    #[test]
+    fn after_hook() {
+        assert_eq!(1, timed(1));
+        assert_eq!(-1, timed(-1));
+        assert_eq!(-2, timed(0));
+        assert_eq!(
+            vec![("timed", true), ("timed", false), ("timed", false)],
+            FINISHED.with(|finished| finished.borrow().clone())
+        );
+    }
+
+    mod init_guard {
+        use super::set_last_error;
+
+        easy_ffi_init!(easy_ffi_test);
+
+        easy_ffi!(guarded_ffi_fn =>
+            before = check_initialized,
+            last_error = set_last_error,
+        );
+
+        guarded_ffi_fn!(
+            fn guarded(n: i32) -> Result<i32, String> {
+                Ok(n)
+            }
+        );
+    }
+
+    #[test]
+    fn init_guard() {
+        use self::init_guard::*;
+        use std::ffi::CStr;
+
+        assert!(!is_initialized());
+        assert_eq!(-1, guarded(1));
+        assert_eq!(
+            "`guarded` was called before initialization",
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        );
+        assert_eq!(0, init());
+        assert_eq!(1, guarded(1));
+    }
+
+    easy_ffi!(poisoning_ffi_fn =>
+        poison = true,
+        |err| {
+            assert_eq!("`poisoned` was called after a panic poisoned the library", err);
+            -1
+        }
+        |_panic_val| { -2 }
+    );
+
+    poisoning_ffi_fn!(
+        fn poisoned(n: i32) -> Result<i32, String> {
+            assert!(n >= 0);
+            Ok(n)
+        }
+    );
+
+    #[test]
+    fn poisoning() {
+        assert_eq!(1, poisoned(1));
+        assert_eq!(-2, poisoned(-1));
+        assert!(crate::is_poisoned());
+        assert_eq!(-1, poisoned(1));
+        crate::reset_poison();
+        assert_eq!(1, poisoned(1));
+    }
+
+    mod lifecycle {
+        easy_ffi_lifecycle!(
+            easy_ffi_test_lifecycle(i32) -> Vec<i32>;
+            init = |start: &i32| {
+                if *start < 0 {
+                    panic!("negative start");
+                }
+                if *start == 0 {
+                    return Err("zero start");
+                }
+                Ok(vec![*start])
+            },
+        );
+    }
+
+    #[test]
+    fn lifecycle() {
+        use self::lifecycle::*;
+
+        assert!(!is_initialized());
+        assert_eq!(-1, unsafe { init(::std::ptr::null()) });
+        assert_eq!(-1, unsafe { init(&0) });
+        assert_eq!(-1, unsafe { init(&-1) });
+        assert!(!is_initialized());
+        assert_eq!(0, unsafe { init(&1) });
+        assert_eq!(0, unsafe { init(&2) });
+        assert_eq!(Ok(vec![1]), with_state("test", |state| state.clone()));
+        shutdown();
+        shutdown();
+        assert_eq!(
+            Err(crate::NotInitialized { fn_name: "test" }),
+            with_state("test", |state| state.clone())
+        );
+        assert_eq!(0, unsafe { init(&3) });
+        assert_eq!(Ok(()), check_initialized("test"));
+    }
+
+    #[test]
+    fn str_args() {
+        use std::ffi::CStr;
+
+        let message = || {
+            unsafe { CStr::from_ptr(last_error_message()) }
+                .to_str()
+                .unwrap()
+        };
+
+        unsafe {
+            assert_eq!(6, str_len(b"hello\0".as_ptr() as *const _, 1));
+            assert_eq!(-1, str_len(::std::ptr::null(), 1));
+            assert_eq!("`s` is null", message());
+            assert_eq!(-1, str_len(b"\xff\0".as_ptr() as *const _, 1));
+            assert!(message().starts_with("`s` isn't valid UTF-8"));
+        }
+    }
+
+    #[test]
+    fn scratch_returns() {
+        use std::ffi::CStr;
+
+        let s = stringify_scratch(12);
+        assert_eq!(Ok("12"), unsafe { CStr::from_ptr(s) }.to_str());
+        let s = stringify_scratch(345);
+        assert_eq!(Ok("345"), unsafe { CStr::from_ptr(s) }.to_str());
+        assert!(stringify_scratch(0).is_null());
+        assert!(stringify_scratch(5).is_null());
+
+        // The stats JSON has a slot of its own.
+        let s = stringify_scratch(12);
+        let json = crate::store_stats_json();
+        assert_eq!(Ok("12"), unsafe { CStr::from_ptr(s) }.to_str());
+        stringify_scratch(345);
+        assert!(unsafe { CStr::from_ptr(json) }.to_bytes().starts_with(b"["));
+    }
+
+    #[test]
+    fn buffer_returns() {
+        use std::ffi::CStr;
+        use std::os::raw::c_char;
+
+        unsafe {
+            let mut buf = [1 as c_char; 4];
+            assert_eq!(3, stringify_into(123, buf.as_mut_ptr(), buf.len()));
+            assert_eq!(Ok("123"), CStr::from_ptr(buf.as_ptr()).to_str());
+            assert_eq!(5, stringify_into(12345, buf.as_mut_ptr(), buf.len()));
+            assert_eq!(Ok("123"), CStr::from_ptr(buf.as_ptr()).to_str());
+            assert_eq!(2, stringify_into(12, ::std::ptr::null_mut(), 0));
+            assert_eq!(4, stringify_into(0, buf.as_mut_ptr(), buf.len()));
+            assert_eq!(Ok("nul"), CStr::from_ptr(buf.as_ptr()).to_str());
+            assert_eq!(-1, stringify_into(5, buf.as_mut_ptr(), buf.len()));
+            assert_eq!(2, describe_into(12, buf.as_mut_ptr(), buf.len()));
+            assert_eq!(Ok("12"), CStr::from_ptr(buf.as_ptr()).to_str());
+            assert_eq!(-1, describe_into(-12, buf.as_mut_ptr(), buf.len()));
+        }
+    }
+
+    #[test]
+    fn result_free() {
+        unsafe {
+            boxed_result_result_free(boxed_result(1));
+            boxed_result_result_free(::std::ptr::null_mut());
+            easy_ffi_test_maybe_boxed_result_result_free(maybe_boxed_result(1));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn double_free() {
+        let boxed = new_boxed(1);
+        unsafe {
+            assert_eq!(0, release_boxed(boxed));
+            assert_eq!(-1, release_boxed(boxed));
+        }
+    }
+
+    #[test]
+    fn handles() {
+        unsafe {
+            let counter = counter_new();
+            assert_eq!(1, counter_incr(counter));
+            assert_eq!(2, counter_incr(counter));
+            counter_free(counter);
+
+            let counter = counter_starting_at(5);
+            assert_eq!(6, counter_incr(counter));
+            counter_free(counter);
+
+            let step = step_new(3);
+            assert_eq!(Ok(3), StepHandle::with(step, |step| step.count));
+            step_free(step);
+            assert!(step_new(-1).is_null());
+
+            assert_eq!(-1, counter_incr(::std::ptr::null_mut()));
+            let message = ::std::ffi::CStr::from_ptr(last_error_message());
+            assert_eq!(Ok("null handle"), message.to_str());
+        }
+    }
+
+    #[cfg(feature = "live_objects")]
+    easy_ffi_live_objects!(easy_ffi_test);
+
+    #[test]
+    #[cfg(feature = "live_objects")]
+    fn live_objects() {
+        let counter = counter_new();
+        assert!(dump_live_objects() >= 1);
+        unsafe { counter_free(counter) };
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn handle_magic() {
+        let mut not_a_counter = [0xdead_u64; 2];
+        let wild = not_a_counter.as_mut_ptr() as *mut CounterHandle;
+        unsafe {
+            assert_eq!(-1, counter_incr(wild));
+            let message = ::std::ffi::CStr::from_ptr(last_error_message());
+            assert_eq!(Ok("invalid handle"), message.to_str());
+            counter_free(wild);
+        }
+        assert_eq!([0xdead; 2], not_a_counter);
+
+        // Freeing a handle twice is caught without reading it, even without
+        // a tag.
+        let step = step_new(1);
+        unsafe {
+            step_free(step);
+            step_free(step);
+        }
+    }
+
+    #[test]
+    fn option_box_returns() {
+        let found = find_boxed(3);
+        assert_eq!(3, *unsafe { Box::from_raw(found) });
+        assert!(find_boxed(0).is_null());
+        assert!(find_boxed(-1).is_null());
+        assert!(find_boxed(5).is_null());
+    }
+
+    #[test]
+    fn tagged_result() {
+        assert_eq!(crate::FfiResult { code: 0, value: 1 }, tagged(1));
+        assert_eq!(crate::FfiResult { code: -1, value: 0 }, tagged(-1));
+        assert_eq!(Err(-2), tagged(5).into_result());
+    }
+
+    #[test]
+    fn sentinel_values() {
+        assert_eq!(1.0, sentinel_float(1.0));
+        assert!(sentinel_float(-1.0).is_nan());
+        assert_eq!(Handle(1), sentinel_handle(1));
+        assert_eq!(Handle(0), sentinel_handle(5));
+    }
+
+    #[test]
+    fn bool_returns() {
+        assert_eq!(1, is_even(2));
+        assert_eq!(0, is_even(3));
+        assert_eq!(-1, is_even(-2));
+        assert_eq!(-2, is_even(5));
+        assert_eq!(b'y' as ::std::os::raw::c_int, is_odd(3));
+        assert_eq!(b'n' as ::std::os::raw::c_int, is_odd(2));
+    }
+
+    #[test]
+    fn status_returns() {
+        assert_eq!(0, status(1));
+        assert_eq!(1, status(-1));
+        assert_eq!(2, status(5));
+        assert_eq!(100, other_status());
+    }
+
+    #[test]
+    fn result_paths_and_aliases() {
+        assert_eq!(4, bar(2));
+        assert_eq!(-1, bar(-2));
+        assert_eq!(6, baz(2));
+        assert_eq!(-1, baz(-2));
+    }
+
+    #[test]
+    fn lifetime_generics() {
+        assert_eq!(5, deref(Some(&5), None));
+        assert_eq!(-1, deref(None, None));
+    }
+
+    #[test]
+    fn where_clauses() {
+        assert_eq!(5, deref_where(Some(&5), None));
+        assert_eq!(-1, deref_where(None, None));
+        assert_eq!(5, deref_alias_where(Some(&5), None));
+        assert_eq!(-1, deref_alias_where(None, None));
     }
 }
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard};
+
+/// Where a live object came from.
+struct Origin {
+    fn_name: &'static str,
+    type_name: &'static str,
+}
+
+/// Pointers handed out by generated functions and not yet freed, by address.
+static LIVE: Mutex<Option<HashMap<usize, Origin>>> = Mutex::new(None);
+
+fn live() -> MutexGuard<'static, Option<HashMap<usize, Origin>>> {
+    // Nothing panics while the lock is held with the map half-updated.
+    LIVE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub(crate) fn record<T>(fn_name: &'static str, ptr: *mut T) {
+    let origin = Origin {
+        fn_name,
+        type_name: ::std::any::type_name::<T>(),
+    };
+    live()
+        .get_or_insert_with(HashMap::new)
+        .insert(ptr as usize, origin);
+}
+
+pub(crate) fn forget<T>(ptr: *mut T) {
+    if let Some(live) = live().as_mut() {
+        live.remove(&(ptr as usize));
+    }
+}
+
+/// Used by [`easy_ffi_live_objects!`](macro.easy_ffi_live_objects.html):
+/// prints the live objects to stderr, returning how many there are.
+#[doc(hidden)]
+pub fn dump_live_objects() -> usize {
+    let live = live();
+    let live = match live.as_ref() {
+        Some(live) => live,
+        None => return 0,
+    };
+    let mut objects: Vec<_> = live.iter().collect();
+    objects.sort_by_key(|&(&addr, _)| addr);
+    let mut stderr = io::stderr();
+    for (&addr, origin) in objects {
+        let _ = writeln!(
+            stderr,
+            "live object {:#x}: {} from {}",
+            addr, origin.type_name, origin.fn_name
+        );
+    }
+    live.len()
+}
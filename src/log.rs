@@ -0,0 +1,100 @@
+use std::fmt::Display;
+
+use crate::error_callback::{call_error_callback_with, report_error};
+use crate::failure::payload_str;
+use crate::panic_hook::take_panic_report;
+use crate::rate_limit::may_log;
+use crate::redact::Redacted;
+use crate::{FfiDefault, FfiFailure, LastError};
+
+/// Whether `log_limit` lets the failure being handled be logged, logging
+/// how many weren't first if some weren't.
+fn may_log_to(target: &str, fn_name: &str) -> bool {
+    may_log(
+        |suppressed| ::log::error!(target: target, "{}: suppressed {} messages", fn_name, suppressed),
+    )
+}
+
+/// Logs `failure` with `log::error!`, as `fn_name: err` or `fn_name: panic:
+/// msg`.
+fn log_failure<E: Display>(target: &str, fn_name: &str, failure: &FfiFailure<E>) {
+    if !may_log_to(target, fn_name) {
+        return;
+    }
+    match *failure {
+        FfiFailure::Error(ref err) => {
+            ::log::error!(target: target, "{}: {}", fn_name, Redacted(err))
+        }
+        FfiFailure::Panic(ref panic_val) => match take_panic_report() {
+            Some(report) => {
+                ::log::error!(target: target, "{}: panic: {}", fn_name, Redacted(&report))
+            }
+            None => match payload_str(&**panic_val) {
+                Some(msg) => ::log::error!(target: target, "{}: panic: {}", fn_name, Redacted(msg)),
+                None => ::log::error!(target: target, "{}: panic", fn_name),
+            },
+        },
+    }
+}
+
+/// The handler used for `handlers = "log"`: logs the failure and returns
+/// `FfiDefault::ffi_default()`.
+#[doc(hidden)]
+pub fn log_handler<T: FfiDefault, E: Display>(
+    target: &str,
+    fn_name: &str,
+    failure: FfiFailure<E>,
+) -> T {
+    match failure {
+        FfiFailure::Error(ref err) => {
+            log_failure(target, fn_name, &failure);
+            call_error_callback_with(|| LastError::from_error(-1, err));
+        }
+        FfiFailure::Panic(ref panic_val) => match take_panic_report() {
+            Some(report) => {
+                if may_log_to(target, fn_name) {
+                    ::log::error!(target: target, "{}: panic: {}", fn_name, Redacted(&report));
+                }
+                call_error_callback_with(|| LastError::from_error(-1, &report));
+            }
+            None => {
+                log_failure(target, fn_name, &failure);
+                call_error_callback_with(|| LastError::from_panic(-1, &**panic_val));
+            }
+        },
+    }
+    T::ffi_default()
+}
+
+/// The handler used for `handlers = "log"` with a `last_error` function:
+/// logs the failure, then records it with code `-1`, and returns
+/// `FfiDefault::ffi_default()`.
+#[doc(hidden)]
+pub fn log_last_error_handler<T: FfiDefault, E: Display>(
+    target: &str,
+    fn_name: &str,
+    set_last_error: fn(LastError),
+    failure: FfiFailure<E>,
+) -> T {
+    match failure {
+        FfiFailure::Error(ref err) => {
+            log_failure(target, fn_name, &failure);
+            report_error(fn_name, set_last_error, LastError::from_error(-1, err));
+        }
+        // The report is taken for the log, so the message comes from the
+        // payload either way.
+        FfiFailure::Panic(ref panic_val) => {
+            let last_error = LastError::from_panic(-1, &**panic_val);
+            if may_log_to(target, fn_name) {
+                ::log::error!(
+                    target: target,
+                    "{}: panic: {}",
+                    fn_name,
+                    last_error.message().to_string_lossy()
+                );
+            }
+            report_error(fn_name, set_last_error, last_error);
+        }
+    }
+    T::ffi_default()
+}
@@ -0,0 +1,208 @@
+use std::fmt::Write;
+use std::os::raw::c_int;
+use std::sync::OnceLock;
+
+use crate::FfiErrorCode;
+
+/// A function wrapped with `manifest = true`, as listed by
+/// [`exports`](fn.exports.html).
+///
+/// Types are as they're written in the generated function, e.g. a `&str`
+/// argument is a `*const ::std::os::raw::c_char`, since that's what C has
+/// to pass. They come from `stringify!`, so how they're spaced out can
+/// change between compiler versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FfiExport {
+    symbol: &'static str,
+    name: &'static str,
+    params: &'static [FfiParam],
+    ret: &'static str,
+    doc: &'static str,
+}
+
+impl FfiExport {
+    #[doc(hidden)]
+    pub const fn new(
+        symbol: &'static str,
+        name: &'static str,
+        params: &'static [FfiParam],
+        ret: &'static str,
+        doc: &'static str,
+    ) -> FfiExport {
+        FfiExport {
+            symbol,
+            name,
+            params,
+            ret,
+            doc,
+        }
+    }
+
+    /// The symbol it's exported as.
+    pub fn symbol(&self) -> &'static str {
+        self.symbol
+    }
+
+    /// The name of the Rust function.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// What C passes it, in order.
+    pub fn params(&self) -> &'static [FfiParam] {
+        self.params
+    }
+
+    /// The type it returns to C.
+    pub fn ret(&self) -> &'static str {
+        self.ret
+    }
+
+    /// Its doc comment, one line per line of it with the space after the
+    /// `///` left in, or `""` if it doesn't have one.
+    pub fn doc(&self) -> &'static str {
+        self.doc
+    }
+}
+
+/// One of the parameters of an [`FfiExport`](struct.FfiExport.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FfiParam {
+    name: &'static str,
+    ty: &'static str,
+}
+
+impl FfiParam {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str, ty: &'static str) -> FfiParam {
+        FfiParam { name, ty }
+    }
+
+    /// The parameter's name, which for a slice's length is `len`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Its type.
+    pub fn ty(&self) -> &'static str {
+        self.ty
+    }
+}
+
+inventory::collect!(FfiExport);
+
+/// Every function wrapped with `manifest = true` that's linked into the
+/// program, sorted by symbol.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// easy_ffi!(my_ffi_fn => manifest = true);
+///
+/// my_ffi_fn!(
+///     /// Adds two numbers.
+///     fn add(a: i32, b: i32) -> Result<i32, &'static str> {
+///         a.checked_add(b).ok_or("overflow")
+///     }
+/// );
+/// # fn main() {
+/// let add = easy_ffi::exports().iter().find(|export| export.symbol() == "add").unwrap();
+/// assert_eq!(" Adds two numbers.", add.doc());
+/// assert_eq!("b", add.params()[1].name());
+/// assert_eq!("i32", add.ret());
+/// # }
+/// ```
+pub fn exports() -> &'static [&'static FfiExport] {
+    static EXPORTS: OnceLock<Vec<&'static FfiExport>> = OnceLock::new();
+    EXPORTS.get_or_init(|| {
+        let mut exports: Vec<_> = inventory::iter::<FfiExport>().collect();
+        exports.sort_by_key(|export| export.symbol);
+        exports
+    })
+}
+
+/// [`exports`](fn.exports.html) as a JSON object, along with `error_codes`
+/// and their descriptions, which is what the function
+/// [`easy_ffi_api_json!`](macro.easy_ffi_api_json.html) exports returns:
+///
+/// ```text
+/// {
+///   "exports": [{
+///     "symbol": "add", "name": "add",
+///     "params": [{"name": "a", "type": "i32"}, {"name": "b", "type": "i32"}],
+///     "returns": "i32", "doc": " Adds two numbers."
+///   }],
+///   "error_codes": [{"code": -1, "description": "overflow"}]
+/// }
+/// ```
+///
+/// It's all on one line, without the spaces.
+pub fn api_json(error_codes: &[(c_int, &str)]) -> String {
+    let mut json = String::from(r#"{"exports":["#);
+    for (i, export) in exports().iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str(r#"{"symbol":"#);
+        push_json_str(&mut json, export.symbol);
+        json.push_str(r#","name":"#);
+        push_json_str(&mut json, export.name);
+        json.push_str(r#","params":["#);
+        for (i, param) in export.params.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str(r#"{"name":"#);
+            push_json_str(&mut json, param.name);
+            json.push_str(r#","type":"#);
+            push_json_str(&mut json, param.ty);
+            json.push('}');
+        }
+        json.push_str(r#"],"returns":"#);
+        push_json_str(&mut json, export.ret);
+        json.push_str(r#","doc":"#);
+        push_json_str(&mut json, export.doc);
+        json.push('}');
+    }
+    json.push_str(r#"],"error_codes":["#);
+    for (i, &(code, description)) in error_codes.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let _ = write!(json, r#"{{"code":{},"description":"#, code);
+        push_json_str(&mut json, description);
+        json.push('}');
+    }
+    json.push_str("]}");
+    json
+}
+
+/// Used by `easy_ffi_api_json!`: the codes in `codes` that `E` has
+/// descriptions for, with them.
+#[doc(hidden)]
+pub fn error_code_descriptions<E: FfiErrorCode>(
+    codes: impl IntoIterator<Item = c_int>,
+) -> Vec<(c_int, &'static str)> {
+    codes
+        .into_iter()
+        .filter_map(|code| Some((code, E::code_description(code)?.to_str().ok()?)))
+        .collect()
+}
+
+/// Appends `s` as a JSON string, quoted and escaped.
+fn push_json_str(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
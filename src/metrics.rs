@@ -0,0 +1,165 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::FfiFailure;
+
+/// Metrics about calls to wrapped functions, for sending on to Prometheus,
+/// StatsD and the like.
+///
+/// Once installed with [`set_metrics`](fn.set_metrics.html), every function
+/// wrapped by `easy_ffi!` reports each of its calls to it, under its own
+/// name. Every method does nothing by default, so implement the ones you
+/// need. Panics in them are ignored.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use easy_ffi::FfiMetrics;
+///
+/// struct Errors(AtomicU64);
+///
+/// impl FfiMetrics for Errors {
+///     fn on_error(&self, fn_name: &str) {
+///         assert_eq!("parse", fn_name);
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// static ERRORS: Errors = Errors(AtomicU64::new(0));
+///
+/// easy_ffi!(my_ffi_fn =>);
+///
+/// my_ffi_fn!(
+///     fn parse(i: i32) -> Result<i32, &'static str> {
+///         if i < 0 { Err("negative") } else { Ok(i) }
+///     }
+/// );
+///
+/// # fn main() {
+/// easy_ffi::set_metrics(&ERRORS);
+/// parse(1);
+/// parse(-1);
+/// assert_eq!(1, ERRORS.0.load(Ordering::Relaxed));
+/// # }
+/// ```
+pub trait FfiMetrics: Send + Sync {
+    /// The function was called.
+    fn on_call(&self, fn_name: &str) {
+        let _ = fn_name;
+    }
+
+    /// The function returned an error, including arguments that failed
+    /// their checks.
+    fn on_error(&self, fn_name: &str) {
+        let _ = fn_name;
+    }
+
+    /// The function panicked.
+    fn on_panic(&self, fn_name: &str) {
+        let _ = fn_name;
+    }
+
+    /// The function's body took `duration` to run, whether it failed or not.
+    fn on_duration(&self, fn_name: &str, duration: Duration) {
+        let _ = (fn_name, duration);
+    }
+}
+
+impl<M: FfiMetrics + ?Sized> FfiMetrics for &'static M {
+    fn on_call(&self, fn_name: &str) {
+        (**self).on_call(fn_name)
+    }
+
+    fn on_error(&self, fn_name: &str) {
+        (**self).on_error(fn_name)
+    }
+
+    fn on_panic(&self, fn_name: &str) {
+        (**self).on_panic(fn_name)
+    }
+
+    fn on_duration(&self, fn_name: &str, duration: Duration) {
+        (**self).on_duration(fn_name, duration)
+    }
+}
+
+/// The metrics from [`set_metrics`](fn.set_metrics.html).
+static METRICS: RwLock<Option<Box<dyn FfiMetrics>>> = RwLock::new(None);
+
+/// Whether there are any, so that calls don't take the lock otherwise.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Installs `metrics` to be told about every call to a wrapped function,
+/// replacing any installed before.
+///
+/// They must not install or clear metrics themselves, which would deadlock.
+pub fn set_metrics(metrics: impl FfiMetrics + 'static) {
+    // The metrics aren't called while the lock is held for writing.
+    let mut installed = METRICS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *installed = Some(Box::new(metrics));
+    INSTALLED.store(true, Ordering::Release);
+}
+
+/// Removes the metrics from [`set_metrics`](fn.set_metrics.html).
+pub fn clear_metrics() {
+    let mut installed = METRICS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    INSTALLED.store(false, Ordering::Release);
+    *installed = None;
+}
+
+/// Calls `f` with the installed metrics, if there are any.
+fn with_metrics(f: impl FnOnce(&dyn FfiMetrics)) {
+    if !INSTALLED.load(Ordering::Acquire) {
+        return;
+    }
+    // Panics in the metrics are caught, so they can't poison the lock.
+    let installed = METRICS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(ref metrics) = *installed {
+        if let Err(panic_val) = panic::catch_unwind(AssertUnwindSafe(|| f(&**metrics))) {
+            // Dropping the payload could panic again.
+            ::std::mem::forget(panic_val);
+        }
+    }
+}
+
+/// Used by every wrapped function when it's called: tells the metrics, and
+/// returns when the body started, if there are any.
+#[doc(hidden)]
+pub fn metrics_call(fn_name: &str) -> Option<Instant> {
+    if !INSTALLED.load(Ordering::Acquire) {
+        return None;
+    }
+    with_metrics(|metrics| metrics.on_call(fn_name));
+    Some(Instant::now())
+}
+
+/// Used by every wrapped function once the body has run: tells the metrics
+/// how it went, if there were any when it was called.
+#[doc(hidden)]
+pub fn metrics_result<T, E>(
+    fn_name: &str,
+    started: Option<Instant>,
+    res: &Result<T, FfiFailure<E>>,
+) {
+    let started = match started {
+        Some(started) => started,
+        None => return,
+    };
+    let duration = started.elapsed();
+    with_metrics(|metrics| {
+        metrics.on_duration(fn_name, duration);
+        match *res {
+            Ok(_) => {}
+            Err(FfiFailure::Error(_)) => metrics.on_error(fn_name),
+            Err(FfiFailure::Panic(_)) => metrics.on_panic(fn_name),
+        }
+    });
+}
@@ -0,0 +1,155 @@
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::{self, Location};
+use std::sync::Once;
+
+use crate::failure::payload_str;
+
+thread_local! {
+    /// The last panic on this thread since the hook was installed.
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+/// What the hook installed by
+/// [`easy_ffi_panic_hook!`](macro.easy_ffi_panic_hook.html) records about a
+/// panic: its message and location, and a backtrace from where it happened.
+///
+/// Its `Display` output is the message followed by the location, e.g.
+/// `too big at src/lib.rs:10:5`.
+#[derive(Debug)]
+pub struct PanicReport {
+    message: Option<String>,
+    location: Option<PanicLocation>,
+    backtrace: Backtrace,
+}
+
+/// Where in the Rust source a panic happened, from `std::panic::Location`.
+///
+/// Its `Display` output is `file:line:column`, e.g. `src/lib.rs:10:5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicLocation {
+    file: String,
+    line: u32,
+    column: u32,
+}
+
+impl PanicLocation {
+    /// The source file, as given to `rustc`.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// The line, counting from 1.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// The column, counting from 1.
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+}
+
+impl<'a> From<&Location<'a>> for PanicLocation {
+    fn from(location: &Location<'a>) -> PanicLocation {
+        PanicLocation {
+            file: location.file().to_string(),
+            line: location.line(),
+            column: location.column(),
+        }
+    }
+}
+
+impl fmt::Display for PanicLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl PanicReport {
+    /// The panic message, if it had one.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Where the panic happened. Functions marked `#[track_caller]`, like
+    /// `Option::unwrap`, report where they were called from instead.
+    pub fn location(&self) -> Option<&PanicLocation> {
+        self.location.as_ref()
+    }
+
+    /// The backtrace from where the panic happened.
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+}
+
+impl fmt::Display for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message().unwrap_or("panic"))?;
+        match self.location() {
+            Some(location) => write!(f, " at {}", location),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Used by [`easy_ffi_panic_hook!`](macro.easy_ffi_panic_hook.html).
+#[doc(hidden)]
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let report = PanicReport {
+                message: payload_str(info.payload()).map(str::to_string),
+                location: info.location().map(PanicLocation::from),
+                backtrace: Backtrace::force_capture(),
+            };
+            // Recording fails only while the thread is being torn down, or
+            // if this hook somehow panicked while recording.
+            let _ = LAST_PANIC.try_with(|last| {
+                if let Ok(mut last) = last.try_borrow_mut() {
+                    *last = Some(report);
+                }
+            });
+            previous(info);
+        }));
+    });
+}
+
+/// Called as a wrapped function starts, so that a report left from an
+/// earlier panic, e.g. one that other code caught, isn't taken for one of
+/// its own.
+#[doc(hidden)]
+pub fn clear_panic_report() {
+    drop(take_panic_report());
+}
+
+/// Puts back a report taken on the thread a panic happened on, for the
+/// thread it's resumed on, since `resume_unwind` doesn't call the hook.
+pub(crate) fn restore_panic_report(report: Option<PanicReport>) {
+    if let Some(report) = report {
+        let _ = LAST_PANIC.try_with(|last| {
+            if let Ok(mut last) = last.try_borrow_mut() {
+                *last = Some(report);
+            }
+        });
+    }
+}
+
+/// Takes the report of the last panic on this thread, as recorded by the
+/// hook from [`easy_ffi_panic_hook!`](macro.easy_ffi_panic_hook.html).
+///
+/// A panic handler can call this to find out more about the panic it was
+/// given, since the hook runs when the panic starts. This is `None` if the
+/// hook isn't installed, or the report has already been taken. Wrapped
+/// functions clear it when they're called, and with `timeout` or
+/// `stack_size`, bring it back from the thread the body panicked on.
+pub fn take_panic_report() -> Option<PanicReport> {
+    LAST_PANIC
+        .try_with(|last| last.try_borrow_mut().ok()?.take())
+        .ok()
+        .flatten()
+}
@@ -0,0 +1,65 @@
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+thread_local! {
+    /// The function a test from `easy_ffi_tests!` is waiting to panic in.
+    static INJECTED: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Called at the start of every wrapped function compiled for the crate's
+/// tests: panics if a test from `easy_ffi_tests!` is calling `fn_name`.
+#[doc(hidden)]
+pub fn inject_panic(fn_name: &'static str) {
+    if INJECTED.with(|injected| injected.get()) == Some(fn_name) {
+        // Only once, so that wrapped functions the handlers call aren't
+        // panicked in too.
+        INJECTED.with(|injected| injected.set(None));
+        panic!("panic injected into `{}` by easy_ffi_tests!", fn_name);
+    }
+}
+
+/// Used by `easy_ffi_tests!`: calls `f`, which calls `fn_name`, with a
+/// panic waiting for it, and returns what it returned and whether it
+/// panicked. Panics if it unwound instead of returning.
+#[doc(hidden)]
+pub fn with_injected_panic<T>(fn_name: &'static str, f: impl FnOnce() -> T) -> (T, bool) {
+    INJECTED.with(|injected| injected.set(Some(fn_name)));
+    let ret = panic::catch_unwind(AssertUnwindSafe(f));
+    let injected = INJECTED.with(|injected| injected.replace(None)).is_none();
+    match ret {
+        Ok(ret) => (ret, injected),
+        Err(_) => panic!("`{}` unwound into its caller", fn_name),
+    }
+}
+
+/// A function in `module` wrapped with `panic_tests = true`, or listed by an
+/// `easy_ffi_tests!` there.
+#[cfg(feature = "manifest")]
+#[doc(hidden)]
+pub struct PanicTest {
+    pub module: &'static str,
+    pub fn_name: &'static str,
+    pub listed: bool,
+}
+
+#[cfg(feature = "manifest")]
+inventory::collect!(PanicTest);
+
+/// Used by `easy_ffi_tests!`: the functions wrapped with `panic_tests =
+/// true` in the module the tests in `tests_module` are for that none of its
+/// `easy_ffi_tests!` list.
+#[cfg(feature = "manifest")]
+#[doc(hidden)]
+pub fn unlisted_panic_tests(tests_module: &str) -> Vec<&'static str> {
+    let module = tests_module
+        .rsplit_once("::")
+        .map_or("", |(module, _)| module);
+    let in_module = || inventory::iter::<PanicTest>().filter(move |test| test.module == module);
+    let mut unlisted: Vec<_> = in_module()
+        .filter(|test| !test.listed)
+        .map(|test| test.fn_name)
+        .filter(|&fn_name| !in_module().any(|test| test.listed && test.fn_name == fn_name))
+        .collect();
+    unlisted.sort_unstable();
+    unlisted
+}
@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::FfiErrorCode;
+
+/// Set when a function wrapped with `poison = true` panics.
+static POISONED: AtomicBool = AtomicBool::new(false);
+
+/// A function wrapped with `poison = true` was called after one of them
+/// panicked, and before [`reset_poison`](fn.reset_poison.html).
+///
+/// This is passed to the error handler instead of running the function, so
+/// with `poison = true`, wrapped functions need an error type implementing
+/// `From<Poisoned>`. It's implemented here for `io::Error` and `String`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Poisoned {
+    /// The function that was called.
+    pub fn_name: &'static str,
+}
+
+impl Poisoned {
+    /// The code from its `FfiErrorCode` implementation, out of the way of
+    /// derived codes, which count down from `-1`.
+    pub const CODE: c_int = -1000;
+}
+
+impl fmt::Display for Poisoned {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` was called after a panic poisoned the library",
+            self.fn_name
+        )
+    }
+}
+
+impl Error for Poisoned {}
+
+impl FfiErrorCode for Poisoned {
+    fn error_code(&self) -> c_int {
+        Poisoned::CODE
+    }
+}
+
+impl From<Poisoned> for io::Error {
+    fn from(err: Poisoned) -> io::Error {
+        io::Error::other(err)
+    }
+}
+
+impl From<Poisoned> for String {
+    fn from(err: Poisoned) -> String {
+        err.to_string()
+    }
+}
+
+/// Whether a function wrapped with `poison = true` has panicked since the
+/// last [`reset_poison`](fn.reset_poison.html).
+pub fn is_poisoned() -> bool {
+    POISONED.load(Ordering::Acquire)
+}
+
+/// Lets functions wrapped with `poison = true` run again after a panic.
+pub fn reset_poison() {
+    POISONED.store(false, Ordering::Release);
+}
+
+/// Used for `poison = true` when a function panics.
+#[doc(hidden)]
+pub fn poison() {
+    POISONED.store(true, Ordering::Release);
+}
+
+/// Used for `poison = true` before running a function.
+#[doc(hidden)]
+pub fn check_poisoned(fn_name: &'static str) -> Result<(), Poisoned> {
+    if is_poisoned() {
+        Err(Poisoned { fn_name })
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::cell::Cell;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What the built-in handlers on this thread may log.
+#[derive(Clone, Copy)]
+enum Permit {
+    /// Anything, since there's no `log_limit`.
+    Unlimited,
+    /// The current failure, after saying how many were suppressed.
+    Allowed(u64),
+    /// Nothing, since the function is over its `log_limit`.
+    Suppressed,
+}
+
+thread_local! {
+    static PERMIT: Cell<Permit> = const { Cell::new(Permit::Unlimited) };
+}
+
+#[derive(Debug)]
+struct Window {
+    start: Option<Instant>,
+    logged: u64,
+    suppressed: u64,
+}
+
+/// How many failures have been logged in the current second, for handlers
+/// of your own to limit their logging the way `log_limit = n` does for the
+/// built-in ones.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::LogLimit;
+///
+/// static LIMIT: LogLimit = LogLimit::new();
+///
+/// easy_ffi!(my_ffi_fn =>
+///     |err, fn_name| {
+///         let summary = |suppressed| eprintln!("{}: suppressed {} messages", fn_name, suppressed);
+///         if LIMIT.may_log(10, summary) {
+///             eprintln!("{}: {}", fn_name, err);
+///         }
+///         -1
+///     }
+///     |panic_val| { -2 }
+/// );
+/// # fn main() {
+/// #     let logged: Vec<_> = (0..3).map(|_| LIMIT.may_log(2, |_| ())).collect();
+/// #     assert_eq!(vec![true, true, false], logged);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LogLimit(Mutex<Window>);
+
+impl LogLimit {
+    /// A limit nothing's been logged under yet.
+    pub const fn new() -> LogLimit {
+        LogLimit(Mutex::new(Window {
+            start: None,
+            logged: 0,
+            suppressed: 0,
+        }))
+    }
+
+    /// Whether a failure may be logged, which it may unless `max` have been
+    /// logged in the last second already. If so, `summary` is first given
+    /// how many weren't logged since the last one that was, if any weren't.
+    pub fn may_log(&self, max: u64, summary: impl FnOnce(u64)) -> bool {
+        match self.admit(max) {
+            Permit::Allowed(suppressed) => {
+                if suppressed > 0 {
+                    summary(suppressed);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Used for `log_limit = n`: lets the built-in handlers log this failure
+    /// unless `max` have been logged in the last second already, until the
+    /// guard is dropped.
+    #[doc(hidden)]
+    pub fn enter(&self, max: u64) -> LogLimitGuard {
+        let permit = self.admit(max);
+        LogLimitGuard {
+            previous: PERMIT.try_with(|current| current.replace(permit)).ok(),
+        }
+    }
+
+    fn admit(&self, max: u64) -> Permit {
+        // Nothing can panic while the lock is held.
+        let mut window = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Instant::now();
+        match window.start {
+            Some(start) if now.duration_since(start) < Duration::from_secs(1) => {}
+            _ => {
+                window.start = Some(now);
+                window.logged = 0;
+            }
+        }
+        if window.logged < max {
+            window.logged += 1;
+            Permit::Allowed(std::mem::replace(&mut window.suppressed, 0))
+        } else {
+            window.suppressed += 1;
+            Permit::Suppressed
+        }
+    }
+}
+
+impl Default for LogLimit {
+    fn default() -> LogLimit {
+        LogLimit::new()
+    }
+}
+
+/// Puts back what the handlers may log when it's dropped.
+#[doc(hidden)]
+pub struct LogLimitGuard {
+    previous: Option<Permit>,
+}
+
+impl Drop for LogLimitGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous {
+            let _ = PERMIT.try_with(|current| current.set(previous));
+        }
+    }
+}
+
+/// Whether the built-in handlers may log the failure they're handling. If
+/// so, `summary` is first given how many of the function's failures weren't
+/// logged since the last one that was, if any weren't.
+pub(crate) fn may_log(summary: impl FnOnce(u64)) -> bool {
+    match PERMIT.try_with(Cell::get).unwrap_or(Permit::Unlimited) {
+        Permit::Unlimited => true,
+        Permit::Allowed(suppressed) => {
+            if suppressed > 0 {
+                summary(suppressed);
+            }
+            true
+        }
+        Permit::Suppressed => false,
+    }
+}
@@ -0,0 +1,71 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::sync::RwLock;
+
+type Hook = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// The hook from [`set_redaction_hook`](fn.set_redaction_hook.html).
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+/// Installs `hook` to rewrite every error and panic message before it's
+/// printed, logged, recorded as the last error or passed to an error
+/// callback, replacing any hook installed before.
+///
+/// This is for keeping user data that ends up in messages, e.g. a panic
+/// formatting a value it was given, away from logs and C callers. It's only
+/// as thorough as the hook, and only applies to messages from `easy_ffi`
+/// itself, not what handlers of your own print.
+///
+/// ```
+/// use easy_ffi::{set_redaction_hook, LastError};
+///
+/// set_redaction_hook(|message: &str| {
+///     message
+///         .split(' ')
+///         .map(|word| if word.contains('@') { "<email>" } else { word })
+///         .collect::<Vec<_>>()
+///         .join(" ")
+/// });
+/// let error = LastError::new(-1, "no account for jo@example.com");
+/// assert_eq!("no account for <email>", error.message().to_str().unwrap());
+/// ```
+pub fn set_redaction_hook(hook: impl Fn(&str) -> String + Send + Sync + 'static) {
+    // The hook isn't called while the lock is held for writing.
+    let mut installed = HOOK
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *installed = Some(Box::new(hook));
+}
+
+/// Removes the hook from [`set_redaction_hook`](fn.set_redaction_hook.html),
+/// so that messages are left as they are.
+pub fn clear_redaction_hook() {
+    let mut installed = HOOK
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *installed = None;
+}
+
+/// `message`, as rewritten by the redaction hook, if there is one.
+pub(crate) fn redact(message: &str) -> Cow<'_, str> {
+    // A hook that panicked can't have left itself half-installed.
+    let installed = HOOK.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match *installed {
+        Some(ref hook) => Cow::Owned(hook(message)),
+        None => Cow::Borrowed(message),
+    }
+}
+
+/// Displays a message as rewritten by the redaction hook, if there is one.
+#[doc(hidden)]
+pub struct Redacted<T>(pub T);
+
+impl<T: Display> Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let installed = HOOK.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match *installed {
+            Some(ref hook) => f.write_str(&hook(&self.0.to_string())),
+            None => self.0.fmt(f),
+        }
+    }
+}
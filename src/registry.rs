@@ -0,0 +1,146 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::HandleError;
+
+/// Values handed to C as integer handles rather than pointers.
+///
+/// A handle is a `u64` naming a slot in the registry along with the slot's
+/// generation, which changes whenever the slot is emptied. So unlike a
+/// pointer, a handle that's been removed, or was never valid at all, is
+/// caught when it's used, as a [`HandleError`](enum.HandleError.html) for
+/// the error handler, rather than being undefined behavior. `0` is never a
+/// valid handle, so C can use it as a null handle.
+///
+/// Registries are usually `static`s:
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use easy_ffi::{HandleError, HandleRegistry};
+///
+/// pub struct Widget {
+///     size: i32,
+/// }
+///
+/// static WIDGETS: HandleRegistry<Widget> = HandleRegistry::new();
+///
+/// easy_ffi!(my_ffi_fn =>);
+///
+/// my_ffi_fn!(
+///     fn widget_new(size: i32) -> Result<u64, HandleError> {
+///         Ok(WIDGETS.insert(Widget { size }))
+///     }
+///
+///     fn widget_size(widget: u64) -> Result<i32, HandleError> {
+///         Ok(WIDGETS.get(widget)?.size)
+///     }
+///
+///     #[easy_ffi(returns = "status")]
+///     fn widget_free(widget: u64) -> Result<(), HandleError> {
+///         WIDGETS.remove(widget).map(drop)
+///     }
+/// );
+/// # fn main() {
+/// #     let widget = widget_new(2);
+/// #     assert_eq!(2, widget_size(widget));
+/// #     assert_eq!(0, widget_free(widget));
+/// #     assert_eq!(-1, widget_size(widget));
+/// #     assert_eq!(-1, widget_free(widget));
+/// # }
+/// ```
+///
+/// Values are kept in `Arc`s, so that they can be used without holding the
+/// registry's lock, and outlive their handle if they're still in use when
+/// it's removed. Anything mutable inside them needs its own locking.
+pub struct HandleRegistry<T> {
+    slots: Mutex<Slots<T>>,
+}
+
+struct Slots<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<Arc<T>>,
+}
+
+impl<T> HandleRegistry<T> {
+    /// An empty registry.
+    pub const fn new() -> HandleRegistry<T> {
+        HandleRegistry {
+            slots: Mutex::new(Slots {
+                slots: Vec::new(),
+                free: Vec::new(),
+            }),
+        }
+    }
+
+    fn slots(&self) -> MutexGuard<'_, Slots<T>> {
+        // Nothing panics while the lock is held with the slots half-updated.
+        self.slots
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Adds `value`, returning its new handle.
+    pub fn insert(&self, value: T) -> u64 {
+        let mut slots = self.slots();
+        let index = match slots.free.pop() {
+            Some(index) => index,
+            None => {
+                let index = slots.slots.len() as u32;
+                assert!(index != u32::MAX, "too many handles");
+                slots.slots.push(Slot {
+                    generation: 1,
+                    value: None,
+                });
+                index
+            }
+        };
+        let slot = &mut slots.slots[index as usize];
+        slot.value = Some(Arc::new(value));
+        (u64::from(slot.generation) << 32) | u64::from(index)
+    }
+
+    /// The value for `handle`.
+    pub fn get(&self, handle: u64) -> Result<Arc<T>, HandleError> {
+        let slots = self.slots();
+        let index = Self::index(&slots, handle)?;
+        Ok(slots.slots[index].value.clone().expect("occupied slot"))
+    }
+
+    /// Removes `handle`, returning its value. The handle, and any copies of
+    /// it, are invalid from then on.
+    pub fn remove(&self, handle: u64) -> Result<Arc<T>, HandleError> {
+        let mut slots = self.slots();
+        let index = Self::index(&slots, handle)?;
+        let slot = &mut slots.slots[index];
+        // Generation 0 would make a handle of 0.
+        slot.generation = slot.generation.checked_add(1).unwrap_or(1);
+        let value = slot.value.take().expect("occupied slot");
+        slots.free.push(index as u32);
+        Ok(value)
+    }
+
+    /// The index of `handle`'s slot, if it's still occupied.
+    fn index(slots: &Slots<T>, handle: u64) -> Result<usize, HandleError> {
+        let generation = (handle >> 32) as u32;
+        let index = handle as u32 as usize;
+        if handle == 0 {
+            return Err(HandleError::Null);
+        }
+        match slots.slots.get(index) {
+            None => Err(HandleError::Invalid),
+            Some(_) if generation == 0 => Err(HandleError::Invalid),
+            Some(slot) if slot.generation == generation && slot.value.is_some() => Ok(index),
+            Some(_) => Err(HandleError::Stale),
+        }
+    }
+}
+
+impl<T> Default for HandleRegistry<T> {
+    fn default() -> HandleRegistry<T> {
+        HandleRegistry::new()
+    }
+}
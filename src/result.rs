@@ -0,0 +1,24 @@
+/// A `Result`-like return type from a wrapped function.
+///
+/// This is what lets wrapped functions return type aliases like
+/// `anyhow::Result<T>` or `io::Result<T>` rather than a literal
+/// `Result<T, E>`: the ffi return type is taken from `Ok`, and `Err` is
+/// what gets passed to the error handler.
+pub trait IntoResult {
+    /// The value returned across the ffi boundary on success.
+    type Ok;
+    /// The error passed to the error handler.
+    type Err;
+
+    /// Convert into a plain `Result`.
+    fn into_result(self) -> Result<Self::Ok, Self::Err>;
+}
+
+impl<T, E> IntoResult for Result<T, E> {
+    type Ok = T;
+    type Err = E;
+
+    fn into_result(self) -> Result<T, E> {
+        self
+    }
+}
@@ -0,0 +1,92 @@
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+/// Something that can run a future to completion on the calling thread, for
+/// wrapped `async fn`s.
+///
+/// Give one to `easy_ffi!` with `runtime = expr`, e.g. a handle to the
+/// runtime the rest of the library uses. Without one,
+/// [`CurrentThread`](struct.CurrentThread.html) is used.
+///
+/// ```
+/// # #[macro_use] extern crate easy_ffi;
+/// use std::future::Future;
+/// use easy_ffi::{CurrentThread, FfiRuntime};
+///
+/// struct Counting;
+///
+/// impl FfiRuntime for Counting {
+///     fn block_on<F: Future>(&self, future: F) -> F::Output {
+///         println!("blocking on a future");
+///         CurrentThread.block_on(future)
+///     }
+/// }
+///
+/// easy_ffi!(my_ffi_fn => runtime = Counting);
+///
+/// my_ffi_fn!(
+///     async fn answer() -> Result<i32, &'static str> {
+///         Ok(async { 42 }.await)
+///     }
+/// );
+/// # fn main() {
+/// #     assert_eq!(42, answer());
+/// # }
+/// ```
+pub trait FfiRuntime {
+    /// Runs `future` until it's ready, blocking the calling thread.
+    fn block_on<F: Future>(&self, future: F) -> F::Output;
+}
+
+impl<R: FfiRuntime + ?Sized> FfiRuntime for &R {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        (**self).block_on(future)
+    }
+}
+
+/// The runtime used for wrapped `async fn`s when there's no `runtime`
+/// option: polls the future on the calling thread, parking it until the
+/// future's woken.
+///
+/// It can't drive I/O or timers itself, so it only suits futures that are
+/// woken by something else, e.g. a channel from a thread of the library's
+/// own.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CurrentThread;
+
+/// Wakes a thread parked in `CurrentThread::block_on`.
+struct Unparker(Thread);
+
+impl Wake for Unparker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+impl FfiRuntime for CurrentThread {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(Unparker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(ret) = future.as_mut().poll(&mut cx) {
+                return ret;
+            }
+            thread::park();
+        }
+    }
+}
+
+/// Used for wrapped `async fn`s: runs the body on `runtime`, with the
+/// function's result type spelled out.
+#[doc(hidden)]
+pub fn block_on<R: FfiRuntime + ?Sized, T, F: Future<Output = T>>(runtime: &R, future: F) -> T {
+    runtime.block_on(future)
+}
@@ -0,0 +1,120 @@
+//! Checking a built library from its own tests, with the `selftest`
+//! feature.
+//!
+//! Export a self-test with
+//! [`easy_ffi_selftest!`](../macro.easy_ffi_selftest.html), make the crate
+//! a `cdylib`, and add `easy_ffi` with the `selftest` feature to its
+//! dev-dependencies. Then an integration test can open the library the way
+//! C would, with [`check_library`](fn.check_library.html), and check that
+//! the functions it's meant to export are there.
+
+use std::os::raw::c_int;
+use std::panic;
+#[cfg(feature = "selftest")]
+use std::{env, error::Error, fmt, path::Path, path::PathBuf};
+
+#[cfg(feature = "selftest")]
+use libloading::Library;
+
+/// Used by `easy_ffi_selftest!`: 0 if panics can be caught in this build,
+/// or 1 if they abort.
+#[doc(hidden)]
+pub fn self_test() -> c_int {
+    if cfg!(panic = "abort") {
+        return 1;
+    }
+    // `resume_unwind` skips the panic hook, so nothing is printed.
+    match panic::catch_unwind(|| panic::resume_unwind(Box::new(()))) {
+        Err(_) => 0,
+        Ok(()) => 1,
+    }
+}
+
+/// Why a built library failed [`check_library`](fn.check_library.html).
+#[cfg(feature = "selftest")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SelftestError {
+    /// The library couldn't be opened.
+    Open(PathBuf, libloading::Error),
+    /// It doesn't export the named symbol.
+    Missing(String, libloading::Error),
+    /// Its `_selftest` export returned this instead of 0.
+    Failed(c_int),
+}
+
+#[cfg(feature = "selftest")]
+impl fmt::Display for SelftestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SelftestError::Open(ref path, ref err) => {
+                write!(f, "couldn't open {}: {}", path.display(), err)
+            }
+            SelftestError::Missing(ref symbol, _) => write!(f, "`{}` isn't exported", symbol),
+            SelftestError::Failed(1) => write!(f, "the library was built with `panic = \"abort\"`"),
+            SelftestError::Failed(code) => write!(f, "the self-test failed with {}", code),
+        }
+    }
+}
+
+#[cfg(feature = "selftest")]
+impl Error for SelftestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SelftestError::Open(_, ref err) | SelftestError::Missing(_, ref err) => Some(err),
+            SelftestError::Failed(_) => None,
+        }
+    }
+}
+
+/// Where cargo builds the library `name` for the tests that are running,
+/// which is next to the directory the test executables are in.
+///
+/// Cargo only builds it for integration tests, in `tests/`.
+#[cfg(feature = "selftest")]
+pub fn built_library(name: &str) -> PathBuf {
+    let mut dir = env::current_exe().expect("the test executable has no path");
+    dir.pop();
+    if dir.ends_with("deps") {
+        dir.pop();
+    }
+    dir.join(libloading::library_filename(name))
+}
+
+/// Opens the library at `path`, checks that it exports each of `symbols`,
+/// and runs its `{prefix}_selftest` export.
+///
+/// # Safety
+///
+/// The library's initializers are run, so it has to be one that's safe to
+/// load, and `{prefix}_selftest` has to come from
+/// [`easy_ffi_selftest!`](../macro.easy_ffi_selftest.html).
+///
+/// ```no_run
+/// use easy_ffi::selftest;
+///
+/// let path = selftest::built_library("mylib");
+/// unsafe { selftest::check_library(&path, "mylib", &["mylib_add", "mylib_parse"]) }.unwrap();
+/// ```
+#[cfg(feature = "selftest")]
+pub unsafe fn check_library(
+    path: impl AsRef<Path>,
+    prefix: &str,
+    symbols: &[&str],
+) -> Result<(), SelftestError> {
+    let path = path.as_ref();
+    let library = Library::new(path).map_err(|err| SelftestError::Open(path.to_owned(), err))?;
+    for symbol in symbols {
+        library
+            .get::<unsafe extern "C" fn()>(symbol.as_bytes())
+            .map_err(|err| SelftestError::Missing(symbol.to_string(), err))?;
+    }
+    let name = format!("{}_selftest", prefix);
+    let self_test = library
+        .get::<extern "C" fn() -> c_int>(name.as_bytes())
+        .map_err(|err| SelftestError::Missing(name.clone(), err))?;
+    match self_test() {
+        0 => Ok(()),
+        code => Err(SelftestError::Failed(code)),
+    }
+}
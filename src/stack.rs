@@ -0,0 +1,49 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+use crate::panic_hook::{restore_panic_report, take_panic_report};
+
+/// Lets the body of a function wrapped with `stack_size` take its arguments
+/// to the thread it's run on, which is joined before the function returns.
+struct AssertSend<F>(F);
+
+// Only made by `run_on_stack`, whose caller vouches for what it holds.
+unsafe impl<F> Send for AssertSend<F> {}
+
+/// Used for `stack_size = bytes`: runs `body` on a thread of its own with a
+/// stack of `stack_size` bytes, and waits for it. Panics in it are resumed
+/// on this thread.
+///
+/// # Safety
+///
+/// Whatever `body` holds on to has to be usable from the other thread while
+/// this one waits for it, and so does its result.
+#[doc(hidden)]
+pub unsafe fn run_on_stack<T>(fn_name: &str, stack_size: usize, body: impl FnOnce() -> T) -> T {
+    let body = AssertSend(body);
+    let ret = thread::scope(|scope| {
+        let spawned = thread::Builder::new()
+            .name(fn_name.to_owned())
+            .stack_size(stack_size)
+            .spawn_scoped(scope, move || {
+                let body = body;
+                // The panic's report is on this thread, but it's handled on
+                // the caller's.
+                panic::catch_unwind(AssertUnwindSafe(body.0))
+                    .map(AssertSend)
+                    .map_err(|panic_val| (panic_val, take_panic_report()))
+            });
+        match spawned {
+            Ok(thread) => thread.join(),
+            Err(err) => panic!("couldn't spawn a thread to run `{}` on: {}", fn_name, err),
+        }
+    });
+    match ret {
+        Ok(Ok(ret)) => ret.0,
+        Ok(Err((panic_val, report))) => {
+            restore_panic_report(report);
+            panic::resume_unwind(panic_val)
+        }
+        Err(panic_val) => panic::resume_unwind(panic_val),
+    }
+}
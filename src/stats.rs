@@ -0,0 +1,140 @@
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt::Write;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::{c_string_lossy, FfiFailure};
+
+/// Call counts for a function wrapped with `stats = true`.
+///
+/// Each such function has one of these, which its calls update with
+/// atomics, so counting is cheap enough to leave on in production. It's
+/// listed by [`fn_stats`](fn.fn_stats.html) from its first call on.
+#[derive(Debug)]
+pub struct FnStats {
+    name: &'static str,
+    calls: AtomicU64,
+    errors: AtomicU64,
+    panics: AtomicU64,
+    registered: AtomicBool,
+}
+
+/// Every `FnStats` that's been called, in the order they were first called.
+static REGISTERED: Mutex<Vec<&'static FnStats>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// The last JSON returned by `easy_ffi_stats!`'s export on this thread,
+    /// kept apart from `returns = "scratch"` strings so neither replaces the
+    /// other.
+    static JSON: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+impl FnStats {
+    /// Used for `stats = true`: no calls yet.
+    #[doc(hidden)]
+    pub const fn new(name: &'static str) -> FnStats {
+        FnStats {
+            name,
+            calls: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            panics: AtomicU64::new(0),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    /// The name of the function.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// How many times it's been called.
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// How many of those calls returned an error. This includes arguments
+    /// that failed their checks.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// How many of those calls panicked.
+    pub fn panics(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    /// Used for `stats = true` when the function is called.
+    #[doc(hidden)]
+    pub fn record_call(&'static self) {
+        if !self.registered.swap(true, Ordering::AcqRel) {
+            // Nothing can panic while the lock is held.
+            REGISTERED
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push(self);
+        }
+        self.calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Used for `stats = true` once the body has run.
+    #[doc(hidden)]
+    pub fn record_result<T, E>(&self, res: &Result<T, FfiFailure<E>>) {
+        match *res {
+            Ok(_) => {}
+            Err(FfiFailure::Error(_)) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(FfiFailure::Panic(_)) => {
+                self.panics.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// The counts for every function wrapped with `stats = true` that's been
+/// called, in the order they were first called.
+pub fn fn_stats() -> Vec<&'static FnStats> {
+    REGISTERED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// [`fn_stats`](fn.fn_stats.html) as a JSON array of objects, e.g.
+/// `[{"name":"parse","calls":3,"errors":1,"panics":0}]`, which is what the
+/// function [`easy_ffi_stats!`](macro.easy_ffi_stats.html) exports returns.
+pub fn fn_stats_json() -> String {
+    let mut json = String::from("[");
+    for (i, stats) in fn_stats().into_iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        // Function names are identifiers, with nothing to escape.
+        let _ = write!(
+            json,
+            r#"{{"name":"{}","calls":{},"errors":{},"panics":{}}}"#,
+            stats.name(),
+            stats.calls(),
+            stats.errors(),
+            stats.panics()
+        );
+    }
+    json.push(']');
+    json
+}
+
+/// Used by `easy_ffi_stats!`: [`fn_stats_json`](fn.fn_stats_json.html),
+/// kept until the next call on this thread. Null if the thread is shutting
+/// down.
+#[doc(hidden)]
+pub fn store_stats_json() -> *const c_char {
+    let json = c_string_lossy(&fn_stats_json());
+    JSON.try_with(|stored| match stored.try_borrow_mut() {
+        Ok(mut stored) => stored.insert(json).as_ptr(),
+        Err(_) => ptr::null(),
+    })
+    .unwrap_or(ptr::null())
+}
@@ -0,0 +1,66 @@
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+thread_local! {
+    /// The last string returned with `returns = "scratch"` on this thread.
+    static SCRATCH: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Converts `s` to a `CString`, replacing any NULs in it with U+FFFD, so
+/// that unlike `CString::new`, it can't fail.
+///
+/// [`LastError`](struct.LastError.html) uses this for its message, so that
+/// recording an error can't fail on what the error says, and handlers can
+/// use it for anything else they hand to C.
+///
+/// ```
+/// use easy_ffi::c_string_lossy;
+///
+/// assert_eq!("bad \u{FFFD} byte", c_string_lossy("bad \0 byte").to_str().unwrap());
+/// assert_eq!("fine", c_string_lossy("fine").to_str().unwrap());
+/// ```
+pub fn c_string_lossy(s: &str) -> CString {
+    let s = if s.contains('\0') {
+        s.replace('\0', "\u{FFFD}")
+    } else {
+        s.to_owned()
+    };
+    CString::new(s).expect("NULs were replaced")
+}
+
+/// Used for `returns = "buffer"`: copies as much of `s` as fits into the
+/// `len` bytes at `buf`, NUL-terminated, and returns the length of all of
+/// `s`, not counting the NUL. Nothing is written if `buf` is null or `len` is
+/// 0. NULs in `s` are copied like any other byte.
+///
+/// # Safety
+///
+/// `buf` must be null or valid for writing `len` bytes.
+#[doc(hidden)]
+pub unsafe fn fill_buffer(s: &str, buf: *mut c_char, len: usize) -> c_int {
+    let bytes = s.as_bytes();
+    if !buf.is_null() && len > 0 {
+        let copied = bytes.len().min(len - 1);
+        ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, buf, copied);
+        *buf.add(copied) = 0;
+    }
+    if bytes.len() > c_int::MAX as usize {
+        c_int::MAX
+    } else {
+        bytes.len() as c_int
+    }
+}
+
+/// Used for `returns = "scratch"`: keeps `s` until the next call on this
+/// thread, and returns a pointer to it. Null if the thread is shutting down.
+#[doc(hidden)]
+pub fn store_scratch(s: CString) -> *const c_char {
+    SCRATCH
+        .try_with(|scratch| match scratch.try_borrow_mut() {
+            Ok(mut scratch) => scratch.insert(s).as_ptr(),
+            Err(_) => ptr::null(),
+        })
+        .unwrap_or(ptr::null())
+}
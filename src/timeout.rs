@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::panic_hook::{restore_panic_report, take_panic_report};
+use crate::FfiErrorCode;
+
+/// A function wrapped with `timeout = duration` didn't finish in time.
+///
+/// This is passed to the error handler in place of the function's result,
+/// so with `timeout`, wrapped functions need an error type implementing
+/// `From<TimedOut>`. It's implemented here for `io::Error` and `String`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedOut {
+    /// The function that was called.
+    pub fn_name: &'static str,
+    /// How long it was given.
+    pub timeout: Duration,
+}
+
+impl TimedOut {
+    /// The code from its `FfiErrorCode` implementation, next to
+    /// [`Poisoned::CODE`](struct.Poisoned.html#associatedconstant.CODE).
+    pub const CODE: c_int = -1001;
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}` timed out after {:?}", self.fn_name, self.timeout)
+    }
+}
+
+impl Error for TimedOut {}
+
+impl FfiErrorCode for TimedOut {
+    fn error_code(&self) -> c_int {
+        TimedOut::CODE
+    }
+}
+
+impl From<TimedOut> for io::Error {
+    fn from(err: TimedOut) -> io::Error {
+        io::Error::new(io::ErrorKind::TimedOut, err)
+    }
+}
+
+impl From<TimedOut> for String {
+    fn from(err: TimedOut) -> String {
+        err.to_string()
+    }
+}
+
+/// Used for `timeout = duration`: runs `body` on a thread of its own, and
+/// gives up on it after `timeout`, leaving it to finish in the background.
+/// Panics in it are resumed on this thread.
+#[doc(hidden)]
+pub fn run_with_timeout<T: Send + 'static>(
+    fn_name: &'static str,
+    timeout: Duration,
+    body: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, TimedOut> {
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let spawned = thread::Builder::new()
+        .name(fn_name.to_owned())
+        .spawn(move || {
+            // The panic's report is on this thread, but it's handled on the
+            // caller's.
+            let ret = panic::catch_unwind(AssertUnwindSafe(body))
+                .map_err(|panic_val| (panic_val, take_panic_report()));
+            // Nobody's waiting for it any more if it timed out.
+            let _ = sender.send(ret);
+        });
+    if let Err(err) = spawned {
+        panic!("couldn't spawn a thread to run `{}` on: {}", fn_name, err);
+    }
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(ret)) => Ok(ret),
+        Ok(Err((panic_val, report))) => {
+            restore_panic_report(report);
+            panic::resume_unwind(panic_val)
+        }
+        Err(_) => Err(TimedOut { fn_name, timeout }),
+    }
+}
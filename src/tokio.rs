@@ -0,0 +1,108 @@
+use std::future::Future;
+use std::io::{self, Write};
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::RwLock;
+
+use ::tokio::runtime::{Builder, Handle, Runtime};
+
+use crate::failure::payload_str;
+use crate::redact::Redacted;
+use crate::{FfiRuntime, NotInitialized};
+
+/// The runtime started by the `runtime_init` export from
+/// [`easy_ffi_runtime!`](macro.easy_ffi_runtime.html).
+static RUNTIME: RwLock<Option<Runtime>> = RwLock::new(None);
+
+/// A handle to the runtime from
+/// [`easy_ffi_runtime!`](macro.easy_ffi_runtime.html), which is what wrapped
+/// `async fn`s with `runtime = tokio` are run on.
+#[derive(Debug, Clone)]
+pub struct TokioRuntime(Handle);
+
+impl TokioRuntime {
+    /// The tokio handle, for spawning tasks of your own.
+    pub fn handle(&self) -> &Handle {
+        &self.0
+    }
+}
+
+impl FfiRuntime for TokioRuntime {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.0.block_on(future)
+    }
+}
+
+/// The runtime from [`easy_ffi_runtime!`](macro.easy_ffi_runtime.html), or
+/// a [`NotInitialized`](struct.NotInitialized.html) error blaming `fn_name`
+/// if it hasn't been started, or has been shut down.
+pub fn tokio_runtime(fn_name: &'static str) -> Result<TokioRuntime, NotInitialized> {
+    // Nothing can panic while the lock is held.
+    let runtime = RUNTIME
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match *runtime {
+        Some(ref runtime) => Ok(TokioRuntime(runtime.handle().clone())),
+        None => Err(NotInitialized { fn_name }),
+    }
+}
+
+/// Used by the `runtime_init` export from
+/// [`easy_ffi_runtime!`](macro.easy_ffi_runtime.html): starts a
+/// multi-threaded runtime, unless there's already one.
+///
+/// Returns 0 if there's a runtime afterwards, or -1 if it couldn't be
+/// started, which is printed to stderr.
+#[doc(hidden)]
+pub fn tokio_runtime_init(fn_name: &str) -> c_int {
+    let mut runtime = RUNTIME
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if runtime.is_some() {
+        return 0;
+    }
+    let built = panic::catch_unwind(|| Builder::new_multi_thread().enable_all().build());
+    let mut stderr = io::stderr();
+    match built {
+        Ok(Ok(built)) => {
+            *runtime = Some(built);
+            0
+        }
+        Ok(Err(err)) => {
+            let _ = writeln!(stderr, "{}: {}", fn_name, Redacted(err));
+            -1
+        }
+        Err(panic_val) => {
+            let _ = match payload_str(&*panic_val) {
+                Some(msg) => writeln!(stderr, "{}: panic: {}", fn_name, Redacted(msg)),
+                None => writeln!(stderr, "{}: panic", fn_name),
+            };
+            // Dropping the payload could panic again.
+            ::std::mem::forget(panic_val);
+            -1
+        }
+    }
+}
+
+/// Used by the `runtime_shutdown` export from
+/// [`easy_ffi_runtime!`](macro.easy_ffi_runtime.html): shuts the runtime
+/// down, without waiting for its tasks, unless there isn't one.
+#[doc(hidden)]
+pub fn tokio_runtime_shutdown(fn_name: &str) {
+    let runtime = RUNTIME
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .take();
+    if let Some(runtime) = runtime {
+        let shutdown = panic::catch_unwind(AssertUnwindSafe(|| runtime.shutdown_background()));
+        if let Err(panic_val) = shutdown {
+            let mut stderr = io::stderr();
+            let _ = match payload_str(&*panic_val) {
+                Some(msg) => writeln!(stderr, "{}: panic: {}", fn_name, Redacted(msg)),
+                None => writeln!(stderr, "{}: panic", fn_name),
+            };
+            // Dropping the payload could panic again.
+            ::std::mem::forget(panic_val);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt::Debug;
+
+/// Used for `tracing = true` to summarize arguments and errors, with their
+/// `Debug` output if they have one and `_` otherwise. Since macros can't
+/// tell whether a type is `Debug`, this picks between `SummarizeDebug` and
+/// `SummarizeAny` by autoref: `(&Summarize(&x)).summarize()` finds the
+/// former first, but only if it applies.
+#[doc(hidden)]
+pub struct Summarize<'a, T: ?Sized>(pub &'a T);
+
+#[doc(hidden)]
+pub trait SummarizeDebug {
+    fn summarize(&self) -> String;
+}
+
+impl<T: Debug + ?Sized> SummarizeDebug for Summarize<'_, T> {
+    fn summarize(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+#[doc(hidden)]
+pub trait SummarizeAny {
+    fn summarize(&self) -> String;
+}
+
+impl<T: ?Sized> SummarizeAny for &Summarize<'_, T> {
+    fn summarize(&self) -> String {
+        "_".to_string()
+    }
+}
+
+/// Used for `tracing = true`: adds `name=value` to the argument summary.
+#[doc(hidden)]
+pub fn push_arg(summary: &mut String, name: &str, value: String) {
+    if !summary.is_empty() {
+        summary.push_str(", ");
+    }
+    summary.push_str(name);
+    summary.push('=');
+    summary.push_str(&value);
+}
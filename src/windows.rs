@@ -0,0 +1,99 @@
+//! Reporting errors through `SetLastError`, for Windows callers.
+//!
+//! Implement [`AsWin32Error`](trait.AsWin32Error.html) for your error type,
+//! then set the thread's last error from the error handler before returning
+//! the failure value, so callers can use `GetLastError()` as usual:
+//!
+//! ```
+//! # #[macro_use] extern crate easy_ffi;
+//! use easy_ffi::windows::{self, AsWin32Error};
+//!
+//! #[derive(Debug)]
+//! enum MyError {
+//!     NotFound,
+//! }
+//!
+//! impl AsWin32Error for MyError {
+//!     fn as_win32_error(&self) -> u32 {
+//!         match *self {
+//!             MyError::NotFound => 2, // ERROR_FILE_NOT_FOUND
+//!         }
+//!     }
+//! }
+//!
+//! easy_ffi!(my_ffi_fn =>
+//!     abi = "system",
+//!     |err| {
+//!         windows::set_last_error_from(&err);
+//!         0
+//!     }
+//!     |panic_val| {
+//!         windows::set_last_error(windows::ERROR_INTERNAL_ERROR);
+//!         0
+//!     }
+//! );
+//!
+//! my_ffi_fn!(
+//!     fn open_thing(id: i32) -> Result<i32, MyError> {
+//!         Err(MyError::NotFound)
+//!     }
+//! );
+//! # fn main() {
+//! #     assert_eq!(open_thing(1), 0);
+//! #     assert_eq!(windows::get_last_error(), 2);
+//! # }
+//! ```
+//!
+//! This is only available on Windows.
+
+use std::io;
+
+/// `ERROR_INTERNAL_ERROR`, for errors without a better code.
+pub const ERROR_INTERNAL_ERROR: u32 = 1359;
+
+extern "system" {
+    fn SetLastError(code: u32);
+    fn GetLastError() -> u32;
+}
+
+/// An error that can be reported as a Win32 error code (a `DWORD`).
+pub trait AsWin32Error {
+    /// The error code for this error.
+    fn as_win32_error(&self) -> u32;
+}
+
+/// Uses the OS error code when there is one, and `ERROR_INTERNAL_ERROR`
+/// otherwise.
+impl AsWin32Error for io::Error {
+    fn as_win32_error(&self) -> u32 {
+        self.raw_os_error()
+            .map_or(ERROR_INTERNAL_ERROR, |code| code as u32)
+    }
+}
+
+impl<E: AsWin32Error + ?Sized> AsWin32Error for &E {
+    fn as_win32_error(&self) -> u32 {
+        (**self).as_win32_error()
+    }
+}
+
+impl<E: AsWin32Error + ?Sized> AsWin32Error for Box<E> {
+    fn as_win32_error(&self) -> u32 {
+        (**self).as_win32_error()
+    }
+}
+
+/// Set this thread's last error, as with `SetLastError`.
+pub fn set_last_error(code: u32) {
+    unsafe { SetLastError(code) }
+}
+
+/// Set this thread's last error from an error.
+pub fn set_last_error_from<E: AsWin32Error + ?Sized>(err: &E) {
+    set_last_error(err.as_win32_error());
+}
+
+/// This thread's last error, as with `GetLastError`.
+pub fn get_last_error() -> u32 {
+    unsafe { GetLastError() }
+}